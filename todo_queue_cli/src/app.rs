@@ -1,34 +1,105 @@
-use std::collections::HashMap;
+use std::cell::OnceCell;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
 use std::path::{Path, PathBuf};
 use std::fs::{self, File};
+use std::io::{self, Read, Write};
 use app_dirs::{self, AppDataType, AppInfo};
+use clap::ArgMatches;
 use serde_json;
+use failure::Fail;
+use crypt::{Encryption, Secret};
 use error::*;
-use list::NativeList;
+use file::{self, Lock};
+use git::{GitConfig, Repo, Synced};
+use history::History;
+use list::{NativeList, Shelf, SHELVES};
+use migrate::{self, Schema};
+use shell;
+use editor;
+use board;
+use storage::{Backend, BACKEND_NAMES};
+use format::{self, ExportOptions, Format, ImportOptions, FORMAT_NAMES, GROUPING_NAMES};
 use todo_queue_lib::query::{Filter, Query};
-use todo_queue_lib::list::{Item, ItemDesc, ItemId, List};
+use todo_queue_lib::range::Range;
+use todo_queue_lib::time::Timestamp;
+use todo_queue_lib::list::{Item, ItemDesc, ItemId, List, MemoryList, Status};
+use todo_queue_lib::csv::Column;
+use todo_queue_lib::history::Changeset;
+use todo_queue_lib::edit;
+use todo_queue_lib::merge;
 use todo_queue_lib::script;
 
-const APP_INFO: AppInfo = AppInfo {
+pub const APP_INFO: AppInfo = AppInfo {
     name: "todo_queue",
     author: "R Miller",
 };
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AppConfig {
+    #[serde(default)]
+    version: u64,
     config_path: PathBuf,
-    list_paths: HashMap<String, PathBuf>,
+    list_paths: HashMap<String, ListConfig>,
     default_list: Option<String>,
 }
 
+/// Where an attached list is kept, and how.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListConfig {
+    path: PathBuf,
+    backend: Backend,
+    /// Set when the list's directory is kept as a git repository, with a
+    /// commit for every change.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    git: Option<GitConfig>,
+    /// Set when the list is kept encrypted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    encryption: Option<Encryption>,
+    /// How many days after being completed items are moved to the list's
+    /// archive, if they are at all.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    archive_after: Option<u32>,
+}
+
+impl ListConfig {
+    /// The secret the list `name` is encrypted with, if it is.
+    fn secret(&self, name: &str) -> Result<Option<Secret>> {
+        match self.encryption {
+            Some(ref encryption) => Ok(Some(encryption.secret(name)?)),
+            None => Ok(None),
+        }
+    }
+}
+
 pub struct App {
-    lists: HashMap<String, NativeList>,
+    /// Every attached list, each loaded the first time it's used.
+    lists: HashMap<String, OnceCell<NativeList>>,
+    /// The archive and trash of every attached list, loaded the same way.
+    shelves: HashMap<(String, Shelf), OnceCell<NativeList>>,
+    /// The secret of every attached list which is encrypted, so that it's
+    /// only asked for once.
+    secrets: HashMap<String, OnceCell<Option<Secret>>>,
     config: AppConfig,
+    /// Whether lists were attached, detached or moved since launching.
+    config_changed: bool,
+    history: History,
+    /// The command being run, as it's recorded in the history.
+    command: String,
+    /// Set while changes are kept in memory until `flush`, instead of being
+    /// written and committed after every command.
+    holding: bool,
+    /// The commit messages of the changes held for each list kept in git.
+    pending: BTreeMap<String, Vec<String>>,
+    /// Held from before the configuration is loaded until the app is
+    /// dropped, so that nothing else saves over our changes in between.
+    _lock: Lock,
 }
 
 impl AppConfig {
     fn default_with_path(config_path: PathBuf) -> Self {
         Self {
+            version: Schema::Config.version(),
             config_path,
             list_paths: HashMap::default(),
             default_list: None,
@@ -41,8 +112,19 @@ impl AppConfig {
             app.save_pretty().context(ErrorKind::SaveConfig)?;
             Ok(app)
         } else {
-            let config_file = File::open(config_path).context(ErrorKind::LoadConfig)?;
-            Ok(serde_json::from_reader(config_file).context(ErrorKind::LoadConfig)?)
+            let config_file = File::open(&config_path).context(ErrorKind::LoadConfig)?;
+            let mut value: serde_json::Value =
+                serde_json::from_reader(config_file).context(ErrorKind::LoadConfig)?;
+            let upgraded = migrate::upgrade(Schema::Config, &config_path, &mut value)
+                .context(ErrorKind::LoadConfig)?;
+            let config: AppConfig =
+                serde_json::from_value(value).context(ErrorKind::LoadConfig)?;
+            if let Some(version) = upgraded {
+                let backup = migrate::backup(&config_path, version).context(ErrorKind::Migrate)?;
+                config.save_pretty().context(ErrorKind::Migrate)?;
+                migrate::report(&config_path, version, config.version, &backup);
+            }
+            Ok(config)
         }
     }
 
@@ -51,32 +133,58 @@ impl AppConfig {
             let config_dir = self.config_path.parent().unwrap();
             fs::create_dir_all(config_dir).context(ErrorKind::SaveConfig)?;
         }
-        let config_file = File::create(&self.config_path).context(ErrorKind::SaveConfig)?;
-        serde_json::to_writer_pretty(config_file, self).context(ErrorKind::LoadConfig)?;
+        let json = serde_json::to_vec_pretty(self).context(ErrorKind::SaveConfig)?;
+        file::write_atomic(&self.config_path, &json).context(ErrorKind::SaveConfig)?;
         Ok(())
     }
 
-    pub fn launch(self) -> Result<App> {
-        Ok(App {
-            lists: self.list_paths
-                .iter()
-                .map(|(name, path)| Ok((name.clone(), NativeList::load(&path)?)))
-                .collect::<Result<HashMap<_, _>>>()
-                .context(ErrorKind::Launch)?,
+    pub fn launch(self, lock: Lock) -> Result<App> {
+        let mut app = App {
+            lists: HashMap::new(),
+            shelves: HashMap::new(),
+            secrets: HashMap::new(),
             config: self,
-        })
+            config_changed: false,
+            history: History::default(),
+            command: env::args().skip(1).collect::<Vec<_>>().join(" "),
+            holding: false,
+            pending: BTreeMap::new(),
+            _lock: lock,
+        };
+        let names: Vec<_> = app.config.list_paths.keys().cloned().collect();
+        for name in names {
+            app.forget(&name);
+        }
+        app.history = History::load(app.get_file_in_config("history.json"))
+            .context(ErrorKind::Launch)?;
+        Ok(app)
     }
 }
 
 impl App {
+    /// Drops whatever was loaded of the list `name`, so it's loaded again
+    /// the next time it's used.
+    fn forget(&mut self, name: &str) {
+        self.lists.insert(name.into(), OnceCell::new());
+        self.secrets.insert(name.into(), OnceCell::new());
+        for &shelf in SHELVES {
+            self.shelves.insert((name.into(), shelf), OnceCell::new());
+        }
+    }
+
     pub fn get_file_in_config<P: AsRef<Path>>(&self, name: P) -> PathBuf {
         self.config.config_path.parent().unwrap().join(name)
     }
 
+    /// Attaches the list at `path`, creating it if it doesn't exist yet.
+    /// Given `encryption`, the list is kept encrypted, and a new list is
+    /// encrypted with a new passphrase or keyfile.
     pub fn attach_list<S: Into<String>, P: Into<PathBuf>>(
         &mut self,
         name: S,
         path: P,
+        backend: Backend,
+        encryption: Option<Encryption>,
     ) -> Result<()> {
         let name = name.into();
         let path = path.into();
@@ -85,14 +193,74 @@ impl App {
             Err(ListAlreadyExists(name.clone())).context(ErrorKind::AddList)?;
         }
 
-        let list = NativeList::load(path.clone()).context(ErrorKind::AddList)?;
-        self.lists.insert(name.clone(), list);
+        let secret = match encryption {
+            Some(ref encryption) if path.exists() => Some(encryption.secret(&name)?),
+            Some(ref encryption) => Some(encryption.new_secret(&name)?),
+            None => None,
+        };
+        let list = NativeList::load(path.clone(), backend, secret.as_ref())
+            .context(ErrorKind::AddList)?;
+        self.forget(&name);
+        self.lists.insert(name.clone(), OnceCell::from(list));
+        self.secrets.insert(name.clone(), OnceCell::from(secret));
 
         if self.lists.len() == 1 {
             self.config.default_list = Some(name.clone());
         }
 
-        self.config.list_paths.insert(name, path);
+        self.config.list_paths.insert(
+            name,
+            ListConfig {
+                path,
+                backend,
+                git: None,
+                encryption,
+                archive_after: None,
+            },
+        );
+        self.config_changed = true;
+
+        Ok(())
+    }
+
+    /// Copies a list into a new file kept by another backend, and uses the
+    /// copy from then on. The old file is left where it was.
+    pub fn convert_list<P: Into<PathBuf>>(
+        &mut self,
+        name: &str,
+        backend: Backend,
+        path: P,
+    ) -> Result<()> {
+        let path = path.into();
+        let items = self.load(name).context(ErrorKind::ConvertList)?.items().to_vec();
+
+        if path.exists() {
+            Err(ListFileExists(path.display().to_string())).context(ErrorKind::ConvertList)?;
+        }
+        // Only JSON lists can be encrypted, so an encrypted list can't move
+        if self.config.list_paths[name].encryption.is_some() {
+            Err(CantEncrypt(name.into(), backend.name().into())).context(ErrorKind::ConvertList)?;
+        }
+
+        let list = NativeList::create(path.clone(), backend, items, None)
+            .context(ErrorKind::ConvertList)?;
+        self.lists.insert(name.into(), OnceCell::from(list));
+        self.rewrite_shelves(name, &path, backend, None)
+            .context(ErrorKind::ConvertList)?;
+        let old = &self.config.list_paths[name];
+        let (git, archive_after) = (old.git.clone(), old.archive_after);
+        self.config.list_paths.insert(
+            name.into(),
+            ListConfig {
+                path,
+                backend,
+                git,
+                encryption: None,
+                archive_after,
+            },
+        );
+        self.config_changed = true;
+        self.commit(name, &format!("convert {} to {}", name, backend.name()))?;
 
         Ok(())
     }
@@ -102,37 +270,296 @@ impl App {
             .remove(name)
             .ok_or_else(|| NoSuchListExists(name.into()))
             .context(ErrorKind::RmList)?;
+        self.secrets.remove(name);
+        for &shelf in SHELVES {
+            self.shelves.remove(&(name.into(), shelf));
+        }
         self.config.list_paths.remove(name).unwrap();
+        self.config_changed = true;
 
         Ok(())
     }
 
-    pub fn get_list(&self, name: Option<String>) -> Result<(String, &NativeList)> {
-        let name = name.or_else(|| self.config.default_list.clone())
-            .ok_or_else(|| NoListSelected)
+    /// The list `name`, loading it if this is the first time it's used.
+    fn load(&self, name: &str) -> Result<&NativeList> {
+        let cell = self.lists
+            .get(name)
+            .ok_or_else(|| NoSuchListExists(name.into()))
             .context(ErrorKind::GetList)?;
+        if let Some(list) = cell.get() {
+            return Ok(list);
+        }
 
-        Ok(self.lists
-            .get(&name)
-            .map(|list| (name.clone(), list))
-            .ok_or_else(|| NoSuchListExists(name.clone()))
-            .context(ErrorKind::GetList)?)
+        let config = &self.config.list_paths[name];
+        let list = NativeList::load(&config.path, config.backend, self.secret(name)?)
+            .context(ErrorKind::GetList)?;
+        Ok(cell.get_or_init(|| list))
     }
 
-    pub fn get_list_mut(&mut self, name: Option<String>) -> Result<(String, &mut NativeList)> {
-        let name = name.or_else(|| self.config.default_list.clone())
-            .ok_or_else(|| NoListSelected)
+    /// The secret the list `name` is encrypted with, if it is, asked for
+    /// the first time it's needed.
+    fn secret(&self, name: &str) -> Result<Option<&Secret>> {
+        let cell = &self.secrets[name];
+        if let Some(secret) = cell.get() {
+            return Ok(secret.as_ref());
+        }
+        let secret = self.config.list_paths[name].secret(name)?;
+        Ok(cell.get_or_init(|| secret).as_ref())
+    }
+
+    /// The archive or trash of the list `name`, if it has one yet.
+    fn shelf(&self, name: &str, shelf: Shelf) -> Result<Option<&NativeList>> {
+        let cell = &self.shelves[&(name.to_string(), shelf)];
+        if let Some(list) = cell.get() {
+            return Ok(Some(list));
+        }
+
+        let config = &self.config.list_paths[name];
+        let path = shelf.path(&config.path);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let list = NativeList::load(path, config.backend, self.secret(name)?)
+            .context(ErrorKind::GetList)?;
+        Ok(Some(cell.get_or_init(|| list)))
+    }
+
+    /// Like `shelf`, starting the shelf if the list doesn't have one yet.
+    fn shelf_mut(&mut self, name: &str, shelf: Shelf) -> Result<&mut NativeList> {
+        if self.shelf(name, shelf)?.is_none() {
+            let config = &self.config.list_paths[name];
+            let path = shelf.path(&config.path);
+            let list = NativeList::create(path, config.backend, vec![], self.secret(name)?)
+                .context(ErrorKind::GetList)?;
+            self.shelves.insert((name.into(), shelf), OnceCell::from(list));
+        }
+        Ok(self.shelves
+            .get_mut(&(name.into(), shelf))
+            .and_then(OnceCell::get_mut)
+            .unwrap())
+    }
+
+    /// The files the list `name` is kept in: the list itself, then its
+    /// archive and trash.
+    fn files(&self, name: &str) -> Vec<PathBuf> {
+        let path = &self.config.list_paths[name].path;
+        let mut files = vec![path.clone()];
+        files.extend(SHELVES.iter().map(|shelf| shelf.path(path)));
+        files
+    }
+
+    /// Writes the archive and trash of the list `name` to go with the list
+    /// at `path`, kept with `backend` and encrypted with `secret`.
+    fn rewrite_shelves(
+        &mut self,
+        name: &str,
+        path: &Path,
+        backend: Backend,
+        secret: Option<&Secret>,
+    ) -> Result<()> {
+        for &shelf in SHELVES {
+            let items = match self.shelf(name, shelf)? {
+                Some(list) => list.items().to_vec(),
+                None => continue,
+            };
+            let list = NativeList::create(shelf.path(path), backend, items, secret)?;
+            self.shelves.insert((name.into(), shelf), OnceCell::from(list));
+        }
+        Ok(())
+    }
+
+    /// Moves the items `ids` out of the list `name` onto one of its
+    /// shelves, replacing any older copies there. Returns how many there
+    /// were to move.
+    pub fn shelve(&mut self, name: &str, shelf: Shelf, ids: &[ItemId]) -> Result<usize> {
+        if ids.is_empty() {
+            return Ok(0);
+        }
+        self.load_mut(name)?;
+        self.shelf_mut(name, shelf)?;
+        let list = self.lists.get_mut(name).and_then(OnceCell::get_mut).unwrap();
+        let shelf = self.shelves
+            .get_mut(&(name.into(), shelf))
+            .and_then(OnceCell::get_mut)
+            .unwrap();
+
+        let mut moved = 0;
+        for id in ids {
+            if let Some(item) = list.get(id).cloned() {
+                list.remove(id);
+                shelf.insert(*id, item);
+                moved += 1;
+            }
+        }
+        self.autosave()?;
+        Ok(moved)
+    }
+
+    /// Moves the items `ids` from one of the shelves of the list `name`
+    /// back into it. Items whose id has since been taken are left where
+    /// they are. Returns how many were moved.
+    pub fn unshelve(&mut self, name: &str, shelf: Shelf, ids: &[ItemId]) -> Result<usize> {
+        self.load_mut(name)?;
+        self.shelf_mut(name, shelf)?;
+        let list = self.lists.get_mut(name).and_then(OnceCell::get_mut).unwrap();
+        let shelf = self.shelves
+            .get_mut(&(name.into(), shelf))
+            .and_then(OnceCell::get_mut)
+            .unwrap();
+
+        let mut moved = 0;
+        for id in ids {
+            if list.get(id).is_some() {
+                continue;
+            }
+            if let Some(item) = shelf.get(id).cloned() {
+                shelf.remove(id);
+                list.insert(*id, item);
+                moved += 1;
+            }
+        }
+        self.autosave()?;
+        Ok(moved)
+    }
+
+    /// Deletes the items `ids` from one of the shelves of the list `name`
+    /// for good, if it has them. Returns how many it had.
+    fn discard(&mut self, name: &str, shelf: Shelf, ids: &[ItemId]) -> Result<usize> {
+        let held = match self.shelf(name, shelf)? {
+            Some(list) => ids.iter().filter(|id| list.get(id).is_some()).count(),
+            None => return Ok(0),
+        };
+        if held > 0 {
+            let list = self.shelf_mut(name, shelf)?;
+            for id in ids {
+                list.remove(id);
+            }
+            self.autosave()?;
+        }
+        Ok(held)
+    }
+
+    /// Moves the items of the list `name` which were completed long enough
+    /// ago to its archive, if it archives items by itself.
+    fn archive_old(&mut self, name: &str) -> Result<()> {
+        let days = match self.config.list_paths[name].archive_after {
+            Some(days) => days,
+            None => return Ok(()),
+        };
+        // A cutoff before dates go back to leaves nothing to archive
+        let cutoff = match archive_cutoff(days) {
+            Some(cutoff) => cutoff,
+            None => return Ok(()),
+        };
+        let filter = Filter::completed(Range::lt(Timestamp::from_datetime(cutoff)));
+        let ids = self.load(name)?.select(&filter);
+        if ids.is_empty() {
+            return Ok(());
+        }
+
+        let moved = self.shelve(name, Shelf::Archive, &ids)?;
+        let message = format!("archive {} item(s) completed over {} day(s) ago", moved, days);
+        self.commit(name, &message)?;
+        eprintln!("{}: {}", name, message);
+        Ok(())
+    }
+
+    /// Shows the archive or trash of the list `name`.
+    pub fn cli_show_shelf(&self, name: &str, shelf: Shelf, query: &Query, plain: bool) -> Result<()> {
+        println!("{} {}:", name, shelf.name());
+        if let Some(list) = self.shelf(name, shelf)? {
+            let ids = match shelf {
+                Shelf::Archive => query.select_archived(list),
+                Shelf::Trash => query.select(list),
+            };
+            self.cli_show_items(list, ids, plain);
+        }
+        Ok(())
+    }
+
+    /// Deletes everything in the trash of the list `name` for good.
+    /// Returns how many items there were.
+    pub fn empty_trash(&mut self, name: &str) -> Result<usize> {
+        let ids = match self.shelf(name, Shelf::Trash)? {
+            Some(trash) => trash.select(&Filter::All),
+            None => return Ok(0),
+        };
+        let emptied = self.discard(name, Shelf::Trash, &ids)?;
+        if emptied > 0 {
+            self.commit(name, &format!("empty the trash of {}", name))?;
+        }
+        Ok(emptied)
+    }
+
+    /// Sets how many days after being completed the items of the list
+    /// `name` are moved to its archive, or stops moving them.
+    pub fn set_archive_after(&mut self, name: &str, days: Option<u32>) -> Result<()> {
+        if let Some(days) = days.filter(|&days| archive_cutoff(days).is_none()) {
+            Err(TooManyDays(days)).context(ErrorKind::SaveConfig)?;
+        }
+        let list = self.config
+            .list_paths
+            .get_mut(name)
+            .ok_or_else(|| NoSuchListExists(name.into()))
             .context(ErrorKind::GetList)?;
+        list.archive_after = days;
+        self.config_changed = true;
+        Ok(())
+    }
 
-        Ok(self.lists
-            .get_mut(&name)
-            .map(|list| (name.clone(), list))
-            .ok_or_else(|| NoSuchListExists(name.clone()))
+    /// The names of every attached list, in order.
+    pub fn list_names(&self) -> Vec<String> {
+        let mut names: Vec<_> = self.lists.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    fn load_mut(&mut self, name: &str) -> Result<&mut NativeList> {
+        self.load(name)?;
+        Ok(self.lists.get_mut(name).and_then(OnceCell::get_mut).unwrap())
+    }
+
+    /// The name of the list used when no list is given, if there is one.
+    pub fn default_list(&self) -> Option<&str> {
+        self.config.default_list.as_deref()
+    }
+
+    fn list_name(&self, name: Option<String>) -> Result<String> {
+        Ok(name.or_else(|| self.config.default_list.clone())
+            .ok_or_else(|| NoListSelected)
             .context(ErrorKind::GetList)?)
     }
 
+    pub fn get_list(&self, name: Option<String>) -> Result<(String, &NativeList)> {
+        let name = self.list_name(name)?;
+        let list = self.load(&name)?;
+        Ok((name, list))
+    }
+
+    /// The list `name`, or the default list, to be changed. Any items due
+    /// to be archived are archived first.
+    pub fn get_list_mut(&mut self, name: Option<String>) -> Result<(String, &mut NativeList)> {
+        let name = self.list_name(name)?;
+        self.archive_old(&name)?;
+        let list = self.load_mut(&name)?;
+        Ok((name, list))
+    }
+
+    /// Loads every attached list, which upgrades any written by older
+    /// versions.
+    pub fn load_all(&self) -> Result<()> {
+        for name in self.lists.keys() {
+            self.load(name)?;
+        }
+        Ok(())
+    }
+
     pub fn cli_show_list<L: List>(&self, list: &L, query: &Query, plain: bool) {
-        let mut iter = query.select(list).into_iter().peekable();
+        self.cli_show_items(list, query.select(list), plain)
+    }
+
+    fn cli_show_items<L: List>(&self, list: &L, ids: Vec<ItemId>, plain: bool) {
+        let mut iter = ids.into_iter().peekable();
 
         while let Some(id) = iter.next() {
             let item = list.get(&id).unwrap();
@@ -152,28 +579,603 @@ impl App {
         }
     }
 
+    pub fn cli_explain<L>(&self, list: &L, query: &Query, id: Option<ItemId>)
+    where
+        L: List,
+        L::Item: Sized,
+    {
+        println!("query: {}", query);
+        for (idx, filter) in query.filters().iter().enumerate() {
+            println!("filter {}:", idx + 1);
+            print!("{}", filter.tree());
+        }
+
+        let ids = match id {
+            Some(id) => vec![id],
+            None => list.select(&Filter::All),
+        };
+
+        for id in ids {
+            let item = match list.get(&id) {
+                Some(item) => item,
+                None => {
+                    println!("{}: no such item", id);
+                    continue;
+                }
+            };
+            let traces: Vec<_> = query
+                .filters()
+                .iter()
+                .map(|filter| filter.trace(&id, item))
+                .collect();
+
+            println!();
+            println!(
+                "{}: '{}' {}",
+                id,
+                item.get_name(),
+                if traces.iter().any(|trace| trace.matched) {
+                    "matched"
+                } else {
+                    "did not match"
+                }
+            );
+            for (idx, trace) in traces.iter().enumerate() {
+                println!("filter {}:", idx + 1);
+                print!("{}", trace);
+            }
+        }
+    }
+
+    /// Shows when an item was created and last modified, and every status
+    /// it has had.
+    pub fn cli_log<L: List>(&self, list: &L, id: ItemId) {
+        let item = match list.get(&id) {
+            Some(item) => item,
+            None => {
+                println!("{}: no such item", id);
+                return;
+            }
+        };
+
+        println!("{}: '{}'", id, item.get_name());
+        if let Some(created) = item.get_created() {
+            println!("created  {}", created);
+        }
+        if let Some(modified) = item.get_modified() {
+            println!("modified {}", modified);
+        }
+        for transition in item.get_transitions() {
+            println!("{}", transition);
+        }
+    }
+
     pub fn cli_show_all<Q: Into<Query>>(&self, query: Q, plain: bool) {
         let query = query.into();
 
-        for (name, list) in self.lists.iter() {
-            println!("{}:", name);
-            self.cli_show_list(list, &query, plain);
+        let mut names: Vec<_> = self.lists.keys().collect();
+        names.sort();
+        for name in names {
+            // A list which won't load shouldn't hide the others
+            match self.load(name) {
+                Ok(list) => {
+                    println!("{}:", name);
+                    self.cli_show_list(list, &query, plain);
+                    // Archived items are only shown when asked for
+                    if query.mentions_archived() {
+                        if let Err(err) = self.cli_show_shelf(name, Shelf::Archive, &query, plain) {
+                            let causes: Vec<_> = err.causes().map(ToString::to_string).collect();
+                            eprintln!("{} archive: {}", name, causes.join(": "));
+                        }
+                    }
+                }
+                Err(err) => {
+                    let causes: Vec<_> = err.causes().map(ToString::to_string).collect();
+                    eprintln!("{}: {}", name, causes.join(": "));
+                }
+            }
+        }
+    }
+
+    /// Writes the configuration if it changed, and whichever lists were
+    /// loaded and changed. Lists which were never loaded are left alone.
+    pub fn save(&mut self) -> Result<()> {
+        if self.config_changed {
+            self.config.save_pretty().context(ErrorKind::SaveApp)?;
+            self.config_changed = false;
+        }
+        let shelves = self.shelves.values_mut().filter_map(OnceCell::get_mut);
+        for list in self.lists.values_mut().filter_map(OnceCell::get_mut).chain(shelves) {
+            list.save().context(ErrorKind::SaveApp)?;
+        }
+        self.history.save().context(ErrorKind::SaveApp)?;
+        Ok(())
+    }
+
+    /// Saves, unless changes are being held until `flush`.
+    pub fn autosave(&mut self) -> Result<()> {
+        if self.holding {
+            return Ok(());
+        }
+        self.save()
+    }
+
+    /// Keeps changes in memory from now on, until they're flushed, instead
+    /// of writing and committing them after every command.
+    pub fn hold(&mut self) {
+        self.holding = true;
+    }
+
+    /// Saves everything held, and commits it to the lists kept in git.
+    pub fn flush(&mut self) -> Result<()> {
+        self.save()?;
+        for (name, messages) in ::std::mem::take(&mut self.pending) {
+            let message = match messages.len() {
+                1 => messages[0].clone(),
+                count => format!("{} changes to {}\n\n{}", count, name, messages.join("\n")),
+            };
+            self.commit_now(&name, &message)?;
+        }
+        Ok(())
+    }
+
+    /// Sets the command being run, for commands which don't come from the
+    /// command line.
+    pub fn set_command<S: Into<String>>(&mut self, command: S) {
+        self.command = command.into();
+    }
+
+    /// The attached lists which have been loaded, by name.
+    pub fn loaded_lists(&self) -> Vec<(&str, &NativeList)> {
+        self.lists
+            .iter()
+            .filter_map(|(name, cell)| cell.get().map(|list| (name.as_str(), list)))
+            .collect()
+    }
+
+    /// Remembers how the list `name` changed since it held `before`, so the
+    /// command which changed it can be undone.
+    pub fn record(&mut self, name: &str, before: &[(ItemId, ItemDesc)]) -> Result<()> {
+        self.record_as(name, before, None)
+    }
+
+    /// Like `record`, committing with `message` instead of a summary of
+    /// the changes.
+    pub fn record_as(
+        &mut self,
+        name: &str,
+        before: &[(ItemId, ItemDesc)],
+        message: Option<&str>,
+    ) -> Result<()> {
+        // Stamped now, so the history holds the items as they're saved
+        self.load_mut(name)?.stamp();
+        let after = self.load(name)?.items();
+        let changeset = Changeset::between(name, self.command.clone(), before, after);
+        let message = match message {
+            _ if changeset.is_empty() => None,
+            Some(message) => Some(message.to_string()),
+            None => Some(changeset.summary()),
+        };
+        self.history.record(changeset);
+        self.autosave()?;
+        if let Some(message) = message {
+            self.commit(name, &message)?;
+        }
+        Ok(())
+    }
+
+    /// Commits the list `name`, along with its archive and trash, if it's
+    /// kept in git. While changes are held, the commit waits for `flush`.
+    pub fn commit(&mut self, name: &str, message: &str) -> Result<()> {
+        if self.holding && self.config.list_paths[name].git.is_some() {
+            self.pending
+                .entry(name.into())
+                .or_default()
+                .push(message.into());
+            return Ok(());
+        }
+        self.commit_now(name, message)
+    }
+
+    fn commit_now(&self, name: &str, message: &str) -> Result<()> {
+        let list = &self.config.list_paths[name];
+        if list.git.is_some() {
+            let files: Vec<_> = self.files(name).into_iter().filter(|path| path.exists()).collect();
+            Repo::open(&list.path)?.commit(&files, message)?;
+        }
+        Ok(())
+    }
+
+    /// Starts or stops keeping the list `name` in git, pulling from and
+    /// pushing to `remote` when synced. The repository is left in place
+    /// when stopping.
+    pub fn set_git(&mut self, name: &str, git: Option<GitConfig>) -> Result<()> {
+        let list = self.config
+            .list_paths
+            .get_mut(name)
+            .ok_or_else(|| NoSuchListExists(name.into()))
+            .context(ErrorKind::Git)?;
+        list.git = git;
+        self.config_changed = true;
+        if list.git.is_some() {
+            let repo = Repo::open(&list.path)?;
+            let list = &self.config.list_paths[name];
+            let files = self.files(name);
+            let existing: Vec<_> = files.iter().filter(|path| path.exists()).collect();
+            repo.commit(&existing, &format!("track {}", name))?;
+            repo.use_merge_driver(&files, list.backend, list.encryption.as_ref())?;
+            println!("{} is kept in the git repository at {}", name, repo.root().display());
+        }
+        Ok(())
+    }
+
+    pub fn is_encrypted(&self, name: &str) -> Result<bool> {
+        let list = self.config
+            .list_paths
+            .get(name)
+            .ok_or_else(|| NoSuchListExists(name.into()))
+            .context(ErrorKind::GetList)?;
+        Ok(list.encryption.is_some())
+    }
+
+    /// Encrypts the list `name` with a new key from `encryption`, or
+    /// decrypts it given `None`. Encrypting an encrypted list changes its
+    /// key.
+    pub fn set_encryption(&mut self, name: &str, encryption: Option<Encryption>) -> Result<()> {
+        let items = self.load(name).context(ErrorKind::Encryption)?.items().to_vec();
+        let config = &self.config.list_paths[name];
+        if encryption.is_some() && config.backend != Backend::Json {
+            Err(CantEncrypt(name.into(), config.backend.name().into()))
+                .context(ErrorKind::Encryption)?;
+        }
+
+        let secret = match encryption {
+            Some(ref encryption) => Some(encryption.new_secret(name)?),
+            None => None,
+        };
+        let (path, backend) = (config.path.clone(), config.backend);
+        let list = NativeList::create(path.clone(), backend, items, secret.as_ref())
+            .context(ErrorKind::Encryption)?;
+        self.lists.insert(name.into(), OnceCell::from(list));
+        self.rewrite_shelves(name, &path, backend, secret.as_ref())
+            .context(ErrorKind::Encryption)?;
+        self.secrets.insert(name.into(), OnceCell::from(secret));
+
+        let files = self.files(name);
+        let config = self.config.list_paths.get_mut(name).unwrap();
+        config.encryption = encryption;
+        self.config_changed = true;
+        // The merge driver has to know how to open the list now
+        if config.git.is_some() {
+            Repo::open(&config.path)?.use_merge_driver(
+                &files,
+                config.backend,
+                config.encryption.as_ref(),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Syncs the list `name`, or every list kept in git with a remote, with
+    /// its remote. Lists sharing a repository and remote are synced
+    /// together.
+    pub fn sync(&mut self, name: Option<&str>) -> Result<()> {
+        // Anything held has to be committed to be synced
+        self.flush().context(ErrorKind::Sync)?;
+        let names: Vec<String> = match name {
+            Some(name) => {
+                let list = self.config
+                    .list_paths
+                    .get(name)
+                    .ok_or_else(|| NoSuchListExists(name.into()))
+                    .context(ErrorKind::Sync)?;
+                match list.git {
+                    None => Err(NotInGit(name.into())).context(ErrorKind::Sync)?,
+                    Some(GitConfig { remote: None }) => {
+                        Err(NoRemote(name.into())).context(ErrorKind::Sync)?
+                    }
+                    _ => vec![name.into()],
+                }
+            }
+            None => self.config
+                .list_paths
+                .iter()
+                .filter(|&(_, list)| list.git.as_ref().is_some_and(|git| git.remote.is_some()))
+                .map(|(name, _)| name.clone())
+                .collect(),
+        };
+
+        let mut groups: BTreeMap<(PathBuf, String), Vec<String>> = BTreeMap::new();
+        for name in names {
+            // Changes made to the file by hand would stop git from merging
+            self.commit(&name, &format!("update {}", name))?;
+            let list = &self.config.list_paths[&name];
+            let remote = list.git.as_ref().and_then(|git| git.remote.clone()).unwrap();
+            let root = Repo::open(&list.path)?.root().to_path_buf();
+            groups.entry((root, remote)).or_default().push(name);
+        }
+
+        for ((root, remote), names) in groups {
+            let mut lists = vec![];
+            for name in &names {
+                let backend = self.config.list_paths[name].backend;
+                let secret = self.secret(name)?;
+                for path in self.files(name) {
+                    lists.push((path, backend, secret));
+                }
+            }
+            let synced = Repo::open(&lists[0].0)?.sync(&remote, &lists)?;
+            // Whatever was loaded may be out of date now
+            for name in &names {
+                self.forget(name);
+            }
+
+            let what = match synced {
+                Synced::UpToDate => "up to date".into(),
+                Synced::Pushed => "pushed".into(),
+                Synced::Pulled => "pulled".into(),
+                Synced::Merged(ref conflicts) => {
+                    for conflict in conflicts {
+                        println!("conflict: {}", conflict);
+                    }
+                    format!("merged with {} conflict(s) and pushed", conflicts.len())
+                }
+            };
+            println!("{} ({}) with {}: {}", names.join(", "), root.display(), remote, what);
         }
+        Ok(())
+    }
+
+    /// Shows what undoing (or redoing) the last `count` commands would do,
+    /// and once confirmed, does it.
+    pub fn cli_undo(&mut self, count: usize, redo: bool, confirm: bool) -> Result<()> {
+        let (verb, changesets): (_, Vec<_>) = if redo {
+            ("redo", self.history.redoable(count).into_iter().cloned().collect())
+        } else {
+            (
+                "undo",
+                self.history
+                    .undoable(count)
+                    .into_iter()
+                    .map(Changeset::reversed)
+                    .collect(),
+            )
+        };
+
+        if changesets.is_empty() {
+            println!("nothing to {}", verb);
+            return Ok(());
+        }
+        for changeset in &changesets {
+            println!("{} '{}' in {}:", verb, changeset.command, changeset.list);
+            for change in &changeset.changes {
+                println!("  {}", change);
+            }
+        }
+        if confirm && !ask(&format!("{} these changes?", verb))? {
+            return Ok(());
+        }
+
+        let result = changesets
+            .iter()
+            .map(|changeset| self.revert(changeset, redo))
+            .collect::<Result<Vec<()>>>();
+        // Whatever was reverted before a failure stays reverted, so the
+        // history has to say so either way.
+        self.autosave()?;
+        result.map(|_| ())
     }
 
-    pub fn save(&self) -> Result<()> {
-        self.config.save_pretty().context(ErrorKind::SaveApp)?;
-        for (_, list) in self.lists.iter() {
-            list.save_pretty().context(ErrorKind::SaveApp)?;
+    fn revert(&mut self, changeset: &Changeset, redo: bool) -> Result<()> {
+        let list = self.load_mut(&changeset.list).context(ErrorKind::Undo)?;
+
+        let conflicts = changeset.conflicts(list);
+        if !conflicts.is_empty() {
+            let ids: Vec<_> = conflicts.iter().map(ToString::to_string).collect();
+            Err(ChangedSince(ids.join(", "))).context(ErrorKind::Undo)?;
+        }
+        // Items taken out of the list go to the trash, and ones put back
+        // don't stay in the trash or archive
+        let (removed, returned): (Vec<_>, Vec<_>) = changeset
+            .changes
+            .iter()
+            .filter(|change| change.before.is_none() != change.after.is_none())
+            .map(|change| change.id)
+            .partition(|id| list.get(id).is_some());
+        self.shelve(&changeset.list, Shelf::Trash, &removed)
+            .context(ErrorKind::Undo)?;
+        let list = self.load_mut(&changeset.list).context(ErrorKind::Undo)?;
+        changeset.apply(list);
+        self.autosave().context(ErrorKind::Undo)?;
+        for &shelf in SHELVES {
+            self.discard(&changeset.list, shelf, &returned)
+                .context(ErrorKind::Undo)?;
+        }
+        let verb = if redo { "redo" } else { "undo" };
+        self.commit(&changeset.list, &format!("{}: {}", verb, changeset.summary()))
+            .context(ErrorKind::Undo)?;
+
+        if redo {
+            self.history.mark_redone();
+        } else {
+            self.history.mark_undone();
         }
         Ok(())
     }
 }
 
-pub fn run_cli() -> Result<App> {
+/// When items have to have been completed before to be archived after
+/// `days` days, unless that's further back than dates go.
+fn archive_cutoff(days: u32) -> Option<::chrono::DateTime<::chrono::Utc>> {
+    let days = ::chrono::Duration::try_days(days.into())?;
+    Timestamp::now().datetime().checked_sub_signed(days)
+}
+
+/// Asks a yes or no question on the terminal, taking anything but yes as
+/// no.
+fn ask(question: &str) -> Result<bool> {
+    print!("{} [y/N] ", question);
+    io::stdout().flush().context(ErrorKind::Cli)?;
+    let mut answer = String::new();
+    io::stdin()
+        .read_line(&mut answer)
+        .context(ErrorKind::Cli)?;
+    Ok(["y", "yes"].contains(&answer.trim().to_lowercase().as_str()))
+}
+
+/// Reports the version of the configuration and of each list, without
+/// changing them, and fails if any need migrating.
+fn cli_migrate_check(config_path: &Path) -> Result<()> {
+    if !config_path.exists() {
+        println!("{}: not created yet", config_path.display());
+        return Ok(());
+    }
+    let file = File::open(config_path).context(ErrorKind::Migrate)?;
+    let mut value: serde_json::Value = serde_json::from_reader(file).context(ErrorKind::Migrate)?;
+
+    let mut outdated = 0;
+    let versions = Some((migrate::version_of(&value), Schema::Config.version()));
+    let mut files = vec![(config_path.to_path_buf(), versions)];
+    migrate::upgrade(Schema::Config, config_path, &mut value).context(ErrorKind::Migrate)?;
+    let config: AppConfig = serde_json::from_value(value).context(ErrorKind::Migrate)?;
+    let mut lists: Vec<_> = config.list_paths.values().collect();
+    lists.sort_by(|a, b| a.path.cmp(&b.path));
+    for list in lists {
+        let versions = migrate::list_versions(&list.path, list.backend)?;
+        files.push((list.path.clone(), versions));
+    }
+
+    for (path, versions) in files {
+        match versions {
+            Some((version, latest)) if version > latest => {
+                Err(TooNew(path.display().to_string(), version)).context(ErrorKind::Migrate)?;
+            }
+            Some((version, latest)) if version < latest => {
+                outdated += 1;
+                println!("{}: version {}, needs migrating to {}", path.display(), version, latest);
+            }
+            Some((version, _)) => println!("{}: version {}, up to date", path.display(), version),
+            None => println!("{}: not versioned", path.display()),
+        }
+    }
+
+    if outdated > 0 {
+        Err(NeedsMigration(outdated)).context(ErrorKind::Migrate)?;
+    }
+    Ok(())
+}
+
+/// The encryption asked for with `--encrypted` or `--keyfile`, if any.
+fn encryption_arg(args: &ArgMatches) -> Result<Option<Encryption>> {
+    match args.value_of("KEYFILE") {
+        Some(keyfile) => Ok(Some(Encryption::with_keyfile(keyfile)?)),
+        None if args.is_present("ENCRYPTED") => Ok(Some(Encryption::default())),
+        None => Ok(None),
+    }
+}
+
+/// Reads a list for merging. Git hands merge drivers an empty file when
+/// there is no common ancestor, which counts as an empty list.
+fn read_list(path: &Path, backend: Backend, secret: Option<&Secret>) -> Result<MemoryList> {
+    let empty = fs::metadata(path).map(|meta| meta.len() == 0).context(ErrorKind::Merge)?;
+    if empty {
+        return Ok(MemoryList::default());
+    }
+    let list = NativeList::load(path, backend, secret).context(ErrorKind::Merge)?;
+    Ok(MemoryList::from(list.items().to_vec()))
+}
+
+/// Merges the lists `ours` and `theirs`, which both started out as `base`,
+/// writing the result to `output`. Any conflicts are reported and fail the
+/// merge, as git expects of a merge driver. Encrypted lists are read with
+/// `secret`, and the result is encrypted with it too.
+fn cli_merge(
+    base: &Path,
+    ours: &Path,
+    theirs: &Path,
+    backend: Backend,
+    secret: Option<&Secret>,
+    output: &Path,
+) -> Result<()> {
+    let merged = merge::merge(
+        &read_list(base, backend, secret)?,
+        &read_list(ours, backend, secret)?,
+        &read_list(theirs, backend, secret)?,
+    );
+
+    NativeList::replace(output, backend, merged.items, secret).context(ErrorKind::Merge)?;
+
+    for conflict in &merged.conflicts {
+        eprintln!("conflict: {}", conflict);
+    }
+    if !merged.conflicts.is_empty() {
+        Err(MergeConflicts(merged.conflicts.len())).context(ErrorKind::Merge)?;
+    }
+    Ok(())
+}
+
+/// A command of `cli()`: its name followed by its aliases, and the
+/// commands under it.
+pub struct CommandNames {
+    pub names: &'static [&'static str],
+    pub subcommands: &'static [CommandNames],
+}
+
+impl CommandNames {
+    /// The command under this one called `word`, by name or alias.
+    pub fn find(&self, word: &str) -> Option<&CommandNames> {
+        self.subcommands.iter().find(|command| command.names.contains(&word))
+    }
+}
+
+const fn command(
+    names: &'static [&'static str],
+    subcommands: &'static [CommandNames],
+) -> CommandNames {
+    CommandNames { names, subcommands }
+}
+
+/// The commands of `cli()`, for the shell to complete. clap 2 doesn't give
+/// out its command tree, so this is kept in step with `cli()` by hand.
+pub static COMMANDS: CommandNames = command(&["todo_queue"], &[
+    command(&["list", "lists", "l"], &[
+        command(&["attach", "new", "a", "n"], &[]),
+        command(&["detach", "d", "delete"], &[]),
+        command(&["convert"], &[]),
+        command(&["git"], &[]),
+        command(&["encrypt"], &[]),
+        command(&["decrypt"], &[]),
+        command(&["rekey"], &[]),
+        command(&["auto-archive"], &[]),
+        command(&["compact"], &[]),
+        command(&["show"], &[]),
+    ]),
+    command(&["todo", "t", "do", "td"], &[
+        command(&["add", "a"], &[]),
+        command(&["delete", "d", "rm", "remove"], &[]),
+        command(&["restore"], &[]),
+        command(&["edit", "e"], &[]),
+        command(&["archive"], &[]),
+    ]),
+    command(&["trash"], &[command(&["show"], &[]), command(&["empty"], &[])]),
+    command(&["undo"], &[]),
+    command(&["redo"], &[]),
+    command(&["log"], &[]),
+    command(&["sync"], &[]),
+    command(&["merge"], &[]),
+    command(&["migrate"], &[]),
+    command(&["explain"], &[]),
+    command(&["import"], &[]),
+    command(&["export"], &[]),
+    command(&["shell"], &[]),
+    command(&["board"], &[]),
+]);
+
+/// The command line interface, which the shell shares.
+pub fn cli<'a, 'b>() -> ::clap::App<'a, 'b> {
     use clap::{App as Cli, Arg, SubCommand as Cmd};
 
-    let cli = Cli::new("TodoQueue")
+    Cli::new("TodoQueue")
         .version(crate_version!())
         .author(crate_authors!())
         .about("A simple task list tool")
@@ -184,6 +1186,11 @@ pub fn run_cli() -> Result<App> {
                 .required(false)
                 .short("c"),
         )
+        .arg(
+            Arg::with_name("NO_WAIT")
+                .help("Fails instead of waiting when another todo_queue is using the configuration")
+                .long("--no-wait")
+        )
         .subcommand(Cmd::with_name("list")
             .alias("lists").alias("l")
             .help("Commands for adding, removeing and modifying lists")
@@ -201,12 +1208,31 @@ pub fn run_cli() -> Result<App> {
                             .help("Specify the path the the list's state file. If no path is given, the list will be placed at APP_CONFIG_DIR/LIST_NAME.json")
                             .takes_value(true),
                     )
+                    .arg(
+                        Arg::with_name("BACKEND")
+                            .help("How the list is stored. If no backend is given, it is guessed from the file's extension")
+                            .long("--backend").short("-b").takes_value(true)
+                            .possible_values(BACKEND_NAMES)
+                    )
                     .arg(
                         Arg::with_name("DEFAULT")
                             .help("Sets this list as the default list")
                             .takes_value(false)
                             .long("--default")
                             .short("-d"),
+                    )
+                    .arg(
+                        Arg::with_name("ENCRYPTED")
+                            .help("Keeps the list encrypted with a passphrase, which is asked for, or taken \
+                                   from TODO_QUEUE_PASSPHRASE")
+                            .long("--encrypted")
+                    )
+                    .arg(
+                        Arg::with_name("KEYFILE")
+                            .help("Keeps the list encrypted with the key in this file. A new list gets a new \
+                                   key, written to the file if it doesn't exist")
+                            .long("--keyfile").takes_value(true)
+                            .conflicts_with("ENCRYPTED")
                     ),
             )
             .subcommand(
@@ -221,9 +1247,127 @@ pub fn run_cli() -> Result<App> {
                     )
             )
             .subcommand(
-                Cmd::with_name("show")
-                .arg(
-                    Arg::with_name("QUERY")
+                Cmd::with_name("convert")
+                    .about("Moves a list to another storage backend")
+                    .arg(
+                        Arg::with_name("NAME")
+                            .help("The name of the list to convert")
+                            .takes_value(true)
+                            .required(true)
+                    )
+                    .arg(
+                        Arg::with_name("BACKEND")
+                            .help("The backend to move the list to")
+                            .long("--to").takes_value(true)
+                            .possible_values(BACKEND_NAMES)
+                            .required(true)
+                    )
+                    .arg(
+                        Arg::with_name("PATH")
+                            .help("The path of the new file. If no path is given, the list's current path is \
+                                   used with the new backend's extension")
+                            .long("--path").takes_value(true)
+                    )
+            )
+            .subcommand(
+                Cmd::with_name("git")
+                    .about("Keeps a list's directory as a git repository, committing every change to the list")
+                    .arg(
+                        Arg::with_name("NAME")
+                            .help("The name of the list to keep in git")
+                            .takes_value(true)
+                            .required(true)
+                    )
+                    .arg(
+                        Arg::with_name("REMOTE")
+                            .help("The repository to sync the list with, as a URL or a path")
+                            .long("--remote").takes_value(true)
+                    )
+                    .arg(
+                        Arg::with_name("OFF")
+                            .help("Stops committing changes to the list, leaving the repository in place")
+                            .long("--off")
+                            .conflicts_with("REMOTE")
+                    )
+            )
+            .subcommand(
+                Cmd::with_name("encrypt")
+                    .about("Encrypts a list with a passphrase or a keyfile. Only json lists can be encrypted")
+                    .arg(
+                        Arg::with_name("NAME")
+                            .help("The name of the list to encrypt")
+                            .takes_value(true)
+                            .required(true)
+                    )
+                    .arg(
+                        Arg::with_name("KEYFILE")
+                            .help("Encrypts the list with the key in this file, instead of a passphrase. \
+                                   If it doesn't exist, it is made with a new random key")
+                            .long("--keyfile").takes_value(true)
+                    )
+            )
+            .subcommand(
+                Cmd::with_name("decrypt")
+                    .about("Stops keeping a list encrypted")
+                    .arg(
+                        Arg::with_name("NAME")
+                            .help("The name of the list to decrypt")
+                            .takes_value(true)
+                            .required(true)
+                    )
+            )
+            .subcommand(
+                Cmd::with_name("rekey")
+                    .about("Encrypts a list with a new passphrase or keyfile")
+                    .arg(
+                        Arg::with_name("NAME")
+                            .help("The name of the list to rekey")
+                            .takes_value(true)
+                            .required(true)
+                    )
+                    .arg(
+                        Arg::with_name("KEYFILE")
+                            .help("Encrypts the list with the key in this file from now on, instead of a \
+                                   passphrase. If it doesn't exist, it is made with a new random key")
+                            .long("--keyfile").takes_value(true)
+                    )
+            )
+            .subcommand(
+                Cmd::with_name("auto-archive")
+                    .about("Moves a list's items to its archive a number of days after they're completed")
+                    .arg(
+                        Arg::with_name("NAME")
+                            .help("The name of the list to archive items of")
+                            .takes_value(true)
+                            .required(true)
+                    )
+                    .arg(
+                        Arg::with_name("DAYS")
+                            .help("How many days after being completed items are archived")
+                            .takes_value(true)
+                            .required_unless("OFF")
+                    )
+                    .arg(
+                        Arg::with_name("OFF")
+                            .help("Stops archiving the list's items by itself")
+                            .long("--off")
+                            .conflicts_with("DAYS")
+                    )
+            )
+            .subcommand(
+                Cmd::with_name("compact")
+                    .about("Replaces the journal of a journal-backed list with a snapshot of it")
+                    .arg(
+                        Arg::with_name("NAME")
+                            .help("The name of the list to compact")
+                            .takes_value(true)
+                            .required(true)
+                    )
+            )
+            .subcommand(
+                Cmd::with_name("show")
+                .arg(
+                    Arg::with_name("QUERY")
                         .takes_value(true)
                         .min_values(1)
                 )
@@ -244,7 +1388,9 @@ pub fn run_cli() -> Result<App> {
                     .about("Add, remove or modify todolist items")
                     .arg(
                         Arg::with_name("ITEM")
-                            .help("The specification of the item to add")
+                            .help("The item to add, written as \
+                                   '[%status] name #tags key:value -- description'. \
+                                   Quote it to keep the '--' from being read as a flag")
                             .required(true)
                             .takes_value(true)
                             .min_values(1)
@@ -253,6 +1399,22 @@ pub fn run_cli() -> Result<App> {
             .subcommand(
                 Cmd::with_name("delete")
                     .alias("d").alias("rm").alias("remove")
+                    .about("Moves the items matching a query to the list's trash")
+                    .arg(
+                        Arg::with_name("QUERY")
+                            .required(true)
+                            .takes_value(true)
+                            .min_values(1)
+                    )
+            )
+            .subcommand(
+                Cmd::with_name("restore")
+                    .about("Moves the items matching a query out of the list's trash, or its archive")
+                    .arg(
+                        Arg::with_name("ARCHIVED")
+                            .help("Restores items from the archive instead of the trash")
+                            .long("--archived").short("-a")
+                    )
                     .arg(
                         Arg::with_name("QUERY")
                             .required(true)
@@ -260,8 +1422,291 @@ pub fn run_cli() -> Result<App> {
                             .min_values(1)
                     )
             )
+            .subcommand(
+                Cmd::with_name("edit")
+                    .alias("e")
+                    .about("Opens the names, tags and descriptions of the items matching a query in \
+                            $EDITOR, and applies the changes once it's closed")
+                    .arg(
+                        Arg::with_name("QUERY")
+                            .required(true)
+                            .takes_value(true)
+                            .min_values(1)
+                    )
+            )
+            .subcommand(
+                Cmd::with_name("archive")
+                    .about("Moves the completed items matching a query to the list's archive")
+                    .arg(
+                        Arg::with_name("QUERY")
+                            .help("Which items to archive. If no query is given, every completed item is")
+                            .takes_value(true)
+                            .min_values(1)
+                    )
+            )
+        )
+        .subcommand(
+            Cmd::with_name("trash")
+                .about("Commands for the items deleted from lists")
+                .subcommand(
+                    Cmd::with_name("show")
+                        .about("Shows the items in the trash")
+                        .arg(
+                            Arg::with_name("LIST")
+                                .help("The name of the list whose trash to show. If no list is given, \
+                                       every list's is")
+                                .takes_value(true)
+                        )
+                )
+                .subcommand(
+                    Cmd::with_name("empty")
+                        .about("Deletes the items in the trash for good")
+                        .arg(
+                            Arg::with_name("LIST")
+                                .help("The name of the list whose trash to empty. If no list is given, \
+                                       every list's is")
+                                .takes_value(true)
+                        )
+                )
+        )
+        .subcommand(
+            Cmd::with_name("undo")
+                .about("Undoes the changes made by the last commands")
+                .arg(
+                    Arg::with_name("COUNT")
+                        .help("How many commands to undo, defaulting to one")
+                        .takes_value(true)
+                )
+                .arg(
+                    Arg::with_name("YES")
+                        .help("Undoes the changes without asking first")
+                        .long("--yes").short("-y")
+                )
+        )
+        .subcommand(
+            Cmd::with_name("redo")
+                .about("Makes the changes undone by the last undo commands again")
+                .arg(
+                    Arg::with_name("COUNT")
+                        .help("How many commands to redo, defaulting to one")
+                        .takes_value(true)
+                )
+                .arg(
+                    Arg::with_name("YES")
+                        .help("Redoes the changes without asking first")
+                        .long("--yes").short("-y")
+                )
+        )
+        .subcommand(
+            Cmd::with_name("log")
+                .about("Shows when an item was created and changed, and the statuses it has had")
+                .arg(
+                    Arg::with_name("LIST")
+                        .help("The name of the list the item is in.")
+                        .long("--list").short("-l").takes_value(true)
+                )
+                .arg(
+                    Arg::with_name("ID")
+                        .help("The id of the item, such as @00042")
+                        .takes_value(true)
+                        .required(true)
+                )
+        )
+        .subcommand(
+            Cmd::with_name("sync")
+                .about("Pulls and pushes lists kept in git, merging them item by item when both sides changed")
+                .arg(
+                    Arg::with_name("LIST")
+                        .help("The name of the list to sync. If no list is given, every list with a remote is synced")
+                        .takes_value(true)
+                )
+        )
+        .subcommand(
+            Cmd::with_name("merge")
+                .about("Merges two copies of a list item by item, given the copy they both started from. \
+                        Works as a git merge driver, given %O %A %B")
+                .arg(
+                    Arg::with_name("BACKEND")
+                        .help("The backend the lists are kept with. If no backend is given, it is guessed \
+                               from OURS's extension")
+                        .long("--backend").short("-b").takes_value(true)
+                        .possible_values(BACKEND_NAMES)
+                )
+                .arg(
+                    Arg::with_name("OUTPUT")
+                        .help("The file to write the merged list to. If no file is given, it replaces OURS")
+                        .long("--output").short("-o").takes_value(true)
+                )
+                .arg(
+                    Arg::with_name("ENCRYPTED")
+                        .help("The lists are encrypted with a passphrase, which is asked for, or taken \
+                               from TODO_QUEUE_PASSPHRASE")
+                        .long("--encrypted")
+                )
+                .arg(
+                    Arg::with_name("KEYFILE")
+                        .help("The lists are encrypted with the key in this file")
+                        .long("--keyfile").takes_value(true)
+                        .conflicts_with("ENCRYPTED")
+                )
+                .arg(
+                    Arg::with_name("BASE")
+                        .help("The list both copies started from")
+                        .takes_value(true)
+                        .required(true)
+                )
+                .arg(
+                    Arg::with_name("OURS")
+                        .help("Our copy of the list")
+                        .takes_value(true)
+                        .required(true)
+                )
+                .arg(
+                    Arg::with_name("THEIRS")
+                        .help("Their copy of the list")
+                        .takes_value(true)
+                        .required(true)
+                )
+        )
+        .subcommand(
+            Cmd::with_name("migrate")
+                .about("Upgrades the configuration and lists written by older versions of TodoQueue")
+                .arg(
+                    Arg::with_name("CHECK")
+                        .help("Only reports which files are out of date, failing if any are")
+                        .long("--check")
+                )
+        )
+        .subcommand(
+            Cmd::with_name("explain")
+                .about("Shows how a query is parsed and which parts of it each item matches")
+                .arg(
+                    Arg::with_name("LIST")
+                        .help("The name of the list to explain the query against.")
+                        .long("--list").short("-l").takes_value(true)
+                )
+                .arg(
+                    Arg::with_name("ID")
+                        .help("Only explain the query for the item with this id")
+                        .long("--id").takes_value(true)
+                )
+                .arg(
+                    Arg::with_name("QUERY")
+                        .required(true)
+                        .takes_value(true)
+                        .min_values(1)
+                )
+        )
+        .subcommand(
+            Cmd::with_name("import")
+                .about("Imports items into a list from a file")
+                .arg(
+                    Arg::with_name("LIST")
+                        .help("The name of the list to import items into.")
+                        .long("--list").short("-l").takes_value(true)
+                )
+                .arg(
+                    Arg::with_name("FORMAT")
+                        .help("The format of the file. If no format is given, it is guessed from the file's extension")
+                        .long("--format").short("-f").takes_value(true)
+                        .possible_values(FORMAT_NAMES)
+                )
+                .arg(
+                    Arg::with_name("MAP")
+                        .help("Maps a spreadsheet column to a part of each item, written as HEADER=FIELD. \
+                               FIELD is one of name, description, status, tags or ignore, or else the \
+                               name of an attribute")
+                        .long("--map").short("-m").takes_value(true)
+                        .multiple(true).number_of_values(1)
+                )
+                .arg(
+                    Arg::with_name("DRY_RUN")
+                        .help("Shows what would be imported without changing the list")
+                        .long("--dry-run").short("-n")
+                )
+                .arg(
+                    Arg::with_name("FILE")
+                        .help("The file to import items from")
+                        .takes_value(true)
+                        .required(true)
+                )
+        )
+        .subcommand(
+            Cmd::with_name("export")
+                .about("Exports the items of a list which match a query")
+                .arg(
+                    Arg::with_name("LIST")
+                        .help("The name of the list to export items from.")
+                        .long("--list").short("-l").takes_value(true)
+                )
+                .arg(
+                    Arg::with_name("FORMAT")
+                        .help("The format to export items as")
+                        .long("--format").short("-f").takes_value(true)
+                        .possible_values(FORMAT_NAMES)
+                        .required(true)
+                )
+                .arg(
+                    Arg::with_name("GROUP")
+                        .help("How to group items under headings, for formats which have them")
+                        .long("--group").short("-g").takes_value(true)
+                        .possible_values(GROUPING_NAMES)
+                )
+                .arg(
+                    Arg::with_name("COLUMNS")
+                        .help("The columns to write for spreadsheets, separated by commas. \
+                               Defaults to id,name,status,tags,description")
+                        .long("--columns").takes_value(true)
+                )
+                .arg(
+                    Arg::with_name("OUTPUT")
+                        .help("The file to write to. If no file is given, items are written to stdout")
+                        .long("--output").short("-o").takes_value(true)
+                )
+                .arg(
+                    Arg::with_name("QUERY")
+                        .takes_value(true)
+                        .min_values(1)
+                )
+        )
+        .subcommand(
+            Cmd::with_name("shell")
+                .about("Starts an interactive session, where every command works without todo_queue in \
+                        front of it. Changes are saved on exit, or with save")
         )
-        .get_matches();
+        .subcommand(
+            Cmd::with_name("board")
+                .about("Shows the default list as a board with a column for each status, where items \
+                        can be moved along and the queue reordered. Changes are saved on quitting")
+        )
+}
+
+/// Runs the merge command, which works on the files it's given rather
+/// than on attached lists.
+fn cli_merge_cmd(merge_cmd: &ArgMatches) -> Result<()> {
+    let path = |name| Path::new(merge_cmd.value_of(name).unwrap());
+    let backend = match merge_cmd.value_of("BACKEND") {
+        Some(backend) => backend.parse::<Backend>().context(ErrorKind::Cli)?,
+        None => Backend::from_path(path("OURS")),
+    };
+    let output = merge_cmd.value_of("OUTPUT").map_or_else(|| path("OURS"), Path::new);
+    let secret = match encryption_arg(merge_cmd).context(ErrorKind::Cli)? {
+        Some(encryption) => Some(encryption.secret(output.to_string_lossy().as_ref())?),
+        None => None,
+    };
+    cli_merge(path("BASE"), path("OURS"), path("THEIRS"), backend, secret.as_ref(), output)
+}
+
+pub fn run_cli() -> Result<()> {
+    let cli = cli().get_matches();
+
+    // Merging only deals with the files it's given, and may be run by git
+    // while another todo_queue holds the lock
+    if let Some(merge_cmd) = cli.subcommand_matches("merge") {
+        return cli_merge_cmd(merge_cmd)
+            .context(ErrorKind::Cli)
+            .map_err(Error::from);
+    }
 
     // Get the path to use for configuration
     let config_path = cli.value_of("CONFIGURATION")
@@ -273,30 +1718,155 @@ pub fn run_cli() -> Result<App> {
         })
         .context(ErrorKind::Cli)?;
 
+    // Keep other invocations out until we're done with the lists
+    let mut lock_path = config_path.clone().into_os_string();
+    lock_path.push(".lock");
+    let lock = Lock::acquire(lock_path, !cli.is_present("NO_WAIT")).context(ErrorKind::Cli)?;
+
+    // Checking for old files mustn't upgrade them on load
+    if cli.subcommand_matches("migrate").is_some_and(|args| args.is_present("CHECK")) {
+        return cli_migrate_check(&config_path).context(ErrorKind::Cli).map_err(Error::from);
+    }
+
     // Load the application configuration
     let app_config = AppConfig::load(config_path).context(ErrorKind::Cli)?;
 
     // Launch the application
-    let mut app = app_config.launch().context(ErrorKind::Cli)?;
-    if let Some(list_cmd) = cli.subcommand_matches("list") {
+    let mut app = app_config.launch(lock).context(ErrorKind::Cli)?;
+    if cli.subcommand_matches("shell").is_some() {
+        return shell::run(app).context(ErrorKind::Cli).map_err(Error::from);
+    }
+    if cli.subcommand_matches("board").is_some() {
+        return board::run(&mut app).context(ErrorKind::Cli).map_err(Error::from);
+    }
+    run_command(&mut app, &cli)
+}
+
+/// Runs the command `cli` against `app`.
+pub fn run_command(app: &mut App, cli: &ArgMatches) -> Result<()> {
+    if let Some(merge_cmd) = cli.subcommand_matches("merge") {
+        cli_merge_cmd(merge_cmd).context(ErrorKind::Cli)?;
+    } else if cli.subcommand_matches("migrate").is_some_and(|args| args.is_present("CHECK")) {
+        cli_migrate_check(&app.config.config_path).context(ErrorKind::Cli)?;
+    } else if let Some(list_cmd) = cli.subcommand_matches("list") {
         if let Some(add_args) = list_cmd.subcommand_matches("attach") {
             let name = add_args.value_of("NAME").unwrap_or("default");
+            let backend = match add_args.value_of("BACKEND") {
+                Some(backend) => Some(backend.parse::<Backend>().context(ErrorKind::Cli)?),
+                None => None,
+            };
             let list_path = add_args
                 .value_of("PATH")
                 .map(PathBuf::from)
                 .unwrap_or_else(|| {
                     let mut path = app.get_file_in_config(name);
-                    path.set_extension("json");
+                    path.set_extension(backend.unwrap_or_default().extension());
                     path
                 });
+            let backend = backend.unwrap_or_else(|| Backend::from_path(&list_path));
+
+            let encryption = encryption_arg(add_args).context(ErrorKind::Cli)?;
+
+            app.attach_list(name, list_path, backend, encryption)
+                .context(ErrorKind::Cli)?;
+            app.autosave().context(ErrorKind::Cli)?;
+        } else if let Some(convert_args) = list_cmd.subcommand_matches("convert") {
+            let name = convert_args.value_of("NAME").unwrap();
+            let backend = convert_args
+                .value_of("BACKEND")
+                .unwrap()
+                .parse::<Backend>()
+                .context(ErrorKind::Cli)?;
+            let (_, list) = app.get_list(Some(name.into())).context(ErrorKind::Cli)?;
+            let old_path = list.get_path().to_path_buf();
+            if list.get_backend() == backend {
+                Err(AlreadyUsesBackend(name.into())).context(ErrorKind::ConvertList)
+                    .context(ErrorKind::Cli)?;
+            }
+            let new_path = convert_args
+                .value_of("PATH")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| old_path.with_extension(backend.extension()));
+
+            app.convert_list(name, backend, &new_path)
+                .context(ErrorKind::Cli)?;
+            app.autosave().context(ErrorKind::Cli)?;
+            println!(
+                "moved {} to {}, {} was left in place",
+                name,
+                new_path.display(),
+                old_path.display()
+            );
+        } else if let Some(git_args) = list_cmd.subcommand_matches("git") {
+            let name = git_args.value_of("NAME").unwrap();
+            let git = if git_args.is_present("OFF") {
+                None
+            } else {
+                Some(GitConfig {
+                    remote: git_args.value_of("REMOTE").map(String::from),
+                })
+            };
 
-            app.attach_list(name, list_path).context(ErrorKind::Cli)?;
-            app.save().context(ErrorKind::Cli)?;
+            app.set_git(name, git).context(ErrorKind::Cli)?;
+            app.autosave().context(ErrorKind::Cli)?;
+        } else if let Some(encrypt_args) = list_cmd.subcommand_matches("encrypt") {
+            let name = encrypt_args.value_of("NAME").unwrap();
+            if app.is_encrypted(name).context(ErrorKind::Cli)? {
+                Err(AlreadyEncrypted(name.into())).context(ErrorKind::Encryption)
+                    .context(ErrorKind::Cli)?;
+            }
+            let encryption = match encrypt_args.value_of("KEYFILE") {
+                Some(keyfile) => Encryption::with_keyfile(keyfile).context(ErrorKind::Cli)?,
+                None => Encryption::default(),
+            };
+
+            app.set_encryption(name, Some(encryption)).context(ErrorKind::Cli)?;
+            app.autosave().context(ErrorKind::Cli)?;
+            app.commit(name, &format!("encrypt {}", name)).context(ErrorKind::Cli)?;
+        } else if let Some(decrypt_args) = list_cmd.subcommand_matches("decrypt") {
+            let name = decrypt_args.value_of("NAME").unwrap();
+            if !app.is_encrypted(name).context(ErrorKind::Cli)? {
+                Err(NotEncrypted(name.into())).context(ErrorKind::Encryption)
+                    .context(ErrorKind::Cli)?;
+            }
+
+            app.set_encryption(name, None).context(ErrorKind::Cli)?;
+            app.autosave().context(ErrorKind::Cli)?;
+            app.commit(name, &format!("decrypt {}", name)).context(ErrorKind::Cli)?;
+        } else if let Some(rekey_args) = list_cmd.subcommand_matches("rekey") {
+            let name = rekey_args.value_of("NAME").unwrap();
+            if !app.is_encrypted(name).context(ErrorKind::Cli)? {
+                Err(NotEncrypted(name.into())).context(ErrorKind::Encryption)
+                    .context(ErrorKind::Cli)?;
+            }
+            let encryption = match rekey_args.value_of("KEYFILE") {
+                Some(keyfile) => Encryption::with_keyfile(keyfile).context(ErrorKind::Cli)?,
+                None => Encryption::default(),
+            };
+
+            app.set_encryption(name, Some(encryption)).context(ErrorKind::Cli)?;
+            app.autosave().context(ErrorKind::Cli)?;
+            app.commit(name, &format!("rekey {}", name)).context(ErrorKind::Cli)?;
+        } else if let Some(archive_args) = list_cmd.subcommand_matches("auto-archive") {
+            let name = archive_args.value_of("NAME").unwrap();
+            let days = match archive_args.value_of("DAYS") {
+                Some(days) => Some(days.parse::<u32>().context(ErrorKind::Cli)?),
+                None => None,
+            };
+
+            app.set_archive_after(name, days).context(ErrorKind::Cli)?;
+            app.autosave().context(ErrorKind::Cli)?;
         } else if let Some(rm_args) = list_cmd.subcommand_matches("detach") {
             let name = rm_args.value_of("NAME").unwrap();
 
             app.detach_list(name).context(ErrorKind::Cli)?;
-            app.save().context(ErrorKind::Cli)?;
+            app.autosave().context(ErrorKind::Cli)?;
+        } else if let Some(compact_args) = list_cmd.subcommand_matches("compact") {
+            let name = compact_args.value_of("NAME").unwrap();
+            let (_, list) = app.get_list_mut(Some(name.into())).context(ErrorKind::Cli)?;
+
+            list.compact().context(ErrorKind::Cli)?;
+            app.commit(name, &format!("compact {}", name)).context(ErrorKind::Cli)?;
         } else if let Some(show) = list_cmd.subcommand_matches("show") {
             let query_str = if let Some(values) = show.values_of("QUERY") {
                 values.collect::<Vec<_>>().join(" ")
@@ -308,35 +1878,265 @@ pub fn run_cli() -> Result<App> {
             app.cli_show_all(query, false);
         }
     } else if let Some(todo_cmd) = cli.subcommand_matches("todo") {
-        let (_, list) = app.get_list_mut(todo_cmd.value_of("LIST").map(String::from))
+        let (name, list) = app.get_list_mut(todo_cmd.value_of("LIST").map(String::from))
             .context(ErrorKind::Cli)?;
+        let before = list.items().to_vec();
+        let query_arg = |args: &ArgMatches| -> Result<Option<Query>> {
+            match args.values_of("QUERY") {
+                Some(values) => {
+                    let query_str = values.collect::<Vec<_>>().join(" ");
+                    Ok(Some(script::query_parser(&query_str).context(ErrorKind::Cli)?))
+                }
+                None => Ok(None),
+            }
+        };
+        let mut message = None;
         if let Some(add_cmd) = todo_cmd.subcommand_matches("add") {
-            let item = ItemDesc::from(
-                add_cmd
-                    .values_of("ITEM")
-                    .unwrap()
-                    .collect::<Vec<_>>()
-                    .join(" "),
-            );
-            list.add(item);
-
-            list.save_pretty().context(ErrorKind::Cli)?;
-        } else if let Some(delete_cmd) = todo_cmd.subcommand_matches("delete") {
-            let query_str: String = delete_cmd
-                .values_of("QUERY")
+            let item = add_cmd
+                .values_of("ITEM")
                 .unwrap()
                 .collect::<Vec<_>>()
-                .join(" ");
+                .join(" ")
+                .parse::<ItemDesc>()
+                .context(ErrorKind::Cli)?;
+            list.add(item);
+        } else if let Some(delete_cmd) = todo_cmd.subcommand_matches("delete") {
+            let query = query_arg(delete_cmd).context(ErrorKind::Cli)?.unwrap();
+            let ids = query.select(list);
 
-            let query = script::query_parser(&query_str).context(ErrorKind::Cli)?;
+            let moved = app.shelve(&name, Shelf::Trash, &ids).context(ErrorKind::Cli)?;
+            println!("moved {} item(s) to the trash of {}", moved, name);
+        } else if let Some(restore_cmd) = todo_cmd.subcommand_matches("restore") {
+            let query = query_arg(restore_cmd).context(ErrorKind::Cli)?.unwrap();
+            let shelf = if restore_cmd.is_present("ARCHIVED") {
+                Shelf::Archive
+            } else {
+                Shelf::Trash
+            };
+            let ids = match app.shelf(&name, shelf).context(ErrorKind::Cli)? {
+                Some(list) if shelf == Shelf::Archive => query.select_archived(list),
+                Some(list) => query.select(list),
+                None => vec![],
+            };
+
+            let moved = app.unshelve(&name, shelf, &ids).context(ErrorKind::Cli)?;
+            if moved < ids.len() {
+                println!("{} item(s) were left in the {}, their ids are taken", ids.len() - moved, shelf.name());
+            }
+            println!("restored {} item(s) from the {} of {}", moved, shelf.name(), name);
+            message = Some(format!("restore {} item(s) from the {}", moved, shelf.name()));
+        } else if let Some(edit_cmd) = todo_cmd.subcommand_matches("edit") {
+            let query = query_arg(edit_cmd).context(ErrorKind::Cli)?.unwrap();
+            let selected = query.select(list);
+            let ids: Vec<_> = list
+                .items()
+                .iter()
+                .map(|&(id, _)| id)
+                .filter(|id| selected.contains(id))
+                .collect();
+
+            if ids.is_empty() {
+                println!("no items of {} match", name);
+            } else {
+                // Mistakes are pointed out in the file, and the editor opened
+                // again, until it can be read
+                let mut text = edit::write(list, &ids);
+                let edits = loop {
+                    text = editor::edit(&text, "items.md").context(ErrorKind::Cli)?;
+                    match edit::parse(&text, &ids) {
+                        Ok(edits) => break edits,
+                        Err(err) => {
+                            eprintln!("{}", err);
+                            text = edit::annotate(&text, &err);
+                        }
+                    }
+                };
+                let changed = edit::apply(list, &edits);
+                println!("changed {} item(s) of {}", changed.len(), name);
+            }
+        } else if let Some(archive_cmd) = todo_cmd.subcommand_matches("archive") {
+            let query = query_arg(archive_cmd)
+                .context(ErrorKind::Cli)?
+                .unwrap_or_else(|| Filter::All.into());
+            let (ids, unfinished): (Vec<_>, Vec<_>) = query
+                .select(list)
+                .into_iter()
+                .partition(|id| *list.get(id).unwrap().get_status() == Status::Completed);
 
-            for id in query.select(list) {
-                list.remove(&id);
+            let moved = app.shelve(&name, Shelf::Archive, &ids).context(ErrorKind::Cli)?;
+            if !unfinished.is_empty() {
+                println!("{} item(s) aren't completed, and were left in place", unfinished.len());
             }
+            println!("archived {} item(s) of {}", moved, name);
+            message = Some(format!("archive {} item(s)", moved));
+        }
+        app.record_as(&name, &before, message.as_deref())
+            .context(ErrorKind::Cli)?;
+    } else if let Some(trash_cmd) = cli.subcommand_matches("trash") {
+        let (args, empty) = match trash_cmd.subcommand() {
+            ("empty", Some(args)) => (args, true),
+            (_, args) => (args.unwrap_or(trash_cmd), false),
+        };
+        let names = match args.value_of("LIST") {
+            Some(name) => vec![app.list_name(Some(name.into())).context(ErrorKind::Cli)?],
+            None => app.list_names(),
+        };
+        for name in &names {
+            if empty {
+                let emptied = app.empty_trash(name).context(ErrorKind::Cli)?;
+                println!("deleted {} item(s) from the trash of {} for good", emptied, name);
+            } else {
+                app.cli_show_shelf(name, Shelf::Trash, &Filter::All.into(), false)
+                    .context(ErrorKind::Cli)?;
+            }
+        }
+    } else if let Some(undo_cmd) = cli.subcommand_matches("undo")
+        .map(|args| (args, false))
+        .or_else(|| cli.subcommand_matches("redo").map(|args| (args, true)))
+    {
+        let (args, redo) = undo_cmd;
+        let count = match args.value_of("COUNT") {
+            Some(count) => count.parse::<usize>().context(ErrorKind::Cli)?,
+            None => 1,
+        };
+        app.cli_undo(count, redo, !args.is_present("YES"))
+            .context(ErrorKind::Cli)?;
+    } else if let Some(log_cmd) = cli.subcommand_matches("log") {
+        let id = log_cmd
+            .value_of("ID")
+            .unwrap()
+            .trim_start_matches('@')
+            .parse::<ItemId>()
+            .context(ErrorKind::Cli)?;
 
-            list.save_pretty().context(ErrorKind::Cli)?;
+        let (_, list) = app.get_list(log_cmd.value_of("LIST").map(String::from))
+            .context(ErrorKind::Cli)?;
+        app.cli_log(list, id);
+    } else if let Some(sync_cmd) = cli.subcommand_matches("sync") {
+        app.sync(sync_cmd.value_of("LIST")).context(ErrorKind::Cli)?;
+    } else if cli.subcommand_matches("migrate").is_some() {
+        // Loading upgrades the configuration and each list
+        app.load_all().context(ErrorKind::Cli)?;
+        println!("everything is up to date");
+    } else if let Some(explain_cmd) = cli.subcommand_matches("explain") {
+        let query_str = explain_cmd
+            .values_of("QUERY")
+            .unwrap()
+            .collect::<Vec<_>>()
+            .join(" ");
+        let query = script::query_parser(&query_str).context(ErrorKind::Cli)?;
+        let id = match explain_cmd.value_of("ID") {
+            Some(id) => Some(
+                id.trim_start_matches('@')
+                    .parse::<ItemId>()
+                    .context(ErrorKind::Cli)?,
+            ),
+            None => None,
+        };
+
+        let (_, list) = app.get_list(explain_cmd.value_of("LIST").map(String::from))
+            .context(ErrorKind::Cli)?;
+        app.cli_explain(list, &query, id);
+    } else if let Some(import_cmd) = cli.subcommand_matches("import") {
+        let path = import_cmd.value_of("FILE").unwrap();
+        let format = match import_cmd.value_of("FORMAT") {
+            Some(format) => format.parse::<Format>().context(ErrorKind::Cli)?,
+            None => Format::from_path(path)
+                .ok_or_else(|| UnknownFormat(path.into()))
+                .context(ErrorKind::Cli)?,
+        };
+        let mut text = String::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut text))
+            .context(ErrorKind::Import)
+            .context(ErrorKind::Cli)?;
+
+        let mut options = ImportOptions::default();
+        for map in import_cmd.values_of("MAP").into_iter().flatten() {
+            let sep = map.rfind('=')
+                .ok_or_else(|| BadMapping(map.into()))
+                .context(ErrorKind::Cli)?;
+            let column = map[sep + 1..]
+                .parse::<Column>()
+                .map_err(|_| BadMapping(map.into()))
+                .context(ErrorKind::Cli)?;
+            options.mapping = options.mapping.map(&map[..sep], column);
         }
+
+        let (name, list) = app.get_list_mut(import_cmd.value_of("LIST").map(String::from))
+            .context(ErrorKind::Cli)?;
+
+        if import_cmd.is_present("DRY_RUN") {
+            let mut preview = MemoryList::from(list.items().to_vec());
+            let report = format::import(&mut preview, &name, format, &text, &options)
+                .context(ErrorKind::Cli)?;
+            for &(sign, ids) in &[("+", &report.imported.added), ("~", &report.imported.updated)] {
+                for id in ids {
+                    let item = preview.get(id).unwrap().to_desc();
+                    println!("{} {}: {}", sign, id, script::format_item(&item));
+                }
+            }
+            for problem in &report.skipped {
+                println!("not imported: {}", problem);
+            }
+            println!(
+                "would add {} and update {} items in {}",
+                report.imported.added.len(),
+                report.imported.updated.len(),
+                name
+            );
+            return Ok(());
+        }
+
+        let before = list.items().to_vec();
+        let report = format::import(list, &name, format, &text, &options)
+            .context(ErrorKind::Cli)?;
+        app.record(&name, &before).context(ErrorKind::Cli)?;
+
+        for problem in &report.skipped {
+            println!("not imported: {}", problem);
+        }
+        println!(
+            "added {} and updated {} items in {}",
+            report.imported.added.len(),
+            report.imported.updated.len(),
+            name
+        );
+    } else if let Some(export_cmd) = cli.subcommand_matches("export") {
+        let format = export_cmd
+            .value_of("FORMAT")
+            .unwrap()
+            .parse::<Format>()
+            .context(ErrorKind::Cli)?;
+        let query_str = if let Some(values) = export_cmd.values_of("QUERY") {
+            values.collect::<Vec<_>>().join(" ")
+        } else {
+            "all".to_string()
+        };
+        let query = script::query_parser(&query_str).context(ErrorKind::Cli)?;
+
+        let (name, list) = app.get_list(export_cmd.value_of("LIST").map(String::from))
+            .context(ErrorKind::Cli)?;
+        let mut options = ExportOptions::default();
+        if let Some(grouping) = export_cmd.value_of("GROUP").and_then(format::parse_grouping) {
+            options.grouping = grouping;
+        }
+        if let Some(columns) = export_cmd.value_of("COLUMNS") {
+            options.columns = columns
+                .split(',')
+                .map(|column| column.parse::<Column>().map_err(|_| BadColumn(column.into())))
+                .collect::<::std::result::Result<_, _>>()
+                .context(ErrorKind::Cli)?;
+        }
+        let text = format::export(list, &name, &query.select(list), format, &options)
+            .context(ErrorKind::Cli)?;
+
+        match export_cmd.value_of("OUTPUT") {
+            Some(path) => File::create(path).and_then(|mut file| file.write_all(text.as_bytes())),
+            None => io::stdout().write_all(text.as_bytes()),
+        }.context(ErrorKind::Export)
+            .context(ErrorKind::Cli)?;
     }
 
-    Ok(app)
+    Ok(())
 }
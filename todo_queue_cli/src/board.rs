@@ -0,0 +1,468 @@
+//! A board with a column for each status, for looking over a list and
+//! moving its items along from the keyboard.
+//!
+//! Like the shell, the board holds changes in memory and saves and commits
+//! them when it's closed.
+
+use std::collections::HashSet;
+use failure::Fail;
+use app::App;
+use error::*;
+use term::{self, Key, Terminal};
+use todo_queue_lib::list::{Item, ItemId, List, Status, STATUSES};
+use todo_queue_lib::query::{Filter, Query};
+use todo_queue_lib::script;
+
+const HELP: &str = "h/l column  j/k item  H/L or 1-4 move  J/K reorder queue  / filter  s list  q quit";
+
+/// What the keys are doing at the moment.
+enum Mode {
+    Browse,
+    /// Typing a query, which replaces the filter once entered.
+    Filter(String),
+    /// Picking the list to show, by its index in the names of the lists.
+    Switch(usize),
+}
+
+struct Board {
+    list: String,
+    filter: String,
+    query: Query,
+    /// The column with the selection, and the selected item in each.
+    column: usize,
+    rows: [usize; 4],
+    mode: Mode,
+    message: String,
+}
+
+/// Shows the board until it's quit, then saves.
+pub fn run(app: &mut App) -> Result<()> {
+    if !term::is_terminal() {
+        Err(NotATerminal).context(ErrorKind::Board)?;
+    }
+    app.hold();
+    app.set_command("board");
+    let list = app
+        .default_list()
+        .map(String::from)
+        .or_else(|| app.list_names().into_iter().next())
+        .ok_or(NoListSelected)
+        .context(ErrorKind::Board)?;
+    // Loading may ask for a passphrase, so it's done before taking over
+    // the terminal
+    app.get_list_mut(Some(list.clone()))?;
+
+    let mut terminal = Terminal::open().context(ErrorKind::Board)?;
+    let mut board = Board::new(list);
+    loop {
+        let columns = board.columns(app)?;
+        board.clamp(&columns);
+        terminal
+            .write(&board.draw(app, &columns, terminal.size()))
+            .context(ErrorKind::Board)?;
+        let key = terminal.read_key().context(ErrorKind::Board)?;
+        board.message.clear();
+        match board.handle(app, &mut terminal, &columns, key) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(err) => board.message = describe(&err),
+        }
+    }
+
+    drop(terminal);
+    app.flush()
+}
+
+/// An error on one line, causes and all.
+fn describe(err: &Error) -> String {
+    err.causes().map(ToString::to_string).collect::<Vec<_>>().join(": ")
+}
+
+/// `text` cut or padded to exactly `width` characters.
+fn fit(text: &str, width: usize) -> String {
+    let mut fitted: String = text.chars().take(width).collect();
+    let len = fitted.chars().count();
+    fitted += &" ".repeat(width - len);
+    fitted
+}
+
+impl Board {
+    /// A board showing all of the list `list`, with the first item of the
+    /// first column selected.
+    fn new(list: String) -> Board {
+        Board {
+            list,
+            filter: String::new(),
+            query: Filter::All.into(),
+            column: 0,
+            rows: [0; 4],
+            mode: Mode::Browse,
+            message: String::new(),
+        }
+    }
+
+    /// The items of the list which match the filter, by status, in the
+    /// order they're kept in.
+    fn columns(&self, app: &App) -> Result<Vec<Vec<ItemId>>> {
+        let (_, list) = app.get_list(Some(self.list.clone()))?;
+        let matching: HashSet<_> = self.query.select(list).into_iter().collect();
+        let mut columns = vec![vec![]; STATUSES.len()];
+        for &(id, ref item) in list.items() {
+            if matching.contains(&id) {
+                let column = STATUSES.iter().position(|status| status == item.get_status()).unwrap();
+                columns[column].push(id);
+            }
+        }
+        Ok(columns)
+    }
+
+    /// Keeps the selection on an item, where a column has any.
+    fn clamp(&mut self, columns: &[Vec<ItemId>]) {
+        for (row, column) in self.rows.iter_mut().zip(columns) {
+            *row = (*row).min(column.len().saturating_sub(1));
+        }
+    }
+
+    fn selected(&self, columns: &[Vec<ItemId>]) -> Option<ItemId> {
+        columns[self.column].get(self.rows[self.column]).cloned()
+    }
+
+    fn draw(&self, app: &App, columns: &[Vec<ItemId>], (width, height): (usize, usize)) -> String {
+        let list = app.get_list(Some(self.list.clone())).map(|(_, list)| list).ok();
+        let column_width = width / STATUSES.len();
+        let card_rows = height.saturating_sub(5);
+        let mut lines = vec![];
+
+        let filter = if self.filter.is_empty() { "all" } else { &self.filter };
+        lines.push(fit(&format!(" {}  filter: {}", self.list, filter), width));
+        lines.push(
+            STATUSES
+                .iter()
+                .zip(columns)
+                .map(|(status, ids)| fit(&format!(" {} ({})", status, ids.len()), column_width))
+                .collect(),
+        );
+        lines.push("─".repeat(column_width * STATUSES.len()));
+
+        // Each column scrolls on its own, just far enough to show its
+        // selected item
+        let offsets: Vec<_> = self.rows.iter().map(|&row| (row + 1).saturating_sub(card_rows)).collect();
+        for line in 0..card_rows {
+            let mut text = String::new();
+            for (column, ids) in columns.iter().enumerate() {
+                let row = offsets[column] + line;
+                let card = match (ids.get(row), list) {
+                    (Some(id), Some(list)) => {
+                        let item = list.get(id).unwrap();
+                        let tags: String = item.get_tags().iter().map(|tag| format!(" #{}", tag)).collect();
+                        format!(" {} {}{}", id, item.get_name(), tags)
+                    }
+                    _ => String::new(),
+                };
+                let card = fit(&card, column_width);
+                if column == self.column && row == self.rows[column] && !ids.is_empty() {
+                    text += &format!("\x1b[7m{}\x1b[0m", card);
+                } else {
+                    text += &card;
+                }
+            }
+            lines.push(text);
+        }
+
+        lines.push(match self.mode {
+            Mode::Browse => fit(&self.message, width),
+            Mode::Filter(ref text) => fit(&format!("/{}", text), width),
+            Mode::Switch(selected) => {
+                let mut text = "switch to:".to_string();
+                for (at, name) in app.list_names().iter().enumerate() {
+                    if at == selected {
+                        text += &format!(" \x1b[7m{}\x1b[0m", name);
+                    } else {
+                        text += &format!(" {}", name);
+                    }
+                }
+                text
+            }
+        });
+        lines.push(fit(HELP, width));
+
+        let mut frame = "\x1b[H\x1b[2J".to_string();
+        for (row, line) in lines.iter().enumerate() {
+            frame += &format!("\x1b[{};1H{}", row + 1, line);
+        }
+        frame
+    }
+
+    /// Acts on a key. Returns whether to carry on.
+    fn handle(
+        &mut self,
+        app: &mut App,
+        terminal: &mut Terminal,
+        columns: &[Vec<ItemId>],
+        key: Key,
+    ) -> Result<bool> {
+        match self.mode {
+            Mode::Browse => {}
+            Mode::Filter(ref mut text) => {
+                match key {
+                    Key::Char(c) => {
+                        text.push(c);
+                        return Ok(true);
+                    }
+                    Key::Backspace => {
+                        text.pop();
+                        return Ok(true);
+                    }
+                    Key::Enter => {
+                        let text = ::std::mem::take(text);
+                        self.mode = Mode::Browse;
+                        self.set_filter(text)?;
+                    }
+                    Key::Escape | Key::Interrupt => self.mode = Mode::Browse,
+                    _ => {}
+                }
+                return Ok(true);
+            }
+            Mode::Switch(ref mut selected) => {
+                let names = app.list_names();
+                match key {
+                    Key::Char('h') | Key::Char('k') | Key::Left | Key::Up => {
+                        *selected = selected.saturating_sub(1)
+                    }
+                    Key::Char('l') | Key::Char('j') | Key::Right | Key::Down => {
+                        *selected = (*selected + 1).min(names.len() - 1)
+                    }
+                    Key::Enter => {
+                        let name = names[*selected].clone();
+                        self.mode = Mode::Browse;
+                        terminal
+                            .cooked(|| app.get_list_mut(Some(name.clone())).map(|_| ()))
+                            .context(ErrorKind::Board)??;
+                        self.list = name;
+                        self.rows = [0; 4];
+                    }
+                    Key::Escape | Key::Interrupt => self.mode = Mode::Browse,
+                    _ => {}
+                }
+                return Ok(true);
+            }
+        }
+
+        let last = STATUSES.len() - 1;
+        match key {
+            Key::Char('q') | Key::Escape | Key::Interrupt => return Ok(false),
+            Key::Char('h') | Key::Left => self.column = self.column.saturating_sub(1),
+            Key::Char('l') | Key::Right => self.column = (self.column + 1).min(last),
+            Key::Char('k') | Key::Up => {
+                self.rows[self.column] = self.rows[self.column].saturating_sub(1)
+            }
+            Key::Char('j') | Key::Down => self.rows[self.column] += 1,
+            Key::Char('H') | Key::Char('<') if self.column > 0 => {
+                let to = self.column - 1;
+                self.move_to(app, columns, to)?
+            }
+            Key::Char('L') | Key::Char('>') if self.column < last => {
+                let to = self.column + 1;
+                self.move_to(app, columns, to)?
+            }
+            Key::Char(c @ '1'..='4') => self.move_to(app, columns, c as usize - '1' as usize)?,
+            Key::Char('K') => self.reorder(app, columns, true)?,
+            Key::Char('J') => self.reorder(app, columns, false)?,
+            Key::Char('/') => self.mode = Mode::Filter(self.filter.clone()),
+            Key::Char('s') => {
+                let names = app.list_names();
+                let at = names.iter().position(|name| *name == self.list).unwrap_or(0);
+                self.mode = Mode::Switch(at);
+            }
+            _ => {}
+        }
+        Ok(true)
+    }
+
+    fn set_filter(&mut self, text: String) -> Result<()> {
+        self.query = if text.trim().is_empty() {
+            Filter::All.into()
+        } else {
+            script::query_parser(&text).context(ErrorKind::Board)?
+        };
+        self.filter = text;
+        self.rows = [0; 4];
+        Ok(())
+    }
+
+    /// Moves the selected item to the column `to`, if its status can
+    /// change to that one. The selection follows it.
+    fn move_to(&mut self, app: &mut App, columns: &[Vec<ItemId>], to: usize) -> Result<()> {
+        let id = match self.selected(columns) {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+        let (from, to_status) = (STATUSES[self.column], STATUSES[to]);
+        if !from.can_become(to_status) {
+            self.message = format!("can't move {} from {} to {}", id, from, to_status);
+            return Ok(());
+        }
+
+        let (name, list) = app.get_list_mut(Some(self.list.clone()))?;
+        let before = list.items().to_vec();
+        list.get_mut(&id).unwrap().set_status(to_status);
+        app.record_as(&name, &before, None)?;
+
+        let columns = self.columns(app)?;
+        self.column = to;
+        self.rows[to] = columns[to].iter().position(|&other| other == id).unwrap_or(0);
+        Ok(())
+    }
+
+    /// Moves the selected item up or down the queue, past the item next to
+    /// it on the board.
+    fn reorder(&mut self, app: &mut App, columns: &[Vec<ItemId>], up: bool) -> Result<()> {
+        let queuing = STATUSES.iter().position(|&status| status == Status::Queuing).unwrap();
+        if self.column != queuing {
+            self.message = "only the queue can be reordered".into();
+            return Ok(());
+        }
+        let row = self.rows[queuing];
+        let (id, past) = match (self.selected(columns), up) {
+            (Some(id), true) if row > 0 => (id, columns[queuing][row - 1]),
+            (Some(id), false) if row + 1 < columns[queuing].len() => (id, columns[queuing][row + 1]),
+            _ => return Ok(()),
+        };
+
+        let (name, list) = app.get_list_mut(Some(self.list.clone()))?;
+        let before = list.items().to_vec();
+        // Going up means going after whatever comes before the item passed,
+        // which may be hidden by the filter
+        let after = if up {
+            let at = list.items().iter().position(|&(other, _)| other == past).unwrap();
+            at.checked_sub(1).map(|at| list.items()[at].0)
+        } else {
+            Some(past)
+        };
+        list.move_after(&id, after.as_ref());
+        let direction = if up { "up" } else { "down" };
+        app.record_as(&name, &before, Some(&format!("move {} {} the queue", id, direction)))?;
+        self.rows[queuing] = if up { row - 1 } else { row + 1 };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process;
+    use app::{App, AppConfig};
+    use board::Board;
+    use file::Lock;
+    use storage::Backend;
+    use todo_queue_lib::list::{Item, ItemDesc, ItemId, List, Status};
+
+    /// An app of its own for the test `name`, with a list named work
+    /// holding `items`, each at its status. Returns the ids of the items.
+    fn launch(name: &str, items: &[(&str, Status)]) -> (App, Vec<ItemId>, PathBuf) {
+        let dir = env::temp_dir().join(format!("todo_queue-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        let lock = Lock::acquire(dir.join("config.json.lock"), false).unwrap();
+        let mut app = AppConfig::load(dir.join("config.json")).unwrap().launch(lock).unwrap();
+        app.hold();
+        app.attach_list("work", dir.join("work.json"), Backend::Json, None).unwrap();
+
+        let (_, list) = app.get_list_mut(Some("work".into())).unwrap();
+        let ids = items
+            .iter()
+            .map(|&(text, status)| {
+                let mut item: ItemDesc = text.parse().unwrap();
+                item.set_status(status);
+                list.add(item)
+            })
+            .collect();
+        (app, ids, dir)
+    }
+
+    fn order(app: &App) -> Vec<ItemId> {
+        let (_, list) = app.get_list(Some("work".into())).unwrap();
+        list.items().iter().map(|&(id, _)| id).collect()
+    }
+
+    #[test]
+    fn columns_hold_matching_items_by_status() {
+        let (app, ids, dir) = launch("board-columns", &[
+            ("deploy #ops", Status::Waiting),
+            ("test", Status::Queuing),
+            ("release #ops", Status::Queuing),
+            ("write docs #ops", Status::Completed),
+        ]);
+        let mut board = Board::new("work".into());
+        assert_eq!(
+            board.columns(&app).unwrap(),
+            vec![vec![ids[0]], vec![ids[1], ids[2]], vec![], vec![ids[3]]]
+        );
+
+        board.set_filter("#ops".into()).unwrap();
+        assert_eq!(
+            board.columns(&app).unwrap(),
+            vec![vec![ids[0]], vec![ids[2]], vec![], vec![ids[3]]]
+        );
+        drop(app);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn items_only_move_to_statuses_they_can_become() {
+        let (mut app, ids, dir) = launch("board-move", &[
+            ("release", Status::Queuing),
+            ("deploy", Status::Waiting),
+            ("test", Status::Waiting),
+        ]);
+        let mut board = Board::new("work".into());
+        board.rows[0] = 1;
+
+        let columns = board.columns(&app).unwrap();
+        board.move_to(&mut app, &columns, 3).unwrap();
+        assert!(board.message.starts_with("can't move"));
+        let status = |app: &App, id| {
+            let (_, list) = app.get_list(Some("work".into())).unwrap();
+            list.get(&id).unwrap().status
+        };
+        assert_eq!(status(&app, ids[2]), Status::Waiting);
+
+        board.move_to(&mut app, &columns, 1).unwrap();
+        assert_eq!(status(&app, ids[2]), Status::Queuing);
+        assert_eq!((board.column, board.rows[1]), (1, 1));
+        drop(app);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reordering_passes_the_next_item_shown() {
+        let (mut app, ids, dir) = launch("board-reorder", &[
+            ("deploy #ops", Status::Queuing),
+            ("test", Status::Queuing),
+            ("release #ops", Status::Queuing),
+        ]);
+        let mut board = Board::new("work".into());
+        let columns = board.columns(&app).unwrap();
+        board.reorder(&mut app, &columns, false).unwrap();
+        assert_eq!(board.message, "only the queue can be reordered");
+        assert_eq!(order(&app), ids);
+
+        // Test is hidden, so release goes up past deploy, to the front
+        board.set_filter("#ops".into()).unwrap();
+        board.column = 1;
+        board.rows[1] = 1;
+        let columns = board.columns(&app).unwrap();
+        board.reorder(&mut app, &columns, true).unwrap();
+        assert_eq!(order(&app), vec![ids[2], ids[0], ids[1]]);
+        assert_eq!(board.rows[1], 0);
+
+        // and back down past deploy, just after it
+        let columns = board.columns(&app).unwrap();
+        board.reorder(&mut app, &columns, false).unwrap();
+        assert_eq!(order(&app), vec![ids[0], ids[2], ids[1]]);
+        assert_eq!(board.rows[1], 1);
+        drop(app);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,356 @@
+//! Keeping lists encrypted at rest.
+//!
+//! An encrypted list is its usual file sealed with ChaCha20-Poly1305, behind
+//! a header holding what's needed to open it again: the salt and number of
+//! rounds the key was derived from the passphrase or keyfile with, and the
+//! nonce it was sealed with. The header is authenticated along with the
+//! list, so a wrong key and a tampered file both fail to open, instead of
+//! turning into a list that doesn't parse.
+
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use ring::aead::{self, Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use rpassword;
+use error::*;
+
+const MAGIC: &[u8] = b"TQSEALED";
+const FORMAT_VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+const HEADER_LEN: usize = MAGIC.len() + 1 + 4 + SALT_LEN + NONCE_LEN;
+const KEY_LEN: usize = 32;
+
+/// Rounds of PBKDF2 spent on a passphrase, to slow down guessing it.
+const PASSPHRASE_ROUNDS: u32 = 200_000;
+/// A keyfile is as random as a key already, so it only needs stretching to
+/// the right length.
+const KEYFILE_ROUNDS: u32 = 1;
+
+/// The environment variable a passphrase is taken from instead of asking
+/// for it, for scripts.
+pub const PASSPHRASE_VAR: &str = "TODO_QUEUE_PASSPHRASE";
+/// Like `PASSPHRASE_VAR`, but for the new passphrase when encrypting a list
+/// or changing its key.
+pub const NEW_PASSPHRASE_VAR: &str = "TODO_QUEUE_NEW_PASSPHRASE";
+
+/// How an encrypted list's key is come by.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Encryption {
+    /// A file whose contents are the key. Without one, the key comes from
+    /// a passphrase.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keyfile: Option<PathBuf>,
+}
+
+impl Encryption {
+    /// Encryption with the key in the file at `path`, which is kept as an
+    /// absolute path so the list opens from any directory.
+    pub fn with_keyfile<P: AsRef<Path>>(path: P) -> Result<Encryption> {
+        let path = env::current_dir().context(ErrorKind::Encryption)?.join(path);
+        Ok(Encryption { keyfile: Some(path) })
+    }
+
+    /// The secret to open the list `name` with, asking for its passphrase
+    /// if it has one.
+    pub fn secret(&self, name: &str) -> Result<Secret> {
+        match self.keyfile {
+            Some(ref keyfile) => Secret::keyfile(keyfile),
+            None => {
+                let prompt = format!("passphrase for {}: ", name);
+                Ok(Secret::passphrase(&passphrase(PASSPHRASE_VAR, &prompt)?))
+            }
+        }
+    }
+
+    /// A secret to newly encrypt the list `name` with. A passphrase is
+    /// asked for twice, and a keyfile which doesn't exist yet is made with
+    /// a random key.
+    pub fn new_secret(&self, name: &str) -> Result<Secret> {
+        match self.keyfile {
+            Some(ref keyfile) => {
+                if !keyfile.exists() {
+                    make_keyfile(keyfile)?;
+                }
+                Secret::keyfile(keyfile)
+            }
+            None => {
+                if let Ok(passphrase) = env::var(NEW_PASSPHRASE_VAR) {
+                    return Ok(Secret::passphrase(&passphrase));
+                }
+                let prompt = format!("new passphrase for {}: ", name);
+                let first = rpassword::prompt_password(prompt).context(ErrorKind::Encryption)?;
+                let again = rpassword::prompt_password("again: ").context(ErrorKind::Encryption)?;
+                if first != again {
+                    Err(PassphraseMismatch).context(ErrorKind::Encryption)?;
+                }
+                Ok(Secret::passphrase(&first))
+            }
+        }
+    }
+}
+
+fn passphrase(var: &str, prompt: &str) -> Result<String> {
+    match env::var(var) {
+        Ok(passphrase) => Ok(passphrase),
+        Err(_) => Ok(rpassword::prompt_password(prompt).context(ErrorKind::Encryption)?),
+    }
+}
+
+/// Writes a random key to a new keyfile only its owner can read.
+fn make_keyfile(path: &Path) -> Result<()> {
+    let mut key = [0; KEY_LEN];
+    SystemRandom::new().fill(&mut key).context(ErrorKind::Encryption)?;
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path).context(ErrorKind::Encryption)?;
+    file.write_all(&key).context(ErrorKind::Encryption)?;
+    file.sync_all().context(ErrorKind::Encryption)?;
+    eprintln!("made a new key at {}, keep a copy of it somewhere safe", path.display());
+    Ok(())
+}
+
+/// What a list's key is derived from.
+pub struct Secret {
+    bytes: Vec<u8>,
+    rounds: u32,
+}
+
+impl Secret {
+    pub fn passphrase(passphrase: &str) -> Secret {
+        Secret {
+            bytes: passphrase.as_bytes().to_vec(),
+            rounds: PASSPHRASE_ROUNDS,
+        }
+    }
+
+    pub fn keyfile(path: &Path) -> Result<Secret> {
+        Ok(Secret {
+            bytes: fs::read(path).context(ErrorKind::Encryption)?,
+            rounds: KEYFILE_ROUNDS,
+        })
+    }
+}
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        self.bytes.fill(0);
+    }
+}
+
+/// Whether `contents` is an encrypted list.
+pub fn is_sealed(contents: &[u8]) -> bool {
+    contents.starts_with(MAGIC)
+}
+
+/// The parts of the header of an encrypted list.
+struct Header<'a> {
+    rounds: u32,
+    salt: &'a [u8],
+    nonce: &'a [u8],
+}
+
+impl<'a> Header<'a> {
+    fn parse(path: &Path, contents: &'a [u8]) -> Result<Header<'a>> {
+        if !is_sealed(contents) || contents.len() < HEADER_LEN || contents[MAGIC.len()] != FORMAT_VERSION {
+            Err(BadContainer(path.display().to_string())).context(ErrorKind::Encryption)?;
+        }
+        let rest = &contents[MAGIC.len() + 1..];
+        let mut rounds = [0; 4];
+        rounds.copy_from_slice(&rest[..4]);
+        Ok(Header {
+            rounds: u32::from_be_bytes(rounds),
+            salt: &rest[4..4 + SALT_LEN],
+            nonce: &rest[4 + SALT_LEN..4 + SALT_LEN + NONCE_LEN],
+        })
+    }
+}
+
+/// The key a list is sealed with, along with what it was derived from
+/// besides the secret.
+#[derive(Debug)]
+pub struct Key {
+    key: LessSafeKey,
+    salt: [u8; SALT_LEN],
+    rounds: u32,
+}
+
+impl Key {
+    /// Derives a key with a new salt, to encrypt a list afresh.
+    pub fn new(secret: &Secret) -> Result<Key> {
+        let mut salt = [0; SALT_LEN];
+        SystemRandom::new().fill(&mut salt).context(ErrorKind::Encryption)?;
+        Key::derive(secret, salt, secret.rounds)
+    }
+
+    /// Derives the key the list at `path` was sealed with, checking that it
+    /// opens the list. A list that isn't encrypted yet gets a new key.
+    pub fn for_file(secret: &Secret, path: &Path) -> Result<Key> {
+        let contents = match fs::read(path) {
+            Ok(contents) => contents,
+            Err(_) => return Key::new(secret),
+        };
+        if !is_sealed(&contents) {
+            return Key::new(secret);
+        }
+        let header = Header::parse(path, &contents)?;
+        let mut salt = [0; SALT_LEN];
+        salt.copy_from_slice(header.salt);
+        let key = Key::derive(secret, salt, header.rounds)?;
+        key.open(path, &contents)?;
+        Ok(key)
+    }
+
+    fn derive(secret: &Secret, salt: [u8; SALT_LEN], rounds: u32) -> Result<Key> {
+        let mut bytes = [0; KEY_LEN];
+        let iterations = NonZeroU32::new(rounds.max(1)).unwrap();
+        pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA256, iterations, &salt, &secret.bytes, &mut bytes);
+        let key = UnboundKey::new(&CHACHA20_POLY1305, &bytes).context(ErrorKind::Encryption)?;
+        bytes.fill(0);
+        Ok(Key {
+            key: LessSafeKey::new(key),
+            salt,
+            rounds,
+        })
+    }
+
+    /// Encrypts `contents`, with a new nonce each time.
+    pub fn seal(&self, contents: &[u8]) -> Result<Vec<u8>> {
+        let mut nonce = [0; NONCE_LEN];
+        SystemRandom::new().fill(&mut nonce).context(ErrorKind::Encryption)?;
+
+        let mut sealed = Vec::with_capacity(HEADER_LEN + contents.len() + aead::MAX_TAG_LEN);
+        sealed.extend_from_slice(MAGIC);
+        sealed.push(FORMAT_VERSION);
+        sealed.extend_from_slice(&self.rounds.to_be_bytes());
+        sealed.extend_from_slice(&self.salt);
+        sealed.extend_from_slice(&nonce);
+
+        let mut body = contents.to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(&sealed[..]),
+                &mut body,
+            )
+            .context(ErrorKind::Encryption)?;
+        sealed.extend(body);
+        Ok(sealed)
+    }
+
+    /// Decrypts the list at `path`, whose contents are `sealed`. Fails with
+    /// `WrongKey` if this isn't the key it was sealed with, or if it was
+    /// changed since.
+    pub fn open(&self, path: &Path, sealed: &[u8]) -> Result<Vec<u8>> {
+        let header = Header::parse(path, sealed)?;
+        let wrong_key = || WrongKey(path.display().to_string());
+        if header.salt != self.salt || header.rounds != self.rounds {
+            Err(wrong_key()).context(ErrorKind::WrongKey)?;
+        }
+        let nonce = Nonce::try_assume_unique_for_key(header.nonce).context(ErrorKind::Encryption)?;
+
+        let mut body = sealed[HEADER_LEN..].to_vec();
+        let len = self.key
+            .open_in_place(nonce, Aad::from(&sealed[..HEADER_LEN]), &mut body)
+            .map_err(|_| wrong_key())
+            .context(ErrorKind::WrongKey)?
+            .len();
+        body.truncate(len);
+        Ok(body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process;
+    use crypt::{is_sealed, Key, Secret, HEADER_LEN, MAGIC};
+    use error::ErrorKind;
+    use storage::{Backend, Store};
+
+    const LIST: &[u8] = br#"{"version":1,"items":[]}"#;
+
+    /// A secret which is quick to derive a key from, unlike a passphrase.
+    fn secret(bytes: &[u8]) -> Secret {
+        Secret {
+            bytes: bytes.to_vec(),
+            rounds: 1,
+        }
+    }
+
+    fn temp_file(name: &str, contents: &[u8]) -> PathBuf {
+        let path = env::temp_dir().join(format!("todo_queue-{}-{}", process::id(), name));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn seal_and_open_round_trip() {
+        let key = Key::new(&secret(b"key")).unwrap();
+        let sealed = key.seal(LIST).unwrap();
+        assert!(is_sealed(&sealed));
+        assert!(!sealed.windows(LIST.len()).any(|window| window == LIST));
+
+        let path = temp_file("round-trip.json", &sealed);
+        assert_eq!(key.open(&path, &sealed).unwrap(), LIST);
+        let key = Key::for_file(&secret(b"key"), &path).unwrap();
+        assert_eq!(key.open(&path, &sealed).unwrap(), LIST);
+        assert_ne!(key.seal(LIST).unwrap(), sealed);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn wrong_key_fails_to_open() {
+        let sealed = Key::new(&secret(b"key")).unwrap().seal(LIST).unwrap();
+        let path = temp_file("wrong-key.json", &sealed);
+
+        let err = Key::for_file(&secret(b"not the key"), &path).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::WrongKey);
+        let err = Store::open(Backend::Json, &path, Some(&secret(b"not the key"))).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::WrongKey);
+        let other = Key::new(&secret(b"key")).unwrap();
+        assert_eq!(other.open(&path, &sealed).unwrap_err().kind(), ErrorKind::WrongKey);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn tampering_fails_to_open() {
+        let key = Key::new(&secret(b"key")).unwrap();
+        let sealed = key.seal(LIST).unwrap();
+        let path = temp_file("tampered.json", &sealed);
+
+        // A byte of the salt, of the nonce, and of the list itself
+        for &at in &[MAGIC.len() + 5, HEADER_LEN - 1, HEADER_LEN + 3] {
+            let mut tampered = sealed.clone();
+            tampered[at] ^= 1;
+            assert_eq!(key.open(&path, &tampered).unwrap_err().kind(), ErrorKind::WrongKey);
+
+            fs::write(&path, &tampered).unwrap();
+            let err = Key::for_file(&secret(b"key"), &path).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::WrongKey);
+        }
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn plaintext_gets_a_new_key() {
+        let path = temp_file("plaintext.json", LIST);
+        let key = Key::for_file(&secret(b"key"), &path).unwrap();
+        let sealed = key.seal(LIST).unwrap();
+        assert_eq!(key.open(&path, &sealed).unwrap(), LIST);
+        assert_ne!(Key::for_file(&secret(b"key"), &path).unwrap().salt, key.salt);
+        fs::remove_file(&path).unwrap();
+
+        assert!(Key::for_file(&secret(b"key"), &path).is_ok());
+    }
+}
@@ -0,0 +1,72 @@
+//! Editing text in the user's editor.
+
+use std::env;
+use std::fs::{self, DirBuilder, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use rand;
+use error::*;
+
+/// Makes a directory in the temporary directory which only its owner can
+/// get into, under a name no one can guess. Failing if the name is taken
+/// keeps it from being a link to somewhere else.
+fn private_dir() -> io::Result<PathBuf> {
+    let mut builder = DirBuilder::new();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    loop {
+        let dir = env::temp_dir().join(format!("todo_queue-{:016x}", rand::random::<u64>()));
+        match builder.create(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Opens `text` in `$VISUAL` or `$EDITOR`, or else vi, and returns it as
+/// it was saved. The temporary file it's written to is called `name`, so
+/// editors can tell what it holds. Since it may hold an encrypted list's
+/// items, only its owner can read it.
+pub fn edit(text: &str, name: &str) -> Result<String> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".into());
+    let dir = private_dir().context(ErrorKind::Edit)?;
+    let result = run(&editor, &dir.join(name), text);
+    // Along with anything the editor left next to the file, such as a swap
+    // file
+    let _ = fs::remove_dir_all(&dir);
+    result
+}
+
+fn run(editor: &str, path: &Path, text: &str) -> Result<String> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path).context(ErrorKind::Edit)?;
+    file.write_all(text.as_bytes()).context(ErrorKind::Edit)?;
+    drop(file);
+
+    // The editor may come with arguments of its own, such as code --wait,
+    // so it's left to the shell to split
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(path)
+        .status()
+        .context(ErrorKind::Edit)?;
+    if !status.success() {
+        Err(EditorFailed(editor.into())).context(ErrorKind::Edit)?;
+    }
+    Ok(fs::read_to_string(path).context(ErrorKind::Edit)?)
+}
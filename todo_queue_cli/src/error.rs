@@ -20,6 +20,118 @@ pub struct NoListSelected;
 #[fail(display = "No list named {} exists", _0)]
 pub struct NoSuchListExists(pub String);
 
+#[derive(Debug, Fail)]
+#[fail(display = "unknown format {}", _0)]
+pub struct UnknownFormat(pub String);
+
+#[derive(Debug, Fail)]
+#[fail(display = "{} days ago is further back than dates go", _0)]
+pub struct TooManyDays(pub u32);
+
+#[derive(Debug, Fail)]
+#[fail(display = "{} is locked by another todo_queue, try again once it is done", _0)]
+pub struct Locked(pub String);
+
+#[derive(Debug, Fail)]
+#[fail(display = "items can't be exported as {}", _0)]
+pub struct ImportOnly(pub String);
+
+#[derive(Debug, Fail)]
+#[fail(display = "bad column mapping {}, expected HEADER=FIELD", _0)]
+pub struct BadMapping(pub String);
+
+#[derive(Debug, Fail)]
+#[fail(display = "bad column name {}", _0)]
+pub struct BadColumn(pub String);
+
+#[derive(Debug, Fail)]
+#[fail(display = "unknown storage backend {}", _0)]
+pub struct UnknownBackend(pub String);
+
+#[derive(Debug, Fail)]
+#[fail(display = "{} already exists", _0)]
+pub struct ListFileExists(pub String);
+
+#[derive(Debug, Fail)]
+#[fail(display = "{} already uses that backend", _0)]
+pub struct AlreadyUsesBackend(pub String);
+
+#[derive(Debug, Fail)]
+#[fail(display = "{} isn't kept as a journal", _0)]
+pub struct NotAJournal(pub String);
+
+#[derive(Debug, Fail)]
+#[fail(display = "{} changed since, so the changes can't be reverted safely", _0)]
+pub struct ChangedSince(pub String);
+
+#[derive(Debug, Fail)]
+#[fail(display = "{} was written by a newer todo_queue (version {})", _0, _1)]
+pub struct TooNew(pub String, pub u64);
+
+#[derive(Debug, Fail)]
+#[fail(display = "{} file(s) need migrating", _0)]
+pub struct NeedsMigration(pub usize);
+
+#[derive(Debug, Fail)]
+#[fail(display = "git {} failed: {}", _0, _1)]
+pub struct GitFailed(pub String, pub String);
+
+#[derive(Debug, Fail)]
+#[fail(display = "{} isn't kept in git", _0)]
+pub struct NotInGit(pub String);
+
+#[derive(Debug, Fail)]
+#[fail(display = "{} has no remote to sync with", _0)]
+pub struct NoRemote(pub String);
+
+#[derive(Debug, Fail)]
+#[fail(display = "couldn't merge {}, which aren't lists, resolve them with git", _0)]
+pub struct UnmergedFiles(pub String);
+
+#[derive(Debug, Fail)]
+#[fail(display = "{} conflict(s) couldn't be merged, our side was kept", _0)]
+pub struct MergeConflicts(pub usize);
+
+#[derive(Debug, Fail)]
+#[fail(display = "the key for {} is wrong, or the file was tampered with", _0)]
+pub struct WrongKey(pub String);
+
+#[derive(Debug, Fail)]
+#[fail(display = "{} is encrypted, but the list isn't set up with a key", _0)]
+pub struct Encrypted(pub String);
+
+#[derive(Debug, Fail)]
+#[fail(display = "{} isn't encrypted", _0)]
+pub struct NotEncrypted(pub String);
+
+#[derive(Debug, Fail)]
+#[fail(display = "{} is already encrypted, use rekey to change its key", _0)]
+pub struct AlreadyEncrypted(pub String);
+
+#[derive(Debug, Fail)]
+#[fail(display = "only json lists can be encrypted, {} is kept as {}", _0, _1)]
+pub struct CantEncrypt(pub String, pub String);
+
+#[derive(Debug, Fail)]
+#[fail(display = "{} isn't a file encrypted by todo_queue", _0)]
+pub struct BadContainer(pub String);
+
+#[derive(Debug, Fail)]
+#[fail(display = "the passphrases didn't match")]
+pub struct PassphraseMismatch;
+
+#[derive(Debug, Fail)]
+#[fail(display = "unbalanced quotes in: {}", _0)]
+pub struct UnbalancedQuotes(pub String);
+
+#[derive(Debug, Fail)]
+#[fail(display = "the board needs a terminal")]
+pub struct NotATerminal;
+
+#[derive(Debug, Fail)]
+#[fail(display = "{} exited with an error, so nothing was changed", _0)]
+pub struct EditorFailed(pub String);
+
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Fail)]
 pub enum ErrorKind {
     #[fail(display = "failed to load config")]
@@ -42,6 +154,38 @@ pub enum ErrorKind {
     RmList,
     #[fail(display = "failed to get list")]
     GetList,
+    #[fail(display = "failed to convert list")]
+    ConvertList,
+    #[fail(display = "failed to compact list")]
+    Compact,
+    #[fail(display = "failed to undo changes")]
+    Undo,
+    #[fail(display = "failed to keep the command history")]
+    History,
+    #[fail(display = "failed to import items")]
+    Import,
+    #[fail(display = "failed to export items")]
+    Export,
+    #[fail(display = "failed to lock the configuration")]
+    Lock,
+    #[fail(display = "failed to migrate files")]
+    Migrate,
+    #[fail(display = "failed to commit to git")]
+    Git,
+    #[fail(display = "failed to sync lists")]
+    Sync,
+    #[fail(display = "failed to merge lists")]
+    Merge,
+    #[fail(display = "failed to encrypt or decrypt list")]
+    Encryption,
+    #[fail(display = "wrong key")]
+    WrongKey,
+    #[fail(display = "problem in the shell")]
+    Shell,
+    #[fail(display = "problem in the board")]
+    Board,
+    #[fail(display = "failed to edit items")]
+    Edit,
 }
 
 pub type Result<T> = result::Result<T, Error>;
@@ -0,0 +1,94 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use fs2::FileExt;
+use error::*;
+
+/// Replaces the file at `path` with `contents`, so that a crash part way
+/// through leaves either the old file or the new one, never a mix.
+///
+/// The contents are written to a temporary file in the same directory,
+/// flushed to disk, and then renamed over `path`.
+pub fn write_atomic<P: AsRef<Path>>(path: P, contents: &[u8]) -> io::Result<()> {
+    let path = path.as_ref();
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let temp_path = temp_path(path)?;
+
+    let result = File::create(&temp_path)
+        .and_then(|mut file| {
+            file.write_all(contents)?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&temp_path, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+        return result;
+    }
+
+    // Make the rename itself durable. Not every platform can open a
+    // directory, so this is best effort.
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+/// A path next to `path` for a temporary file, to be renamed over it once
+/// it's written.
+pub fn temp_path(path: &Path) -> io::Result<PathBuf> {
+    let name = path.file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file path"))?;
+    let mut temp_name = name.to_os_string();
+    temp_name.push(format!(".{}.tmp", process::id()));
+    Ok(path.with_file_name(temp_name))
+}
+
+/// An advisory lock held on a file for as long as it is alive, keeping
+/// other `todo_queue` processes from loading and saving the same lists at
+/// the same time.
+#[derive(Debug)]
+pub struct Lock {
+    file: File,
+}
+
+impl Lock {
+    /// Locks the file at `path`, creating it if needed. If another process
+    /// holds the lock, this waits for it to be released, or fails with
+    /// `Locked` if `wait` is false.
+    pub fn acquire<P: Into<PathBuf>>(path: P, wait: bool) -> Result<Lock> {
+        let path = path.into();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).context(ErrorKind::Lock)?;
+        }
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .context(ErrorKind::Lock)?;
+
+        if file.try_lock_exclusive().is_err() {
+            if !wait {
+                Err(Locked(path.display().to_string())).context(ErrorKind::Lock)?;
+            }
+            eprintln!(
+                "waiting for another todo_queue to release {}",
+                path.display()
+            );
+            file.lock_exclusive().context(ErrorKind::Lock)?;
+        }
+
+        Ok(Lock { file })
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = FileExt::unlock(&self.file);
+    }
+}
@@ -0,0 +1,183 @@
+use std::path::Path;
+use std::str::FromStr;
+use chrono::Utc;
+use todo_queue_lib::csv::{self, Column, Mapping};
+use todo_queue_lib::ical;
+use todo_queue_lib::list::{Imported, Item, ItemId, List};
+use todo_queue_lib::markdown::{self, Grouping};
+use todo_queue_lib::taskwarrior;
+use todo_queue_lib::todotxt;
+use error::*;
+
+/// The file formats items can be imported from and exported to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    TodoTxt,
+    Markdown,
+    Ics,
+    Csv,
+    Tsv,
+    /// The JSON written by Taskwarrior's `task export`, which can only be
+    /// imported.
+    Taskwarrior,
+}
+
+pub const FORMAT_NAMES: &[&str] = &["todotxt", "markdown", "ics", "csv", "tsv", "taskwarrior"];
+pub const GROUPING_NAMES: &[&str] = &["none", "status", "tag"];
+
+/// Settings for imports which only some formats use.
+#[derive(Debug, Clone, Default)]
+pub struct ImportOptions {
+    pub mapping: Mapping,
+}
+
+/// What an import did, and the parts of the file it had to leave out.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub imported: Imported,
+    pub skipped: Vec<String>,
+}
+
+/// Settings for exports which only some formats use.
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    pub grouping: Grouping,
+    pub columns: Vec<Column>,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            grouping: Grouping::Status,
+            columns: vec![
+                Column::Id,
+                Column::Name,
+                Column::Status,
+                Column::Tags,
+                Column::Description,
+            ],
+        }
+    }
+}
+
+pub fn parse_grouping(s: &str) -> Option<Grouping> {
+    match s {
+        "none" => Some(Grouping::None),
+        "status" => Some(Grouping::Status),
+        "tag" => Some(Grouping::Tag),
+        _ => None,
+    }
+}
+
+impl FromStr for Format {
+    type Err = UnknownFormat;
+    fn from_str(s: &str) -> ::std::result::Result<Self, Self::Err> {
+        match s {
+            "todotxt" => Ok(Format::TodoTxt),
+            "markdown" | "md" => Ok(Format::Markdown),
+            "ics" | "ical" => Ok(Format::Ics),
+            "csv" => Ok(Format::Csv),
+            "tsv" => Ok(Format::Tsv),
+            "taskwarrior" | "task" => Ok(Format::Taskwarrior),
+            _ => Err(UnknownFormat(s.into())),
+        }
+    }
+}
+
+impl Format {
+    /// Guesses the format of a file from its extension.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Format> {
+        let extension = path.as_ref().extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "txt" => Some(Format::TodoTxt),
+            "md" | "markdown" => Some(Format::Markdown),
+            "ics" | "ical" => Some(Format::Ics),
+            "csv" => Some(Format::Csv),
+            "tsv" | "tab" => Some(Format::Tsv),
+            _ => None,
+        }
+    }
+
+    fn delimiter(self) -> char {
+        match self {
+            Format::Tsv => '\t',
+            _ => ',',
+        }
+    }
+}
+
+/// Adds the items described by `text` to `list`, or updates the ones
+/// already in it for formats which record item ids.
+pub fn import<L: List>(
+    list: &mut L,
+    list_name: &str,
+    format: Format,
+    text: &str,
+    options: &ImportOptions,
+) -> Result<Report> {
+    let imported = match format {
+        Format::TodoTxt => Imported {
+            added: text.lines()
+                .filter_map(todotxt::parse_line)
+                .map(|item| list.add(item))
+                .collect(),
+            updated: vec![],
+        },
+        Format::Markdown => markdown::import(list, text).context(ErrorKind::Import)?,
+        Format::Ics => ical::import(list, list_name, text).context(ErrorKind::Import)?,
+        Format::Csv | Format::Tsv => {
+            let rows = csv::parse(text, format.delimiter(), &options.mapping)
+                .context(ErrorKind::Import)?;
+            return Ok(Report {
+                imported: Imported {
+                    added: rows.items
+                        .into_iter()
+                        .map(|(_, item)| list.add(item))
+                        .collect(),
+                    updated: vec![],
+                },
+                skipped: rows.errors.iter().map(ToString::to_string).collect(),
+            });
+        }
+        Format::Taskwarrior => {
+            let report = taskwarrior::import(list, text).context(ErrorKind::Import)?;
+            return Ok(Report {
+                imported: report.imported,
+                skipped: report.unmapped.iter().map(ToString::to_string).collect(),
+            });
+        }
+    };
+    Ok(Report {
+        imported,
+        skipped: vec![],
+    })
+}
+
+/// Writes out the items of `list` with the given ids.
+pub fn export<L: List>(
+    list: &L,
+    list_name: &str,
+    ids: &[ItemId],
+    format: Format,
+    options: &ExportOptions,
+) -> Result<String> {
+    match format {
+        Format::TodoTxt => Ok(ids.iter()
+            .filter_map(|id| list.get(id))
+            .map(|item| todotxt::format_item(&item.to_desc()) + "\n")
+            .collect()),
+        Format::Markdown => Ok(markdown::export(list, ids, options.grouping)),
+        Format::Ics => {
+            let stamp = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+            Ok(ical::export(list, list_name, ids, &stamp))
+        }
+        Format::Csv | Format::Tsv => Ok(csv::export(
+            list,
+            ids,
+            &options.columns,
+            format.delimiter(),
+        )),
+        Format::Taskwarrior => Err(ImportOnly("taskwarrior".into()))
+            .context(ErrorKind::Export)?,
+    }
+}
@@ -0,0 +1,319 @@
+//! Keeping lists in git repositories, with a commit for every change and
+//! syncing against a remote.
+//!
+//! Everything goes through the `git` command, so lists can share a
+//! repository that is also used by hand, such as a dotfiles repository.
+
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use todo_queue_lib::list::MemoryList;
+use todo_queue_lib::merge::{self, Conflict};
+use crypt::{Encryption, Secret};
+use error::*;
+use list::NativeList;
+use storage::Backend;
+
+/// How a list kept in git is synced.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GitConfig {
+    /// The repository to pull from and push to, as anything `git fetch`
+    /// takes: a URL or the path of another repository.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote: Option<String>,
+}
+
+/// What syncing with a remote did.
+#[derive(Debug)]
+pub enum Synced {
+    UpToDate,
+    /// Only we had new commits, which were pushed.
+    Pushed,
+    /// Only the remote had new commits, which were pulled.
+    Pulled,
+    /// Both sides had new commits, which were merged and pushed.
+    Merged(Vec<Conflict>),
+}
+
+/// A git repository holding one or more lists.
+#[derive(Debug)]
+pub struct Repo {
+    root: PathBuf,
+    /// Settings passed to every command, standing in for whatever git
+    /// hasn't been told about the user.
+    identity: Vec<String>,
+}
+
+impl Repo {
+    /// Opens the repository the list at `path` is in, making the list's
+    /// directory a repository if it isn't in one yet.
+    pub fn open(path: &Path) -> Result<Repo> {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        fs::create_dir_all(dir).context(ErrorKind::Git)?;
+        let mut repo = Repo {
+            root: dir.canonicalize().context(ErrorKind::Git)?,
+            identity: vec![],
+        };
+        if repo.try_git(&["rev-parse", "--show-toplevel"]).is_none() {
+            repo.git(&["init", "--quiet"])?;
+        }
+        repo.root = PathBuf::from(repo.git(&["rev-parse", "--show-toplevel"])?.trim());
+
+        // Committing and merging fail without knowing who the user is, so
+        // a made up identity is used instead.
+        if repo.try_git(&["config", "user.name"]).is_none() {
+            repo.identity.push("user.name=todo_queue".into());
+        }
+        if repo.try_git(&["config", "user.email"]).is_none() {
+            repo.identity.push("user.email=todo_queue@localhost".into());
+        }
+        Ok(repo)
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn command(&self, args: &[&str]) -> Command {
+        let mut command = Command::new("git");
+        command.arg("-C").arg(&self.root);
+        for setting in &self.identity {
+            command.arg("-c").arg(setting);
+        }
+        command.args(args);
+        command
+    }
+
+    fn git_bytes(&self, args: &[&str]) -> Result<Vec<u8>> {
+        let output = self.command(args).output().context(ErrorKind::Git)?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+            Err(GitFailed(args.join(" "), stderr)).context(ErrorKind::Git)?;
+        }
+        Ok(output.stdout)
+    }
+
+    /// Runs git, failing with whatever it printed if it fails.
+    fn git(&self, args: &[&str]) -> Result<String> {
+        let stdout = self.git_bytes(args)?;
+        Ok(String::from_utf8_lossy(&stdout).into_owned())
+    }
+
+    /// Runs git for a yes or no answer, or where failing is expected.
+    fn try_git(&self, args: &[&str]) -> Option<String> {
+        let output = self.command(args).output().ok()?;
+        if output.status.success() {
+            Some(String::from_utf8_lossy(&output.stdout).into_owned())
+        } else {
+            None
+        }
+    }
+
+    /// The path of `path` within the repository, as git names it.
+    fn relative(&self, path: &Path) -> Result<String> {
+        let name = path.file_name()
+            .ok_or_else(|| GitFailed("add".into(), "not a file path".into()))
+            .context(ErrorKind::Git)?;
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let dir = dir.canonicalize().context(ErrorKind::Git)?;
+        let relative = dir.strip_prefix(&self.root)
+            .map_err(|_| GitFailed("add".into(), format!("{} is outside the repository", path.display())))
+            .context(ErrorKind::Git)?
+            .join(name);
+        Ok(relative.to_string_lossy().replace('\\', "/"))
+    }
+
+    /// Commits the files at `paths` on their own, with `message`. Returns
+    /// whether there was anything to commit.
+    pub fn commit<P: AsRef<Path>>(&self, paths: &[P], message: &str) -> Result<bool> {
+        let relative = paths
+            .iter()
+            .map(|path| self.relative(path.as_ref()))
+            .collect::<Result<Vec<_>>>()?;
+        fn with_paths<'a>(mut args: Vec<&'a str>, paths: &'a [String]) -> Vec<&'a str> {
+            args.push("--");
+            args.extend(paths.iter().map(String::as_str));
+            args
+        }
+
+        self.git(&with_paths(vec!["add"], &relative))?;
+        if self.try_git(&with_paths(vec!["diff", "--cached", "--quiet"], &relative)).is_some() {
+            return Ok(false);
+        }
+        let commit = vec!["commit", "--quiet", "--no-verify", "-m", message];
+        self.git(&with_paths(commit, &relative))?;
+        Ok(true)
+    }
+
+    /// Pulls from and pushes to `remote`. When both sides have new commits,
+    /// the `lists` kept in the repository are merged item by item, instead
+    /// of line by line, opening encrypted ones with their secret.
+    pub fn sync(&self, remote: &str, lists: &[(PathBuf, Backend, Option<&Secret>)]) -> Result<Synced> {
+        let branch = self.git(&["symbolic-ref", "--short", "HEAD"])?;
+        let branch = branch.trim();
+        let refspec = format!("HEAD:refs/heads/{}", branch);
+
+        // A new remote has nothing to merge with
+        if self.try_git(&["ls-remote", "--exit-code", "--heads", remote, branch]).is_none() {
+            self.git(&["push", "--quiet", remote, &refspec])?;
+            return Ok(Synced::Pushed);
+        }
+
+        self.git(&["fetch", "--quiet", remote, branch])?;
+        let ours = self.git(&["rev-parse", "HEAD"])?;
+        let theirs = self.git(&["rev-parse", "FETCH_HEAD"])?;
+        if ours == theirs {
+            return Ok(Synced::UpToDate);
+        }
+        if self.is_ancestor("FETCH_HEAD", "HEAD") {
+            self.git(&["push", "--quiet", remote, &refspec])?;
+            return Ok(Synced::Pushed);
+        }
+        if self.is_ancestor("HEAD", "FETCH_HEAD") {
+            self.git(&["merge", "--quiet", "--ff-only", "FETCH_HEAD"])?;
+            return Ok(Synced::Pulled);
+        }
+
+        let base = self.git(&["merge-base", "HEAD", "FETCH_HEAD"])?;
+        let base = base.trim();
+        let mut merged = vec![];
+        for &(ref path, backend, secret) in lists {
+            let relative = self.relative(path)?;
+            let revs = [base, "HEAD", "FETCH_HEAD"];
+            // Such as the trash of a list which never had anything deleted
+            if !path.exists() && revs.iter().all(|rev| !self.exists_at(rev, &relative)) {
+                continue;
+            }
+            let versions = revs.iter()
+                .map(|rev| self.items_at(rev, &relative, backend, secret))
+                .collect::<Result<Vec<_>>>()?;
+            let merge = merge::merge(&versions[0], &versions[1], &versions[2]);
+            merged.push((path, relative, backend, secret, merge));
+        }
+
+        // The lists are about to be replaced, so git only has to get the
+        // rest of the repository right.
+        let _ = self.try_git(&["merge", "--quiet", "--no-ff", "--no-commit", "FETCH_HEAD"]);
+        let unmerged = self.git(&["diff", "--name-only", "--diff-filter=U"])?;
+        let unmerged: Vec<_> = unmerged
+            .lines()
+            .filter(|file| merged.iter().all(|list| list.1 != *file))
+            .collect();
+        if !unmerged.is_empty() {
+            let _ = self.try_git(&["merge", "--abort"]);
+            Err(UnmergedFiles(unmerged.join(", "))).context(ErrorKind::Sync)?;
+        }
+
+        let mut conflicts = vec![];
+        for (path, relative, backend, secret, merge) in merged {
+            NativeList::replace(path, backend, merge.items, secret).context(ErrorKind::Sync)?;
+            self.git(&["add", "--", &relative])?;
+            conflicts.extend(merge.conflicts);
+        }
+        self.git(&["commit", "--quiet", "--no-verify", "-m", &format!("merge {}", remote)])?;
+        self.git(&["push", "--quiet", remote, &refspec])?;
+        Ok(Synced::Merged(conflicts))
+    }
+
+    fn is_ancestor(&self, ancestor: &str, of: &str) -> bool {
+        self.try_git(&["merge-base", "--is-ancestor", ancestor, of]).is_some()
+    }
+
+    fn exists_at(&self, rev: &str, relative: &str) -> bool {
+        self.try_git(&["cat-file", "-e", &format!("{}:{}", rev, relative)]).is_some()
+    }
+
+    /// The items of the list at `relative` as of the commit `rev`, or none
+    /// if it didn't exist yet.
+    fn items_at(
+        &self,
+        rev: &str,
+        relative: &str,
+        backend: Backend,
+        secret: Option<&Secret>,
+    ) -> Result<MemoryList> {
+        if !self.exists_at(rev, relative) {
+            return Ok(MemoryList::default());
+        }
+        let object = format!("{}:{}", rev, relative);
+        let contents = self.git_bytes(&["show", &object])?;
+
+        // Each backend only reads from files, so the old version is put in
+        // one where it won't be in the way.
+        let git_dir = self.git(&["rev-parse", "--absolute-git-dir"])?;
+        let scratch = Path::new(git_dir.trim()).join("todo_queue");
+        fs::create_dir_all(&scratch).context(ErrorKind::Git)?;
+        let path = scratch.join(format!("{}.{}", rev, backend.extension()));
+        fs::write(&path, contents).context(ErrorKind::Git)?;
+        let items = NativeList::load(path.clone(), backend, secret)
+            .map(|list| MemoryList::from(list.items().to_vec()));
+        let _ = fs::remove_dir_all(&scratch);
+        items
+    }
+
+    /// Has git merge the list at `paths[0]` with `todo_queue merge`, along
+    /// with the other `paths` kept the same way, so that merges and pulls
+    /// made by hand go item by item too. An encrypted list gets a driver of
+    /// its own, which knows how to open it.
+    pub fn use_merge_driver<P: AsRef<Path>>(
+        &self,
+        paths: &[P],
+        backend: Backend,
+        encryption: Option<&Encryption>,
+    ) -> Result<()> {
+        let exe = env::current_exe().context(ErrorKind::Git)?;
+        let relative = self.relative(paths[0].as_ref())?;
+        let mut driver = format!("todo_queue-{}", backend.name());
+        let mut command = format!("'{}' merge --backend {}", exe.display(), backend.name());
+        if let Some(encryption) = encryption {
+            driver.push('-');
+            driver.extend(relative.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '-' }));
+            match encryption.keyfile {
+                Some(ref keyfile) => {
+                    let keyfile = keyfile.canonicalize().context(ErrorKind::Git)?;
+                    command.push_str(&format!(" --keyfile '{}'", keyfile.display()));
+                }
+                None => command.push_str(" --encrypted"),
+            }
+        }
+        self.git(&["config", &format!("merge.{}.name", driver), "todo_queue list merge"])?;
+        self.git(&[
+            "config",
+            &format!("merge.{}.driver", driver),
+            &format!("{} %O %A %B", command),
+        ])?;
+
+        // Later lines win, so this replaces any driver the list had before
+        let attributes = self.root.join(".gitattributes");
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&attributes)
+            .context(ErrorKind::Git)?;
+        let mut text = String::new();
+        file.read_to_string(&mut text).context(ErrorKind::Git)?;
+        for path in paths {
+            let relative = self.relative(path.as_ref())?;
+            let line = format!("/{} merge={}", relative, driver);
+            let prefix = format!("/{} ", relative);
+            if text.lines().rfind(|existing| existing.starts_with(&prefix)) != Some(line.as_str()) {
+                let separator = if text.is_empty() || text.ends_with('\n') { "" } else { "\n" };
+                writeln!(file, "{}{}", separator, line).context(ErrorKind::Git)?;
+                text.push_str(separator);
+                text.push_str(&line);
+                text.push('\n');
+            }
+        }
+        self.commit(&[&attributes], "merge lists with todo_queue")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,77 @@
+use std::fs::File;
+use std::path::PathBuf;
+use serde_json;
+use todo_queue_lib::history::Changeset;
+use error::*;
+use file;
+
+/// How many commands can be undone.
+const MAX_UNDO: usize = 100;
+
+/// The commands which can be undone or redone, most recent last. Kept next
+/// to the configuration so it lasts between invocations.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct History {
+    #[serde(skip)]
+    path: PathBuf,
+    undo: Vec<Changeset>,
+    redo: Vec<Changeset>,
+}
+
+impl History {
+    pub fn load<P: Into<PathBuf>>(path: P) -> Result<Self> {
+        let path = path.into();
+        let mut history: History = if path.exists() {
+            let file = File::open(&path).context(ErrorKind::History)?;
+            serde_json::from_reader(file).context(ErrorKind::History)?
+        } else {
+            History::default()
+        };
+        history.path = path;
+        Ok(history)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let json = serde_json::to_vec_pretty(self).context(ErrorKind::History)?;
+        file::write_atomic(&self.path, &json).context(ErrorKind::History)?;
+        Ok(())
+    }
+
+    /// Remembers the changes a command made. Anything which was undone
+    /// can't be redone after this.
+    pub fn record(&mut self, changeset: Changeset) {
+        if changeset.is_empty() {
+            return;
+        }
+        self.undo.push(changeset);
+        if self.undo.len() > MAX_UNDO {
+            let excess = self.undo.len() - MAX_UNDO;
+            self.undo.drain(..excess);
+        }
+        self.redo.clear();
+    }
+
+    /// The last `count` commands which can be undone, most recent first.
+    pub fn undoable(&self, count: usize) -> Vec<&Changeset> {
+        self.undo.iter().rev().take(count).collect()
+    }
+
+    /// The last `count` commands which can be redone, most recent first.
+    pub fn redoable(&self, count: usize) -> Vec<&Changeset> {
+        self.redo.iter().rev().take(count).collect()
+    }
+
+    /// Moves the last command which can be undone over to be redone.
+    pub fn mark_undone(&mut self) {
+        if let Some(changeset) = self.undo.pop() {
+            self.redo.push(changeset);
+        }
+    }
+
+    /// Moves the last command which can be redone back to be undone.
+    pub fn mark_redone(&mut self) {
+        if let Some(changeset) = self.redo.pop() {
+            self.undo.push(changeset);
+        }
+    }
+}
@@ -1,143 +1,185 @@
-use std::collections::HashSet;
-use todo_queue_lib::list::{Item, ItemDesc, ItemId, List, Status};
+use todo_queue_lib::list::{ItemDesc, ItemId, List};
 use todo_queue_lib::query::Filter;
+use todo_queue_lib::storage::StoredList;
+use std::fs;
 use std::path::{Path, PathBuf};
-use std::fs::File;
-use serde_json;
-use rand;
+use crypt::Secret;
 use error::*;
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct NativeItem {
-    name: String,
-    description: String,
-    status: Status,
-    tags: HashSet<String>,
+use file;
+use storage::{Backend, Store};
+
+/// Where items taken out of a list are kept, each in a file of its own
+/// next to the list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Shelf {
+    /// Completed items moved out of the way.
+    Archive,
+    /// Deleted items, until the trash is emptied.
+    Trash,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct NativeList {
-    items: Vec<(ItemId, NativeItem)>,
-    path: PathBuf,
-}
+pub const SHELVES: &[Shelf] = &[Shelf::Archive, Shelf::Trash];
 
-impl From<ItemDesc> for NativeItem {
-    fn from(item: ItemDesc) -> Self {
-        let ItemDesc {
-            name,
-            description,
-            status,
-            tags,
-        } = item;
-
-        Self {
-            name,
-            description,
-            status,
-            tags: tags.into_iter().collect(),
+impl Shelf {
+    pub fn name(self) -> &'static str {
+        match self {
+            Shelf::Archive => "archive",
+            Shelf::Trash => "trash",
         }
     }
-}
 
-impl Item for NativeItem {
-    fn get_name(&self) -> &str {
-        &self.name
-    }
-    fn get_description(&self) -> &str {
-        &self.description
-    }
-    fn get_status(&self) -> &Status {
-        &self.status
-    }
-    fn has_tag(&self, tag: &str) -> bool {
-        self.tags.contains(tag)
-    }
-
-    fn set_name(&mut self, name: &str) {
-        self.name = name.into();
-    }
-    fn set_description(&mut self, description: &str) {
-        self.description = description.into()
-    }
-    fn set_status(&mut self, status: Status) {
-        self.status = status;
-    }
-    fn set_tag(&mut self, tag: &str, set: bool) {
-        if set {
-            self.tags.insert(tag.into());
-        } else {
-            self.tags.remove(tag);
+    /// The file the shelf of the list at `path` is kept in, such as
+    /// `work.trash.json` for `work.json`.
+    pub fn path(self, path: &Path) -> PathBuf {
+        let mut name = path.file_stem().unwrap_or_default().to_os_string();
+        name.push(".");
+        name.push(self.name());
+        if let Some(extension) = path.extension() {
+            name.push(".");
+            name.push(extension);
         }
+        path.with_file_name(name)
     }
 }
 
+/// A list attached to the app, kept in a file by one of the storage
+/// backends.
+#[derive(Debug)]
+pub struct NativeList {
+    list: StoredList<Store>,
+    path: PathBuf,
+    backend: Backend,
+}
+
 impl List for NativeList {
-    type Item = NativeItem;
+    type Item = ItemDesc;
 
     fn add(&mut self, item: ItemDesc) -> ItemId {
-        let item: Self::Item = item.into();
-        let id = rand::random();
+        self.list.add(item)
+    }
 
-        self.items.push((id, item));
-        id
+    fn insert(&mut self, id: ItemId, item: ItemDesc) {
+        self.list.insert(id, item)
     }
 
     fn remove(&mut self, target_id: &ItemId) {
-        if let Some(idx) = self.items.iter().position(|me| me.0 == *target_id) {
-            self.items.remove(idx);
-        }
+        self.list.remove(target_id)
+    }
+
+    fn move_after(&mut self, id: &ItemId, after: Option<&ItemId>) {
+        self.list.move_after(id, after)
     }
 
     fn get(&self, target_id: &ItemId) -> Option<&Self::Item> {
-        self.items
-            .iter()
-            .find(|me| me.0 == *target_id)
-            .map(|&(_, ref item)| item)
+        self.list.get(target_id)
     }
 
     fn get_mut(&mut self, target_id: &ItemId) -> Option<&mut Self::Item> {
-        self.items
-            .iter_mut()
-            .find(|me| me.0 == *target_id)
-            .map(|&mut (_, ref mut item)| item)
+        self.list.get_mut(target_id)
     }
 
     fn select(&self, filter: &Filter) -> Vec<ItemId> {
-        self.items
-            .iter()
-            .filter(|&&(ref id, ref item)| filter.matches(id, item))
-            .map(|m| m.0)
-            .collect()
+        self.list.select(filter)
     }
 }
 
 impl NativeList {
-    fn default_with_path<P: Into<PathBuf>>(path: P) -> Self {
-        Self {
-            items: Vec::new(),
-            path: path.into(),
-        }
+    /// Writes whatever changed since the list was loaded.
+    pub fn save(&mut self) -> Result<()> {
+        self.list.save().context(ErrorKind::SaveList)?;
+        Ok(())
     }
 
-    pub fn save_pretty(&self) -> Result<()> {
-        let file = File::create(&self.path).context(ErrorKind::SaveList)?;
-        serde_json::to_writer_pretty(file, self).context(ErrorKind::SaveList)?;
-        Ok(())
+    /// Timestamps whatever was added or modified since the list was saved,
+    /// as saving would.
+    pub fn stamp(&mut self) {
+        self.list.stamp();
     }
 
-    pub fn load<P: Into<PathBuf>>(path: P) -> Result<Self> {
+    /// Loads the list at `path`, creating it if it doesn't exist yet. An
+    /// encrypted list needs the `secret` it was encrypted with.
+    pub fn load<P: Into<PathBuf>>(path: P, backend: Backend, secret: Option<&Secret>) -> Result<Self> {
+        let path = path.into();
+        let store = Store::open(backend, &path, secret)?;
+        Ok(Self {
+            list: StoredList::open(store).context(ErrorKind::LoadList)?,
+            path,
+            backend,
+        })
+    }
+
+    /// Writes `items` to a new list at `path`, replacing anything there,
+    /// and encrypting it with a new key if there is a `secret`.
+    pub fn create<P: Into<PathBuf>>(
+        path: P,
+        backend: Backend,
+        items: Vec<(ItemId, ItemDesc)>,
+        secret: Option<&Secret>,
+    ) -> Result<Self> {
         let path = path.into();
-        if !path.exists() {
-            let list = Self::default_with_path(path);
-            list.save_pretty().context(ErrorKind::SaveList)?;
-            Ok(list)
-        } else {
-            let file = File::open(path).context(ErrorKind::LoadList)?;
-            Ok(serde_json::from_reader(file).context(ErrorKind::LoadList)?)
+        let store = Store::create(backend, &path, secret).context(ErrorKind::SaveList)?;
+        let mut list = Self {
+            list: StoredList::with_items(items, store),
+            path,
+            backend,
+        };
+        list.save()?;
+        Ok(list)
+    }
+
+    /// Like `create`, but the new list is written beside `path` and only
+    /// then moved into place, so the list already there stays whole if
+    /// writing fails.
+    pub fn replace<P: AsRef<Path>>(
+        path: P,
+        backend: Backend,
+        items: Vec<(ItemId, ItemDesc)>,
+        secret: Option<&Secret>,
+    ) -> Result<()> {
+        let path = path.as_ref();
+        let temp = file::temp_path(path).context(ErrorKind::SaveList)?;
+        // A crdt list is a directory of replicas rather than a file
+        let remove = |temp: &Path| {
+            if temp.is_dir() {
+                fs::remove_dir_all(temp)
+            } else {
+                fs::remove_file(temp)
+            }
+        };
+        // Anything left there by a run which didn't finish would be added to
+        let _ = remove(&temp);
+
+        let result = NativeList::create(temp.clone(), backend, items, secret).and_then(|list| {
+            drop(list);
+            fs::rename(&temp, path).context(ErrorKind::SaveList)?;
+            Ok(())
+        });
+        if result.is_err() {
+            let _ = remove(&temp);
         }
+        result
+    }
+
+    /// Replaces the journal of a journal-backed list with a snapshot of
+    /// the list as it is now.
+    pub fn compact(&mut self) -> Result<()> {
+        self.save()?;
+        match *self.list.storage_mut() {
+            Store::Journal(ref mut journal) => journal.compact().context(ErrorKind::Compact)?,
+            _ => Err(NotAJournal(self.path.display().to_string())).context(ErrorKind::Compact)?,
+        }
+        Ok(())
+    }
+
+    pub fn items(&self) -> &[(ItemId, ItemDesc)] {
+        self.list.items()
     }
 
     pub fn get_path(&self) -> &Path {
         &self.path
     }
+
+    pub fn get_backend(&self) -> Backend {
+        self.backend
+    }
 }
@@ -1,9 +1,15 @@
 extern crate app_dirs;
+extern crate chrono;
 #[macro_use]
 extern crate clap;
 #[macro_use]
 extern crate failure;
+extern crate fs2;
+extern crate libc;
 extern crate rand;
+extern crate ring;
+extern crate rpassword;
+extern crate rusqlite;
 extern crate rustyline;
 extern crate serde;
 #[macro_use]
@@ -13,8 +19,28 @@ extern crate todo_queue_lib;
 
 mod list;
 mod app;
+mod board;
+mod crypt;
+mod editor;
 mod error;
+mod format;
+mod file;
+mod git;
+mod history;
+mod migrate;
+mod shell;
+mod storage;
+mod term;
+
+use std::process;
+use failure::Fail;
 
 fn main() {
-    let _ = app::run_cli().unwrap();
+    if let Err(err) = app::run_cli() {
+        eprintln!("error: {}", err);
+        for cause in err.causes().skip(1) {
+            eprintln!("  caused by: {}", cause);
+        }
+        process::exit(1);
+    }
 }
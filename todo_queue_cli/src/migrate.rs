@@ -0,0 +1,167 @@
+//! Upgrading list and configuration files written by older versions.
+//!
+//! JSON files carry a `version` field, with files from before versioning
+//! counting as version 0. Each migration takes a file's JSON from one
+//! version to the next, so an old file is brought up to date by running
+//! every migration after its version in turn. SQLite lists keep their
+//! version in `PRAGMA user_version` and are upgraded with SQL.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use rusqlite::{Connection, OpenFlags};
+use serde_json::{Map, Value};
+use crypt;
+use error::*;
+use storage::Backend;
+
+type Migration = fn(&mut Value);
+
+/// Migrations for list files, the first taking version 0 to version 1.
+const LIST_MIGRATIONS: &[Migration] = &[list_v1];
+
+/// Migrations for the configuration file.
+const CONFIG_MIGRATIONS: &[Migration] = &[config_v1];
+
+/// Migrations for SQLite lists, run on top of the base tables.
+pub const SQLITE_MIGRATIONS: &[&str] = &["
+    CREATE TABLE IF NOT EXISTS timestamps (
+        item INTEGER PRIMARY KEY REFERENCES items(id) ON DELETE CASCADE,
+        created TEXT,
+        modified TEXT
+    );
+    CREATE TABLE IF NOT EXISTS transitions (
+        item INTEGER NOT NULL REFERENCES items(id) ON DELETE CASCADE,
+        seq INTEGER NOT NULL,
+        from_status TEXT,
+        to_status TEXT NOT NULL,
+        at TEXT NOT NULL,
+        PRIMARY KEY (item, seq)
+    );
+", "
+    ALTER TABLE items ADD COLUMN position INTEGER;
+", "
+    ALTER TABLE tags ADD COLUMN position INTEGER;
+"];
+
+/// The kinds of JSON file which are versioned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Schema {
+    List,
+    Config,
+}
+
+impl Schema {
+    fn migrations(self) -> &'static [Migration] {
+        match self {
+            Schema::List => LIST_MIGRATIONS,
+            Schema::Config => CONFIG_MIGRATIONS,
+        }
+    }
+
+    /// The version files of this kind are written with.
+    pub fn version(self) -> u64 {
+        self.migrations().len() as u64
+    }
+}
+
+pub fn version_of(value: &Value) -> u64 {
+    value.get("version").and_then(Value::as_u64).unwrap_or(0)
+}
+
+/// Brings `value` up to date, returning the version it was at if it had to
+/// change.
+pub fn upgrade(
+    schema: Schema,
+    path: &Path,
+    value: &mut Value,
+) -> ::std::result::Result<Option<u64>, TooNew> {
+    let version = version_of(value);
+    if version > schema.version() {
+        return Err(TooNew(path.display().to_string(), version));
+    }
+    if version == schema.version() {
+        return Ok(None);
+    }
+
+    for migration in &schema.migrations()[version as usize..] {
+        migration(value);
+    }
+    if let Some(object) = value.as_object_mut() {
+        object.insert("version".into(), schema.version().into());
+    }
+    Ok(Some(version))
+}
+
+/// Copies the file at `path` to `<path>.v<version>.bak`, keeping it from
+/// before it was migrated.
+pub fn backup(path: &Path, version: u64) -> io::Result<PathBuf> {
+    let mut backup = path.as_os_str().to_os_string();
+    backup.push(format!(".v{}.bak", version));
+    let backup = PathBuf::from(backup);
+    fs::copy(path, &backup)?;
+    Ok(backup)
+}
+
+/// Tells the user a file was upgraded on load.
+pub fn report(path: &Path, from: u64, to: u64, backup: &Path) {
+    eprintln!(
+        "upgraded {} from version {} to {}, the old file was kept at {}",
+        path.display(),
+        from,
+        to,
+        backup.display()
+    );
+}
+
+/// Lists gained attributes, which older files leave out.
+fn list_v1(list: &mut Value) {
+    let items = list.get_mut("items").and_then(Value::as_array_mut);
+    for entry in items.into_iter().flatten() {
+        if let Some(item) = entry.get_mut(1).and_then(Value::as_object_mut) {
+            item.entry("attributes")
+                .or_insert_with(|| Value::Object(Map::new()));
+        }
+    }
+}
+
+/// Lists were kept as bare paths before they could have other backends.
+fn config_v1(config: &mut Value) {
+    let lists = config.get_mut("list_paths").and_then(Value::as_object_mut);
+    for list in lists.into_iter().flat_map(|lists| lists.values_mut()) {
+        if list.is_string() {
+            let mut moved = Map::new();
+            moved.insert("path".into(), list.take());
+            moved.insert("backend".into(), "json".into());
+            *list = Value::Object(moved);
+        }
+    }
+}
+
+/// The version of the list at `path` and the version it would be upgraded
+/// to, without changing it. Journals aren't versioned, and encrypted lists
+/// can't be read without their key, so have neither.
+pub fn list_versions(path: &Path, backend: Backend) -> Result<Option<(u64, u64)>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    match backend {
+        Backend::Json => {
+            let contents = fs::read(path).context(ErrorKind::Migrate)?;
+            if crypt::is_sealed(&contents) {
+                return Ok(None);
+            }
+            let value: Value = ::serde_json::from_slice(&contents).context(ErrorKind::Migrate)?;
+            Ok(Some((version_of(&value), Schema::List.version())))
+        }
+        Backend::Sqlite => {
+            let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+                .context(ErrorKind::Migrate)?;
+            let version: i64 = connection
+                .query_row("PRAGMA user_version", [], |row| row.get(0))
+                .context(ErrorKind::Migrate)?;
+            Ok(Some((version as u64, SQLITE_MIGRATIONS.len() as u64)))
+        }
+        Backend::Journal | Backend::Crdt => Ok(None),
+    }
+}
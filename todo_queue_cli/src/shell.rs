@@ -0,0 +1,403 @@
+//! An interactive session over one loaded `App`.
+//!
+//! Every command line works in the shell without `todo_queue` in front of
+//! it. Changes are held in memory and written, and committed to lists
+//! kept in git, on `save` or on leaving the shell, rather than after every
+//! command.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::iter;
+use std::rc::Rc;
+use rustyline::{self, Editor};
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use failure::Fail;
+use app::{self, App, COMMANDS};
+use board;
+use error::*;
+use todo_queue_lib::list::STATUSES;
+
+const PROMPT: &str = "todo_queue> ";
+
+/// Commands which only mean something in the shell.
+const SHELL_COMMANDS: &[&str] = &["save", "exit", "quit"];
+
+/// Commands whose first argument is the name of an attached list.
+const TAKES_LIST: &[&str] = &[
+    "detach", "convert", "git", "encrypt", "decrypt", "rekey", "compact", "auto-archive", "sync",
+    "empty",
+];
+
+/// Runs the shell until it's exited, then saves.
+pub fn run(mut app: App) -> Result<()> {
+    app.hold();
+    let completer = Rc::new(ShellCompleter::new());
+    // Load the default list up front, so its items can be completed
+    if let Err(err) = app.get_list(None) {
+        report(&err);
+    }
+    completer.refresh(&app);
+
+    let mut editor = Editor::new();
+    editor.set_completer(Some(completer.clone()));
+    let history_path = app.get_file_in_config("shell_history");
+    // There's no history to load the first time
+    let _ = editor.load_history(&history_path);
+
+    loop {
+        let line = match editor.readline(PROMPT) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(err) => Err(err).context(ErrorKind::Shell)?,
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        editor.add_history_entry(line);
+
+        match run_line(&mut app, line) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(err) => report(&err),
+        }
+        completer.refresh(&app);
+    }
+
+    editor.save_history(&history_path).context(ErrorKind::Shell)?;
+    app.flush()
+}
+
+/// Splits a line into words the way a POSIX shell does, with quotes and
+/// backslashes, except that a `#` is kept as it is rather than starting a
+/// comment, since it starts a tag. Returns `None` if a quote isn't closed
+/// or the line ends in a backslash.
+fn split(line: &str) -> Option<Vec<String>> {
+    let mut words = vec![];
+    // A word which has started, even if only with "", so it isn't lost
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            words.extend(word.take());
+            continue;
+        }
+        let text = word.get_or_insert_with(String::new);
+        match c {
+            '\'' => loop {
+                match chars.next()? {
+                    '\'' => break,
+                    c => text.push(c),
+                }
+            },
+            '"' => loop {
+                match chars.next()? {
+                    '"' => break,
+                    // Only these can be escaped inside double quotes
+                    '\\' => match chars.next()? {
+                        c @ '"' | c @ '\\' | c @ '$' | c @ '`' => text.push(c),
+                        c => {
+                            text.push('\\');
+                            text.push(c);
+                        }
+                    },
+                    c => text.push(c),
+                }
+            },
+            '\\' => text.push(chars.next()?),
+            c => text.push(c),
+        }
+    }
+    words.extend(word);
+    Some(words)
+}
+
+/// Runs one line typed into the shell. Returns whether to carry on.
+fn run_line(app: &mut App, line: &str) -> Result<bool> {
+    let words = split(line)
+        .ok_or_else(|| UnbalancedQuotes(line.into()))
+        .context(ErrorKind::Shell)?;
+    let first = match words.first() {
+        Some(first) => first.clone(),
+        None => return Ok(true),
+    };
+    match first.as_str() {
+        "exit" | "quit" => return Ok(false),
+        "save" => {
+            app.flush()?;
+            return Ok(true);
+        }
+        "shell" => {
+            println!("already in the shell");
+            return Ok(true);
+        }
+        "board" => {
+            board::run(app)?;
+            return Ok(true);
+        }
+        _ => {}
+    }
+
+    let args = iter::once("todo_queue".to_string()).chain(words);
+    match app::cli().get_matches_from_safe(args) {
+        Ok(matches) => {
+            app.set_command(line);
+            app::run_command(app, &matches)?;
+        }
+        // Covers --help as well as mistakes
+        Err(err) => println!("{}", err.message),
+    }
+    Ok(true)
+}
+
+/// Shows an error the way `main` does, leaving the shell running.
+fn report(err: &Error) {
+    eprintln!("error: {}", err);
+    for cause in err.causes().skip(1) {
+        eprintln!("  caused by: {}", cause);
+    }
+}
+
+/// What's known of the loaded lists, as of the last command.
+#[derive(Default)]
+struct Lists {
+    names: Vec<String>,
+    default: Option<String>,
+    tags: HashMap<String, Vec<String>>,
+    ids: HashMap<String, Vec<String>>,
+}
+
+/// Completes commands, list names, tags, statuses and item ids, depending
+/// on what comes before the word being completed.
+#[derive(Default)]
+struct ShellCompleter {
+    lists: RefCell<Lists>,
+}
+
+impl ShellCompleter {
+    fn new() -> ShellCompleter {
+        ShellCompleter::default()
+    }
+
+    /// Catches up with the lists in `app`. Lists which haven't been loaded
+    /// are only completed by name, so completing never asks for a
+    /// passphrase.
+    fn refresh(&self, app: &App) {
+        let mut lists = self.lists.borrow_mut();
+        lists.names = app.list_names();
+        lists.default = app.default_list().map(String::from);
+        lists.tags.clear();
+        lists.ids.clear();
+        for (name, list) in app.loaded_lists() {
+            let mut tags: Vec<_> = list
+                .items()
+                .iter()
+                .flat_map(|(_, item)| item.tags.iter().map(|tag| format!("#{}", tag)))
+                .collect();
+            tags.sort();
+            tags.dedup();
+            let ids = list.items().iter().map(|&(id, _)| id.to_string()).collect();
+            lists.tags.insert(name.into(), tags);
+            lists.ids.insert(name.into(), ids);
+        }
+    }
+
+    /// The candidates for `word`, given the words before it.
+    fn candidates(&self, before: &[&str], word: &str) -> Vec<String> {
+        let lists = self.lists.borrow();
+        // Tags and ids come from the list given with --list, or else the
+        // default list
+        let list = before
+            .iter()
+            .position(|&arg| arg == "-l" || arg == "--list")
+            .and_then(|at| before.get(at + 1).cloned())
+            .or_else(|| lists.default.as_deref())
+            .unwrap_or_default();
+
+        if word.starts_with('%') {
+            return STATUSES.iter().map(ToString::to_string).collect();
+        }
+        if word.starts_with('#') {
+            return lists.tags.get(list).cloned().unwrap_or_default();
+        }
+        if word.starts_with('@') {
+            return lists.ids.get(list).cloned().unwrap_or_default();
+        }
+        if before.last().is_some_and(|&arg| arg == "-l" || arg == "--list") {
+            return lists.names.clone();
+        }
+
+        // Follow the commands typed so far, past any flags. Once a word
+        // isn't a command, only arguments can come after it.
+        let mut command = &COMMANDS;
+        let mut args = before.iter();
+        while let Some(&arg) = args.next() {
+            if arg == "-l" || arg == "--list" {
+                args.next();
+            } else if arg.starts_with('-') {
+                continue;
+            } else {
+                match command.find(arg) {
+                    Some(subcommand) => command = subcommand,
+                    None => return vec![],
+                }
+            }
+        }
+        if TAKES_LIST.contains(&command.names[0]) {
+            return lists.names.clone();
+        }
+        let mut names: Vec<_> = command
+            .subcommands
+            .iter()
+            .map(|command| command.names[0].to_string())
+            .collect();
+        if before.is_empty() {
+            names.extend(SHELL_COMMANDS.iter().map(|name| name.to_string()));
+        }
+        names
+    }
+}
+
+impl Completer for ShellCompleter {
+    fn complete(&self, line: &str, pos: usize) -> rustyline::Result<(usize, Vec<String>)> {
+        let line = &line[..pos];
+        let start = line.rfind(char::is_whitespace).map_or(0, |at| at + 1);
+        let (before, word) = line.split_at(start);
+        let before: Vec<_> = before.split_whitespace().collect();
+
+        let mut candidates: Vec<_> = self
+            .candidates(&before, word)
+            .into_iter()
+            .filter(|candidate| candidate.starts_with(word))
+            .collect();
+        candidates.sort();
+        candidates.dedup();
+        Ok((start, candidates))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::iter;
+    use std::process;
+    use clap;
+    use app::{self, AppConfig, CommandNames, COMMANDS};
+    use file::Lock;
+    use shell::{run_line, split};
+    use todo_queue_lib::list::{Item, List};
+
+    #[test]
+    fn split_keeps_tags() {
+        assert_eq!(
+            split("todo add deploy #ops").unwrap(),
+            vec!["todo", "add", "deploy", "#ops"]
+        );
+        assert_eq!(split("#ops").unwrap(), vec!["#ops"]);
+        assert_eq!(
+            split(r#"add 'it'\''s' "say \"hi\" \n" a\ b """#).unwrap(),
+            vec!["add", "it's", r#"say "hi" \n"#, "a b", ""]
+        );
+        assert!(split("  ").unwrap().is_empty());
+        assert_eq!(split("say \"hi"), None);
+        assert_eq!(split("trailing \\"), None);
+    }
+
+    #[test]
+    fn lines_with_tags_run() {
+        let dir = env::temp_dir().join(format!("todo_queue-{}-shell", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let lock = Lock::acquire(dir.join("config.json.lock"), false).unwrap();
+        let mut app = AppConfig::load(dir.join("config.json")).unwrap().launch(lock).unwrap();
+        app.hold();
+
+        let attach = format!("list attach work {} -d", dir.join("work.json").display());
+        assert!(run_line(&mut app, &attach).unwrap());
+        assert!(run_line(&mut app, "todo add deploy #ops").unwrap());
+        assert!(run_line(&mut app, "#ops").unwrap());
+        assert!(run_line(&mut app, "   ").unwrap());
+
+        let (_, list) = app.get_list(Some("work".into())).unwrap();
+        let deploy = list.items()[0].0;
+        assert!(list.get(&deploy).unwrap().has_tag("ops"));
+        drop(app);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn adds_undo_and_redo() {
+        let dir = env::temp_dir().join(format!("todo_queue-{}-undo", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let lock = Lock::acquire(dir.join("config.json.lock"), false).unwrap();
+        let mut app = AppConfig::load(dir.join("config.json")).unwrap().launch(lock).unwrap();
+
+        let attach = format!("list attach work {} -d", dir.join("work.json").display());
+        assert!(run_line(&mut app, &attach).unwrap());
+        assert!(run_line(&mut app, "todo add deploy #ops").unwrap());
+        let (_, list) = app.get_list(Some("work".into())).unwrap();
+        let added = list.items().to_vec();
+        assert_eq!(added.len(), 1);
+
+        assert!(run_line(&mut app, "undo --yes").unwrap());
+        let (_, list) = app.get_list(Some("work".into())).unwrap();
+        assert!(list.items().is_empty());
+
+        assert!(run_line(&mut app, "redo --yes").unwrap());
+        let (_, list) = app.get_list(Some("work".into())).unwrap();
+        assert_eq!(list.items(), &added[..]);
+        drop(app);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// The subcommands `cli()` lists in the help of `path`, if it lists
+    /// them. `list` has a help line of its own instead.
+    fn subcommands_in_help(path: &[&str]) -> Option<Vec<String>> {
+        let args = iter::once("todo_queue")
+            .chain(path.iter().cloned())
+            .chain(iter::once("--help"));
+        let help = app::cli().get_matches_from_safe(args).unwrap_err().message;
+        if !help.contains("SUBCOMMANDS:") {
+            return None;
+        }
+        let mut names: Vec<_> = help
+            .lines()
+            .skip_while(|line| !line.starts_with("SUBCOMMANDS:"))
+            .filter(|line| line.starts_with("    ") && !line[4..].starts_with(' '))
+            .filter_map(|line| line.split_whitespace().next())
+            .filter(|&name| name != "help")
+            .map(String::from)
+            .collect();
+        names.sort();
+        Some(names)
+    }
+
+    fn check(command: &CommandNames, path: &[&str]) {
+        if let Some(in_help) = subcommands_in_help(path) {
+            let mut names: Vec<_> = command.subcommands.iter().map(|sub| sub.names[0]).collect();
+            names.sort();
+            assert_eq!(names, in_help, "under {:?}", path);
+        }
+
+        for subcommand in command.subcommands {
+            for name in subcommand.names {
+                let args = iter::once("todo_queue")
+                    .chain(path.iter().cloned())
+                    .chain(vec![*name, "--help"]);
+                let err = app::cli().get_matches_from_safe(args).unwrap_err();
+                assert_eq!(err.kind, clap::ErrorKind::HelpDisplayed, "{:?} {}", path, name);
+            }
+            let mut path = path.to_vec();
+            path.push(subcommand.names[0]);
+            check(subcommand, &path);
+        }
+    }
+
+    #[test]
+    fn commands_match_cli() {
+        check(&COMMANDS, &[]);
+    }
+}
@@ -0,0 +1,1053 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use app_dirs::{self, AppDataType};
+use rand;
+use rusqlite::{self, params, Connection};
+use serde_json;
+use todo_queue_lib::crdt::{CrdtList, State};
+use todo_queue_lib::journal::{self, Event};
+use todo_queue_lib::list::{ItemDesc, ItemId, List, Status, STATUSES};
+use todo_queue_lib::storage::{Change, Storage, StorageError};
+use todo_queue_lib::time::{BadTimestamp, Timestamp, Transition};
+use failure::Fail;
+use app::APP_INFO;
+use crypt::{self, Key, Secret};
+use error::*;
+use file;
+use migrate::{self, Schema, SQLITE_MIGRATIONS};
+
+/// The ways a list can be kept on disk.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// One JSON file, rewritten in full on every save.
+    #[default]
+    Json,
+    /// A SQLite database, which only writes the items that changed.
+    Sqlite,
+    /// A JSON-lines journal of every change made to the list.
+    Journal,
+    /// A directory shared by replicas of the list, which each write their
+    /// own state to it and merge everyone else's.
+    Crdt,
+}
+
+pub const BACKEND_NAMES: &[&str] = &["json", "sqlite", "journal", "crdt"];
+
+impl FromStr for Backend {
+    type Err = UnknownBackend;
+    fn from_str(s: &str) -> ::std::result::Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Backend::Json),
+            "sqlite" => Ok(Backend::Sqlite),
+            "journal" => Ok(Backend::Journal),
+            "crdt" => Ok(Backend::Crdt),
+            _ => Err(UnknownBackend(s.into())),
+        }
+    }
+}
+
+impl Backend {
+    /// Guesses the backend of a list from its file's extension.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Backend {
+        let extension = path.as_ref()
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_lowercase);
+        match extension.as_deref() {
+            Some("db") | Some("sqlite") | Some("sqlite3") => Backend::Sqlite,
+            Some("jsonl") => Backend::Journal,
+            Some("crdt") => Backend::Crdt,
+            _ => Backend::Json,
+        }
+    }
+
+    /// The name the backend goes by on the command line and in the
+    /// configuration.
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Json => "json",
+            Backend::Sqlite => "sqlite",
+            Backend::Journal => "journal",
+            Backend::Crdt => "crdt",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Backend::Json => "json",
+            Backend::Sqlite => "db",
+            Backend::Journal => "jsonl",
+            Backend::Crdt => "crdt",
+        }
+    }
+}
+
+/// How an item is written in a JSON list.
+#[derive(Debug, Serialize, Deserialize)]
+struct NativeItem {
+    name: String,
+    description: String,
+    status: Status,
+    tags: Vec<String>,
+    #[serde(default)]
+    attributes: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    created: Option<Timestamp>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    modified: Option<Timestamp>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    transitions: Vec<Transition>,
+}
+
+impl<'a> From<&'a ItemDesc> for NativeItem {
+    fn from(item: &'a ItemDesc) -> Self {
+        Self {
+            name: item.name.clone(),
+            description: item.description.clone(),
+            status: item.status,
+            tags: item.tags.clone(),
+            attributes: item.attributes.clone(),
+            created: item.created,
+            modified: item.modified,
+            transitions: item.transitions.clone(),
+        }
+    }
+}
+
+impl From<NativeItem> for ItemDesc {
+    fn from(item: NativeItem) -> Self {
+        let mut desc = ItemDesc::new(item.name, item.description, item.tags);
+        desc.status = item.status;
+        desc.attributes = item.attributes;
+        desc.created = item.created;
+        desc.modified = item.modified;
+        desc.transitions = item.transitions;
+        desc
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct JsonList {
+    #[serde(default)]
+    version: u64,
+    items: Vec<(ItemId, NativeItem)>,
+    path: PathBuf,
+}
+
+/// Keeps a list in a single JSON file, encrypted if it has a key.
+#[derive(Debug)]
+pub struct JsonStorage {
+    path: PathBuf,
+    key: Option<Box<Key>>,
+}
+
+impl JsonStorage {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            path: path.into(),
+            key: None,
+        }
+    }
+
+    /// Keeps the list encrypted with `key`. A list which isn't encrypted
+    /// yet still loads, and is encrypted when next saved.
+    pub fn sealed<P: Into<PathBuf>>(path: P, key: Key) -> Self {
+        Self {
+            path: path.into(),
+            key: Some(Box::new(key)),
+        }
+    }
+
+    fn read(&self) -> ::std::result::Result<Vec<u8>, StorageError> {
+        let contents = fs::read(&self.path)
+            .map_err(|err| StorageError::with_cause("couldn't open the list", err))?;
+        match self.key {
+            Some(ref key) if crypt::is_sealed(&contents) => key.open(&self.path, &contents)
+                .map_err(|err| StorageError::with_cause("couldn't decrypt the list", err.compat())),
+            None if crypt::is_sealed(&contents) => Err(StorageError::with_cause(
+                "couldn't read the list",
+                Encrypted(self.path.display().to_string()).compat(),
+            )),
+            _ => Ok(contents),
+        }
+    }
+
+    fn write(&self, json: &[u8]) -> ::std::result::Result<(), StorageError> {
+        let contents = match self.key {
+            Some(ref key) => key.seal(json)
+                .map_err(|err| StorageError::with_cause("couldn't encrypt the list", err.compat()))?,
+            None => json.to_vec(),
+        };
+        file::write_atomic(&self.path, &contents)
+            .map_err(|err| StorageError::with_cause("couldn't save the list", err))
+    }
+}
+
+impl Storage for JsonStorage {
+    fn load(&mut self) -> ::std::result::Result<Vec<(ItemId, ItemDesc)>, StorageError> {
+        if !self.path.exists() {
+            self.store(&[], &[])?;
+            return Ok(vec![]);
+        }
+
+        let contents = self.read()?;
+        let mut value: serde_json::Value = serde_json::from_slice(&contents)
+            .map_err(|err| StorageError::with_cause("couldn't read the list", err))?;
+
+        if let Some(version) = migrate::upgrade(Schema::List, &self.path, &mut value)
+            .map_err(|err| StorageError::with_cause("couldn't upgrade the list", err.compat()))?
+        {
+            let backup = migrate::backup(&self.path, version)
+                .map_err(|err| StorageError::with_cause("couldn't back up the list", err))?;
+            let json = serde_json::to_vec_pretty(&value)
+                .map_err(|err| StorageError::with_cause("couldn't upgrade the list", err))?;
+            self.write(&json)?;
+            migrate::report(&self.path, version, Schema::List.version(), &backup);
+        }
+
+        let list: JsonList = serde_json::from_value(value)
+            .map_err(|err| StorageError::with_cause("couldn't read the list", err))?;
+        Ok(list.items
+            .into_iter()
+            .map(|(id, item)| (id, item.into()))
+            .collect())
+    }
+
+    fn store(
+        &mut self,
+        items: &[(ItemId, ItemDesc)],
+        _: &[Change],
+    ) -> ::std::result::Result<(), StorageError> {
+        let list = JsonList {
+            version: Schema::List.version(),
+            items: items
+                .iter()
+                .map(|&(id, ref item)| (id, item.into()))
+                .collect(),
+            path: self.path.clone(),
+        };
+        let json = serde_json::to_vec_pretty(&list)
+            .map_err(|err| StorageError::with_cause("couldn't write the list", err))?;
+        self.write(&json)
+    }
+}
+
+/// The tables every SQLite list starts out with, before any migrations.
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS items (
+        id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL,
+        description TEXT NOT NULL,
+        status TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS tags (
+        item INTEGER NOT NULL REFERENCES items(id) ON DELETE CASCADE,
+        tag TEXT NOT NULL,
+        PRIMARY KEY (item, tag)
+    );
+    CREATE TABLE IF NOT EXISTS attributes (
+        item INTEGER NOT NULL REFERENCES items(id) ON DELETE CASCADE,
+        key TEXT NOT NULL,
+        value TEXT NOT NULL,
+        PRIMARY KEY (item, key)
+    );
+";
+
+fn sqlite_error(message: &str) -> impl Fn(rusqlite::Error) -> StorageError + '_ {
+    move |err| StorageError::with_cause(message, err)
+}
+
+fn status_name(status: Status) -> String {
+    format!("{:?}", status)
+}
+
+fn parse_status(name: &str) -> ::std::result::Result<Status, StorageError> {
+    STATUSES
+        .iter()
+        .find(|status| status_name(**status) == name)
+        .cloned()
+        .ok_or_else(|| StorageError::new(format!("unknown status {}", name)))
+}
+
+fn parse_timestamp(text: &str) -> ::std::result::Result<Timestamp, StorageError> {
+    text.parse()
+        .map_err(|err: BadTimestamp| StorageError::new(err.to_string()))
+}
+
+/// Keeps a list in a SQLite database, writing only the items which changed
+/// on each save.
+#[derive(Debug)]
+pub struct SqliteStorage {
+    connection: Connection,
+}
+
+impl SqliteStorage {
+    pub fn open<P: AsRef<Path>>(path: P) -> ::std::result::Result<Self, StorageError> {
+        let path = path.as_ref();
+        let existed = path.metadata().map(|meta| meta.len() > 0).unwrap_or(false);
+        let mut connection =
+            Connection::open(path).map_err(sqlite_error("couldn't open the database"))?;
+        connection
+            .execute_batch("PRAGMA foreign_keys = ON;")
+            .map_err(sqlite_error("couldn't set up the database"))?;
+
+        let version: i64 = connection
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .map_err(sqlite_error("couldn't read the database version"))?;
+        let latest = SQLITE_MIGRATIONS.len() as i64;
+        if version > latest {
+            let err = TooNew(path.display().to_string(), version as u64);
+            return Err(StorageError::with_cause("couldn't upgrade the database", err.compat()));
+        }
+        if version < latest {
+            let backup = if existed {
+                Some(migrate::backup(path, version as u64)
+                    .map_err(|err| StorageError::with_cause("couldn't back up the database", err))?)
+            } else {
+                None
+            };
+
+            let error = sqlite_error("couldn't upgrade the database");
+            let transaction = connection.transaction().map_err(&error)?;
+            transaction.execute_batch(SCHEMA).map_err(&error)?;
+            for migration in &SQLITE_MIGRATIONS[version as usize..] {
+                transaction.execute_batch(migration).map_err(&error)?;
+            }
+            transaction
+                .execute_batch(&format!("PRAGMA user_version = {};", latest))
+                .map_err(&error)?;
+            transaction.commit().map_err(&error)?;
+
+            if let Some(backup) = backup {
+                migrate::report(path, version as u64, latest as u64, &backup);
+            }
+        }
+
+        Ok(Self { connection })
+    }
+
+    /// Deletes every item in the database.
+    pub fn clear(&mut self) -> ::std::result::Result<(), StorageError> {
+        self.connection
+            .execute("DELETE FROM items", [])
+            .map_err(sqlite_error("couldn't clear the list"))?;
+        Ok(())
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn load(&mut self) -> ::std::result::Result<Vec<(ItemId, ItemDesc)>, StorageError> {
+        let error = sqlite_error("couldn't read the list");
+        let mut items: BTreeMap<u16, ItemDesc> = BTreeMap::new();
+
+        let mut select = self.connection
+            .prepare("SELECT id, name, description, status FROM items
+                 ORDER BY position IS NULL, position, rowid")
+            .map_err(&error)?;
+        let mut order = vec![];
+        let rows = select
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, u16>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                ))
+            })
+            .map_err(&error)?;
+        for row in rows {
+            let (id, name, description, status) = row.map_err(&error)?;
+            let mut item = ItemDesc::new(name, description, Vec::<String>::new());
+            item.status = parse_status(&status)?;
+            order.push(id);
+            items.insert(id, item);
+        }
+
+        let mut select = self.connection
+            .prepare("SELECT item, tag FROM tags ORDER BY item, position, tag")
+            .map_err(&error)?;
+        let rows = select
+            .query_map([], |row| Ok((row.get::<_, u16>(0)?, row.get::<_, String>(1)?)))
+            .map_err(&error)?;
+        for row in rows {
+            let (id, tag) = row.map_err(&error)?;
+            if let Some(item) = items.get_mut(&id) {
+                item.tags.push(tag);
+            }
+        }
+
+        let mut select = self.connection
+            .prepare("SELECT item, key, value FROM attributes")
+            .map_err(&error)?;
+        let rows = select
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, u16>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                ))
+            })
+            .map_err(&error)?;
+        for row in rows {
+            let (id, key, value) = row.map_err(&error)?;
+            if let Some(item) = items.get_mut(&id) {
+                item.attributes.insert(key, value);
+            }
+        }
+
+        let mut select = self.connection
+            .prepare("SELECT item, created, modified FROM timestamps")
+            .map_err(&error)?;
+        let rows = select
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, u16>(0)?,
+                    row.get::<_, Option<String>>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                ))
+            })
+            .map_err(&error)?;
+        for row in rows {
+            let (id, created, modified) = row.map_err(&error)?;
+            if let Some(item) = items.get_mut(&id) {
+                item.created = created.as_deref().map(parse_timestamp).transpose()?;
+                item.modified = modified.as_deref().map(parse_timestamp).transpose()?;
+            }
+        }
+
+        let mut select = self.connection
+            .prepare(
+                "SELECT item, from_status, to_status, at FROM transitions ORDER BY item, seq",
+            )
+            .map_err(&error)?;
+        let rows = select
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, u16>(0)?,
+                    row.get::<_, Option<String>>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                ))
+            })
+            .map_err(&error)?;
+        for row in rows {
+            let (id, from, to, at) = row.map_err(&error)?;
+            if let Some(item) = items.get_mut(&id) {
+                item.transitions.push(Transition {
+                    from: from.as_deref().map(parse_status).transpose()?,
+                    to: parse_status(&to)?,
+                    at: parse_timestamp(&at)?,
+                });
+            }
+        }
+
+        Ok(order
+            .into_iter()
+            .filter_map(|id| items.remove(&id).map(|item| (id.into(), item)))
+            .collect())
+    }
+
+    fn store(
+        &mut self,
+        items: &[(ItemId, ItemDesc)],
+        changes: &[Change],
+    ) -> ::std::result::Result<(), StorageError> {
+        let error = sqlite_error("couldn't save the list");
+        let transaction = self.connection.transaction().map_err(&error)?;
+
+        let mut reordered = false;
+        for change in changes {
+            match *change {
+                Change::Remove(id) => {
+                    transaction
+                        .execute("DELETE FROM items WHERE id = ?1", params![u16::from(id)])
+                        .map_err(&error)?;
+                }
+                Change::Put(id, ref item) => {
+                    let id = u16::from(id);
+                    let updated = transaction
+                        .execute(
+                            "UPDATE items SET name = ?2, description = ?3, status = ?4
+                             WHERE id = ?1",
+                            params![id, item.name, item.description, status_name(item.status)],
+                        )
+                        .map_err(&error)?;
+                    if updated == 0 {
+                        transaction
+                            .execute(
+                                "INSERT INTO items (id, name, description, status)
+                                 VALUES (?1, ?2, ?3, ?4)",
+                                params![id, item.name, item.description, status_name(item.status)],
+                            )
+                            .map_err(&error)?;
+                        reordered = true;
+                    }
+
+                    transaction
+                        .execute("DELETE FROM tags WHERE item = ?1", params![id])
+                        .map_err(&error)?;
+                    for (position, tag) in item.tags.iter().enumerate() {
+                        transaction
+                            .execute(
+                                "INSERT OR IGNORE INTO tags (item, tag, position)
+                                 VALUES (?1, ?2, ?3)",
+                                params![id, tag, position as i64],
+                            )
+                            .map_err(&error)?;
+                    }
+
+                    transaction
+                        .execute("DELETE FROM attributes WHERE item = ?1", params![id])
+                        .map_err(&error)?;
+                    for (key, value) in &item.attributes {
+                        transaction
+                            .execute(
+                                "INSERT INTO attributes (item, key, value) VALUES (?1, ?2, ?3)",
+                                params![id, key, value],
+                            )
+                            .map_err(&error)?;
+                    }
+
+                    transaction
+                        .execute(
+                            "INSERT OR REPLACE INTO timestamps (item, created, modified)
+                             VALUES (?1, ?2, ?3)",
+                            params![
+                                id,
+                                item.created.map(|at| at.to_string()),
+                                item.modified.map(|at| at.to_string())
+                            ],
+                        )
+                        .map_err(&error)?;
+                    transaction
+                        .execute("DELETE FROM transitions WHERE item = ?1", params![id])
+                        .map_err(&error)?;
+                    for (seq, transition) in item.transitions.iter().enumerate() {
+                        transaction
+                            .execute(
+                                "INSERT INTO transitions (item, seq, from_status, to_status, at)
+                                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                                params![
+                                    id,
+                                    seq as i64,
+                                    transition.from.map(status_name),
+                                    status_name(transition.to),
+                                    transition.at.to_string()
+                                ],
+                            )
+                            .map_err(&error)?;
+                    }
+                }
+                Change::Move(..) => reordered = true,
+            }
+        }
+
+        // Positions are written for every item whenever one is added or
+        // moved, since one move can shift all the others
+        if reordered {
+            for (position, &(id, _)) in items.iter().enumerate() {
+                transaction
+                    .execute(
+                        "UPDATE items SET position = ?2 WHERE id = ?1",
+                        params![u16::from(id), position as i64],
+                    )
+                    .map_err(&error)?;
+            }
+        }
+
+        transaction.commit().map_err(&error)
+    }
+}
+
+/// How many events are written to a journal between snapshots.
+const SNAPSHOT_EVERY: usize = 100;
+
+/// Keeps a list as a journal of events, appending the changes made on each
+/// save. Nothing already written is ever rewritten, except by compacting.
+#[derive(Debug)]
+pub struct JournalStorage {
+    path: PathBuf,
+    /// The list as the journal has it, to tell what each change did.
+    items: Vec<(ItemId, ItemDesc)>,
+    since_snapshot: usize,
+}
+
+impl JournalStorage {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            path: path.into(),
+            items: vec![],
+            since_snapshot: 0,
+        }
+    }
+
+    fn append(&self, text: &str) -> ::std::result::Result<(), StorageError> {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| {
+                file.write_all(text.as_bytes())?;
+                file.sync_data()
+            })
+            .map_err(|err| StorageError::with_cause("couldn't write to the journal", err))
+    }
+
+    /// Empties the journal, if there is one.
+    pub fn clear(&mut self) -> ::std::result::Result<(), StorageError> {
+        if self.path.exists() {
+            file::write_atomic(&self.path, b"")
+                .map_err(|err| StorageError::with_cause("couldn't clear the journal", err))?;
+        }
+        self.items.clear();
+        self.since_snapshot = 0;
+        Ok(())
+    }
+
+    /// Replaces the whole journal with a snapshot of the list.
+    pub fn compact(&mut self) -> ::std::result::Result<(), StorageError> {
+        let snapshot = Event::Snapshot {
+            items: self.items.clone(),
+        };
+        file::write_atomic(&self.path, snapshot.to_line().as_bytes())
+            .map_err(|err| StorageError::with_cause("couldn't compact the journal", err))?;
+        self.since_snapshot = 0;
+        Ok(())
+    }
+}
+
+impl Storage for JournalStorage {
+    fn load(&mut self) -> ::std::result::Result<Vec<(ItemId, ItemDesc)>, StorageError> {
+        if !self.path.exists() {
+            self.append("")?;
+            return Ok(vec![]);
+        }
+
+        let mut text = String::new();
+        File::open(&self.path)
+            .and_then(|mut file| file.read_to_string(&mut text))
+            .map_err(|err| StorageError::with_cause("couldn't read the journal", err))?;
+        let replayed = journal::replay(&text)
+            .map_err(|err| StorageError::with_cause("couldn't replay the journal", err))?;
+
+        // Cut off whatever a crash left half written, so the next event
+        // starts on a line of its own.
+        if replayed.valid_len < text.len() {
+            OpenOptions::new()
+                .write(true)
+                .open(&self.path)
+                .and_then(|file| file.set_len(replayed.valid_len as u64))
+                .map_err(|err| StorageError::with_cause("couldn't repair the journal", err))?;
+        }
+        if !text[..replayed.valid_len].is_empty() && !text[..replayed.valid_len].ends_with('\n') {
+            self.append("\n")?;
+        }
+
+        self.items = replayed.items.clone();
+        self.since_snapshot = replayed.since_snapshot;
+        Ok(replayed.items)
+    }
+
+    fn store(
+        &mut self,
+        items: &[(ItemId, ItemDesc)],
+        changes: &[Change],
+    ) -> ::std::result::Result<(), StorageError> {
+        let mut text = String::new();
+        for change in changes {
+            let events = match *change {
+                Change::Remove(id) => vec![Event::Remove { id }],
+                Change::Put(id, ref item) => {
+                    let old = self.items.iter().find(|me| me.0 == id).map(|me| &me.1);
+                    journal::diff(id, old, item)
+                }
+                Change::Move(id, after) => vec![Event::Move { id, after }],
+            };
+            for event in events {
+                text += &event.to_line();
+                event.apply(&mut self.items);
+                self.since_snapshot += 1;
+            }
+        }
+
+        if self.since_snapshot >= SNAPSHOT_EVERY {
+            text += &Event::Snapshot {
+                items: items.to_vec(),
+            }.to_line();
+            self.items = items.to_vec();
+            self.since_snapshot = 0;
+        }
+        self.append(&text)
+    }
+}
+
+/// The name this machine goes by among the replicas of a shared list:
+/// `TODO_QUEUE_REPLICA` if it's set, or else a random name made up once and
+/// kept in the configuration directory.
+fn replica_name() -> io::Result<String> {
+    if let Ok(replica) = env::var("TODO_QUEUE_REPLICA") {
+        return Ok(replica);
+    }
+    let dir = app_dirs::get_app_root(AppDataType::UserConfig, &APP_INFO)
+        .map_err(io::Error::other)?;
+    let path = dir.join("replica");
+    if let Ok(replica) = fs::read_to_string(&path) {
+        if !replica.trim().is_empty() {
+            return Ok(replica.trim().into());
+        }
+    }
+    let replica = format!("{:016x}", rand::random::<u64>());
+    fs::create_dir_all(&dir)?;
+    file::write_atomic(&path, replica.as_bytes())?;
+    Ok(replica)
+}
+
+/// Shares a list between replicas through a directory. Each replica only
+/// ever writes its own state, to `<replica>.json`, and merges in all the
+/// others when loading, so the directory can be synced by anything which
+/// copies files.
+#[derive(Debug)]
+pub struct CrdtStorage {
+    dir: PathBuf,
+    list: CrdtList,
+}
+
+impl CrdtStorage {
+    pub fn open<P: Into<PathBuf>>(dir: P) -> ::std::result::Result<Self, StorageError> {
+        let replica = replica_name()
+            .map_err(|err| StorageError::with_cause("couldn't name this replica", err))?;
+        Ok(Self {
+            dir: dir.into(),
+            list: CrdtList::new(replica),
+        })
+    }
+
+    /// Removes the state of every replica from the directory, if there is
+    /// one.
+    pub fn clear(&mut self) -> ::std::result::Result<(), StorageError> {
+        let error = |err| StorageError::with_cause("couldn't clear the replica directory", err);
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(error(err)),
+        };
+        for entry in entries {
+            let path = entry.map_err(error)?.path();
+            if path.extension().and_then(|extension| extension.to_str()) == Some("json") {
+                fs::remove_file(&path).map_err(error)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Storage for CrdtStorage {
+    fn load(&mut self) -> ::std::result::Result<Vec<(ItemId, ItemDesc)>, StorageError> {
+        fs::create_dir_all(&self.dir)
+            .map_err(|err| StorageError::with_cause("couldn't create the replica directory", err))?;
+        let entries = fs::read_dir(&self.dir)
+            .map_err(|err| StorageError::with_cause("couldn't read the replica directory", err))?;
+        for entry in entries {
+            let path = entry
+                .map_err(|err| StorageError::with_cause("couldn't read the replica directory", err))?
+                .path();
+            if path.extension().and_then(|extension| extension.to_str()) != Some("json") {
+                continue;
+            }
+            let state: State = File::open(&path)
+                .map_err(|err| StorageError::with_cause("couldn't open a replica", err))
+                .and_then(|file| {
+                    serde_json::from_reader(file)
+                        .map_err(|err| StorageError::with_cause("couldn't read a replica", err))
+                })?;
+            self.list.merge(&state);
+        }
+        Ok(self.list.items().to_vec())
+    }
+
+    fn store(
+        &mut self,
+        _items: &[(ItemId, ItemDesc)],
+        changes: &[Change],
+    ) -> ::std::result::Result<(), StorageError> {
+        for change in changes {
+            match *change {
+                Change::Put(id, ref item) => self.list.insert(id, item.clone()),
+                Change::Remove(id) => self.list.remove(&id),
+                Change::Move(id, after) => self.list.move_after(&id, after.as_ref()),
+            }
+        }
+
+        let json = serde_json::to_vec(self.list.state())
+            .map_err(|err| StorageError::with_cause("couldn't write this replica", err))?;
+        fs::create_dir_all(&self.dir)
+            .map_err(|err| StorageError::with_cause("couldn't create the replica directory", err))?;
+        let path = self.dir.join(format!("{}.json", self.list.replica()));
+        file::write_atomic(&path, &json)
+            .map_err(|err| StorageError::with_cause("couldn't write this replica", err))
+    }
+}
+
+/// The storage of a list, whichever backend it uses.
+#[derive(Debug)]
+pub enum Store {
+    Json(JsonStorage),
+    Sqlite(SqliteStorage),
+    Journal(JournalStorage),
+    Crdt(CrdtStorage),
+}
+
+impl Store {
+    /// Opens the storage of the list at `path`. Given a `secret`, the list
+    /// is kept encrypted, and it has to be the one the list was encrypted
+    /// with.
+    pub fn open<P: AsRef<Path>>(backend: Backend, path: P, secret: Option<&Secret>) -> Result<Store> {
+        let key = match secret {
+            Some(secret) => Some(Key::for_file(secret, path.as_ref())?),
+            None => None,
+        };
+        Store::with_key(backend, path, key)
+    }
+
+    /// Opens the storage for a new list at `path`, emptying any list
+    /// already there, encrypted afresh if there is a `secret`.
+    pub fn create<P: AsRef<Path>>(backend: Backend, path: P, secret: Option<&Secret>) -> Result<Store> {
+        let key = match secret {
+            Some(secret) => Some(Key::new(secret)?),
+            None => None,
+        };
+        let mut store = Store::with_key(backend, path, key)?;
+        match store {
+            // A JSON list is rewritten in full when it's saved anyway
+            Store::Json(_) => {}
+            Store::Sqlite(ref mut storage) => storage.clear().context(ErrorKind::SaveList)?,
+            Store::Journal(ref mut storage) => storage.clear().context(ErrorKind::SaveList)?,
+            Store::Crdt(ref mut storage) => storage.clear().context(ErrorKind::SaveList)?,
+        }
+        Ok(store)
+    }
+
+    fn with_key<P: AsRef<Path>>(backend: Backend, path: P, key: Option<Key>) -> Result<Store> {
+        if key.is_some() && backend != Backend::Json {
+            let path = path.as_ref().display().to_string();
+            Err(CantEncrypt(path, backend.name().into())).context(ErrorKind::Encryption)?;
+        }
+        Ok(match backend {
+            Backend::Json => match key {
+                Some(key) => Store::Json(JsonStorage::sealed(path.as_ref(), key)),
+                None => Store::Json(JsonStorage::new(path.as_ref())),
+            },
+            Backend::Sqlite => {
+                Store::Sqlite(SqliteStorage::open(path).context(ErrorKind::LoadList)?)
+            }
+            Backend::Journal => Store::Journal(JournalStorage::new(path.as_ref())),
+            Backend::Crdt => {
+                Store::Crdt(CrdtStorage::open(path.as_ref()).context(ErrorKind::LoadList)?)
+            }
+        })
+    }
+}
+
+impl Storage for Store {
+    fn load(&mut self) -> ::std::result::Result<Vec<(ItemId, ItemDesc)>, StorageError> {
+        match *self {
+            Store::Json(ref mut storage) => storage.load(),
+            Store::Sqlite(ref mut storage) => storage.load(),
+            Store::Journal(ref mut storage) => storage.load(),
+            Store::Crdt(ref mut storage) => storage.load(),
+        }
+    }
+
+    fn store(
+        &mut self,
+        items: &[(ItemId, ItemDesc)],
+        changes: &[Change],
+    ) -> ::std::result::Result<(), StorageError> {
+        match *self {
+            Store::Json(ref mut storage) => storage.store(items, changes),
+            Store::Sqlite(ref mut storage) => storage.store(items, changes),
+            Store::Journal(ref mut storage) => storage.store(items, changes),
+            Store::Crdt(ref mut storage) => storage.store(items, changes),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process;
+    use rusqlite::{params, Connection};
+    use todo_queue_lib::list::{ItemDesc, ItemId, List};
+    use todo_queue_lib::storage::{Storage, StoredList};
+    use migrate::SQLITE_MIGRATIONS;
+    use storage::{Backend, JournalStorage, SqliteStorage, Store, SCHEMA};
+
+    /// An empty directory for the test `name` to work in.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("todo_queue-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn item(text: &str) -> ItemDesc {
+        text.parse().unwrap()
+    }
+
+    fn items(texts: &[&str]) -> Vec<(ItemId, ItemDesc)> {
+        texts
+            .iter()
+            .enumerate()
+            .map(|(idx, text)| (ItemId::from(idx as u16 + 1), item(text)))
+            .collect()
+    }
+
+    #[test]
+    fn sqlite_saves_changes_in_place() {
+        let dir = temp_dir("sqlite");
+        let path = dir.join("work.db");
+        let mut list = StoredList::open(SqliteStorage::open(&path).unwrap()).unwrap();
+        let deploy = list.add(item("deploy #zeta #alpha"));
+        let test = list.add(item("test"));
+        let docs = list.add(item("docs"));
+        list.save().unwrap();
+
+        list.get_mut(&test).unwrap().name = "test it all".into();
+        list.remove(&deploy);
+        list.move_after(&docs, None);
+        list.add(item("release #ops"));
+        list.save().unwrap();
+
+        let saved = list.items().to_vec();
+        assert_eq!(saved[0].0, docs);
+        assert_eq!(saved[1].1.name, "test it all");
+        let mut reopened = SqliteStorage::open(&path).unwrap();
+        assert_eq!(reopened.load().unwrap(), saved);
+
+        list.move_after(&test, Some(&docs));
+        list.get_mut(&docs).unwrap().tags = vec!["zeta".into(), "alpha".into()];
+        list.save().unwrap();
+        let reloaded = SqliteStorage::open(&path).unwrap().load().unwrap();
+        assert_eq!(reloaded, list.items());
+        assert_eq!(reloaded[0].1.tags, vec!["zeta", "alpha"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn sqlite_upgrades_from_the_first_schema() {
+        let dir = temp_dir("sqlite-upgrade");
+        let path = dir.join("old.db");
+        {
+            let connection = Connection::open(&path).unwrap();
+            connection.execute_batch(SCHEMA).unwrap();
+            connection
+                .execute(
+                    "INSERT INTO items (id, name, description, status) VALUES (?1, ?2, '', 'Working')",
+                    params![7, "deploy"],
+                )
+                .unwrap();
+            for tag in &["ops", "infra"] {
+                connection
+                    .execute("INSERT INTO tags (item, tag) VALUES (7, ?1)", params![tag])
+                    .unwrap();
+            }
+        }
+
+        let mut storage = SqliteStorage::open(&path).unwrap();
+        let version: i64 = storage
+            .connection
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, SQLITE_MIGRATIONS.len() as i64);
+
+        let loaded = storage.load().unwrap();
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded[0].0, ItemId::from(7));
+        assert_eq!(loaded[0].1.name, "deploy");
+        assert_eq!(loaded[0].1.tags, vec!["infra", "ops"]);
+        assert!(loaded[0].1.transitions.is_empty());
+
+        // The upgraded tables take timestamps and positions
+        let mut list = StoredList::open(storage).unwrap();
+        list.add(item("test"));
+        list.save().unwrap();
+        assert_eq!(SqliteStorage::open(&path).unwrap().load().unwrap(), list.items());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn journal_replays_and_compacts() {
+        let dir = temp_dir("journal");
+        let path = dir.join("work.jsonl");
+        let mut list = StoredList::open(JournalStorage::new(&path)).unwrap();
+        let deploy = list.add(item("deploy #ops"));
+        let test = list.add(item("test"));
+        let docs = list.add(item("docs"));
+        list.save().unwrap();
+        list.get_mut(&deploy).unwrap().description = "to production".into();
+        list.remove(&test);
+        list.move_after(&docs, None);
+        list.save().unwrap();
+
+        let saved = list.items().to_vec();
+        assert_eq!(JournalStorage::new(&path).load().unwrap(), saved);
+
+        // A line torn by a crash is cut off, and the journal carries on
+        let length = fs::metadata(&path).unwrap().len();
+        let mut text = fs::read_to_string(&path).unwrap();
+        text += "{\"Put\": {\"id\": 9";
+        fs::write(&path, text).unwrap();
+        let mut list = StoredList::open(JournalStorage::new(&path)).unwrap();
+        assert_eq!(list.items(), &saved[..]);
+        assert_eq!(fs::metadata(&path).unwrap().len(), length);
+
+        list.add(item("release"));
+        list.save().unwrap();
+        list.storage_mut().compact().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 1);
+        assert_eq!(JournalStorage::new(&path).load().unwrap(), list.items());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn create_replaces_existing_lists() {
+        let dir = temp_dir("create");
+        env::set_var("TODO_QUEUE_REPLICA", "test");
+        for &backend in &[Backend::Json, Backend::Sqlite, Backend::Journal, Backend::Crdt] {
+            let path = dir.join(format!("work.{}", backend.extension()));
+            let old = items(&["deploy #ops", "test"]);
+            let store = Store::create(backend, &path, None).unwrap();
+            StoredList::with_items(old, store).save().unwrap();
+
+            let new = items(&["write docs #docs"]);
+            let store = Store::create(backend, &path, None).unwrap();
+            StoredList::with_items(new.clone(), store).save().unwrap();
+
+            let mut store = Store::open(backend, &path, None).unwrap();
+            assert_eq!(store.load().unwrap(), new, "{}", backend.name());
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn tags_keep_their_order() {
+        let dir = temp_dir("tags");
+        for &backend in &[Backend::Json, Backend::Sqlite] {
+            let path = dir.join(format!("work.{}", backend.extension()));
+            let tagged = items(&["deploy #zeta #alpha #ops"]);
+            let store = Store::create(backend, &path, None).unwrap();
+            StoredList::with_items(tagged.clone(), store).save().unwrap();
+
+            let mut store = Store::open(backend, &path, None).unwrap();
+            assert_eq!(store.load().unwrap(), tagged, "{}", backend.name());
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,229 @@
+//! Just enough of the terminal for the board: raw mode on the alternate
+//! screen, the size of the window, and reading one key at a time.
+
+use std::io::{self, Write};
+use std::mem;
+use libc;
+
+/// A key read from the terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Char(char),
+    Up,
+    Down,
+    Left,
+    Right,
+    Enter,
+    Backspace,
+    Escape,
+    /// Ctrl-C, which doesn't send a signal in raw mode.
+    Interrupt,
+    /// Anything else, such as function keys.
+    Unknown,
+}
+
+/// Whether both ends of the program are a terminal.
+pub fn is_terminal() -> bool {
+    unsafe { libc::isatty(libc::STDIN_FILENO) == 1 && libc::isatty(libc::STDOUT_FILENO) == 1 }
+}
+
+/// The terminal in raw mode, showing the alternate screen. It's put back
+/// the way it was when dropped.
+pub struct Terminal {
+    original: libc::termios,
+    raw: bool,
+}
+
+impl Terminal {
+    pub fn open() -> io::Result<Terminal> {
+        let mut original: libc::termios = unsafe { mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut terminal = Terminal {
+            original,
+            raw: false,
+        };
+        terminal.enter()?;
+        Ok(terminal)
+    }
+
+    fn enter(&mut self) -> io::Result<()> {
+        let mut raw = self.original;
+        unsafe { libc::cfmakeraw(&mut raw) };
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &raw) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        self.raw = true;
+        self.write("\x1b[?1049h\x1b[?25l")
+    }
+
+    fn leave(&mut self) -> io::Result<()> {
+        if !self.raw {
+            return Ok(());
+        }
+        self.raw = false;
+        self.write("\x1b[?25h\x1b[?1049l")?;
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &self.original) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Runs `f` with the terminal as it was before, so that it can ask for
+    /// a passphrase or print messages.
+    pub fn cooked<T, F: FnOnce() -> T>(&mut self, f: F) -> io::Result<T> {
+        self.leave()?;
+        let result = f();
+        self.enter()?;
+        Ok(result)
+    }
+
+    /// The width and height of the window, in characters.
+    pub fn size(&self) -> (usize, usize) {
+        let mut size: libc::winsize = unsafe { mem::zeroed() };
+        let found = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0;
+        if found && size.ws_col > 0 && size.ws_row > 0 {
+            (usize::from(size.ws_col), usize::from(size.ws_row))
+        } else {
+            (80, 24)
+        }
+    }
+
+    pub fn write(&self, text: &str) -> io::Result<()> {
+        let mut stdout = io::stdout();
+        stdout.write_all(text.as_bytes())?;
+        stdout.flush()
+    }
+
+    /// Waits for the next key.
+    pub fn read_key(&self) -> io::Result<Key> {
+        decode_key(read_byte, || ready(50))
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = self.leave();
+    }
+}
+
+/// Reads one key, a byte at a time from `read`. `waiting` tells whether
+/// more input follows an escape straight away.
+fn decode_key<R, W>(mut read: R, mut waiting: W) -> io::Result<Key>
+where
+    R: FnMut() -> io::Result<u8>,
+    W: FnMut() -> bool,
+{
+    let first = read()?;
+    let key = match first {
+        3 => Key::Interrupt,
+        b'\r' | b'\n' => Key::Enter,
+        8 | 127 => Key::Backspace,
+        // A lone escape is the key itself, anything straight after it
+        // is the rest of a sequence
+        27 if !waiting() => Key::Escape,
+        27 => match read()? {
+            b'[' | b'O' => {
+                let mut last = read()?;
+                while !(0x40..=0x7e).contains(&last) {
+                    last = read()?;
+                }
+                match last {
+                    b'A' => Key::Up,
+                    b'B' => Key::Down,
+                    b'C' => Key::Right,
+                    b'D' => Key::Left,
+                    _ => Key::Unknown,
+                }
+            }
+            _ => Key::Unknown,
+        },
+        _ => {
+            let len = match first {
+                0x00..=0x7f => 1,
+                0xc0..=0xdf => 2,
+                0xe0..=0xef => 3,
+                _ => 4,
+            };
+            let mut bytes = vec![first];
+            for _ in 1..len {
+                bytes.push(read()?);
+            }
+            match String::from_utf8(bytes) {
+                Ok(text) => text.chars().next().map_or(Key::Unknown, Key::Char),
+                Err(_) => Key::Unknown,
+            }
+        }
+    };
+    Ok(key)
+}
+
+fn read_byte() -> io::Result<u8> {
+    let mut byte = 0u8;
+    loop {
+        let read = unsafe { libc::read(libc::STDIN_FILENO, &mut byte as *mut u8 as *mut libc::c_void, 1) };
+        match read {
+            1 => return Ok(byte),
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            _ => {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+        }
+    }
+}
+
+/// Whether there's input waiting, or arrives within `millis`.
+fn ready(millis: i32) -> bool {
+    let mut fd = libc::pollfd {
+        fd: libc::STDIN_FILENO,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    unsafe { libc::poll(&mut fd, 1, millis) > 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::io;
+    use term::{decode_key, Key};
+
+    /// The key `input` decodes to, and how many bytes of it were read.
+    fn decode(input: &[u8]) -> (Key, usize) {
+        let at = Cell::new(0);
+        let read = || {
+            let byte = input.get(at.get()).cloned();
+            at.set(at.get() + 1);
+            byte.ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))
+        };
+        let key = decode_key(read, || at.get() < input.len()).unwrap();
+        (key, at.get())
+    }
+
+    #[test]
+    fn keys_decode() {
+        assert_eq!(decode(b"q"), (Key::Char('q'), 1));
+        assert_eq!(decode("é".as_bytes()), (Key::Char('é'), 2));
+        assert_eq!(decode(b"\r"), (Key::Enter, 1));
+        assert_eq!(decode(b"\x7f"), (Key::Backspace, 1));
+        assert_eq!(decode(b"\x03"), (Key::Interrupt, 1));
+        assert_eq!(decode(&[0xc3, b'(']), (Key::Unknown, 2));
+    }
+
+    #[test]
+    fn escape_sequences_decode() {
+        assert_eq!(decode(b"\x1b"), (Key::Escape, 1));
+        assert_eq!(decode(b"\x1b[A"), (Key::Up, 3));
+        assert_eq!(decode(b"\x1b[B"), (Key::Down, 3));
+        assert_eq!(decode(b"\x1bOC"), (Key::Right, 3));
+        // Modifiers come as parameters before the final byte
+        assert_eq!(decode(b"\x1b[1;5Dq"), (Key::Left, 6));
+        // The whole of a sequence is read, even when it isn't known
+        assert_eq!(decode(b"\x1b[15~q"), (Key::Unknown, 5));
+        assert_eq!(decode(b"\x1bx"), (Key::Unknown, 2));
+    }
+}
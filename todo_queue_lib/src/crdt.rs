@@ -0,0 +1,621 @@
+//! A list which replicas can edit on their own and still come to agree on.
+//!
+//! Every replica keeps a `State` of the whole list. Any two states can be
+//! merged, in any order and as often as needed, and replicas which have
+//! merged the same states end up with the same list. Names, descriptions,
+//! statuses and attributes are last-writer-wins registers, tags are an
+//! observed-remove set, where adding a tag beats removing it at the same
+//! time, and the order of the queue is a replicated growable array.
+//!
+//! Items are told apart by when and where they were added rather than by
+//! their ids, since two replicas can pick the same id for different items.
+//! When they do, the item added first keeps the id, and the other takes the
+//! next one no item was added with.
+
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BTreeSet};
+use std::iter;
+
+use rand;
+
+use list::{Item, ItemDesc, ItemId, List, Status};
+use query::Filter;
+use time::{Timestamp, Transition};
+
+/// When and where an edit was made: a Lamport clock, with the replica
+/// breaking ties. Later stamps win.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Stamp {
+    pub counter: u64,
+    pub replica: String,
+}
+
+/// A value which takes whichever write was stamped last.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lww<T> {
+    pub value: T,
+    pub stamp: Stamp,
+}
+
+impl<T: Clone + PartialEq> Lww<T> {
+    pub fn new(value: T, stamp: Stamp) -> Self {
+        Self { value, stamp }
+    }
+
+    /// Writes `value`, unless it's what the register holds already.
+    pub fn set(&mut self, value: T, stamp: &Stamp) {
+        if self.value != value {
+            self.value = value;
+            self.stamp = stamp.clone();
+        }
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        if other.stamp > self.stamp {
+            *self = other.clone();
+        }
+    }
+}
+
+/// A set of strings which holds whatever was added and not seen removed
+/// since. Removing only takes out the adds a replica knew about, so an add
+/// made at the same time survives.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OrSet {
+    added: BTreeMap<String, BTreeSet<Stamp>>,
+    removed: BTreeSet<Stamp>,
+}
+
+impl OrSet {
+    pub fn add(&mut self, value: &str, stamp: &Stamp) {
+        self.added
+            .entry(value.into())
+            .or_default()
+            .insert(stamp.clone());
+    }
+
+    pub fn remove(&mut self, value: &str) {
+        if let Some(stamps) = self.added.get(value) {
+            self.removed.extend(stamps.iter().cloned());
+        }
+    }
+
+    /// The add of `value` which is still standing, if any, earliest first.
+    fn live(&self, value: &str) -> Option<&Stamp> {
+        self.added
+            .get(value)
+            .and_then(|stamps| stamps.iter().find(|stamp| !self.removed.contains(stamp)))
+    }
+
+    pub fn contains(&self, value: &str) -> bool {
+        self.live(value).is_some()
+    }
+
+    /// The values in the set, in the order they were added.
+    pub fn values(&self) -> Vec<&str> {
+        let mut values: Vec<_> = self.added
+            .keys()
+            .filter_map(|value| self.live(value).map(|stamp| (stamp, value.as_str())))
+            .collect();
+        values.sort();
+        values.into_iter().map(|(_, value)| value).collect()
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        for (value, stamps) in &other.added {
+            self.added
+                .entry(value.clone())
+                .or_default()
+                .extend(stamps.iter().cloned());
+        }
+        self.removed.extend(other.removed.iter().cloned());
+    }
+}
+
+/// Everything known about one item, including whether it was removed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct ItemState {
+    /// When the item was added, which sets it apart from every other.
+    origin: Stamp,
+    /// The id the item was added with.
+    id: ItemId,
+    name: Lww<String>,
+    description: Lww<String>,
+    status: Lww<Status>,
+    alive: Lww<bool>,
+    tags: OrSet,
+    attributes: BTreeMap<String, Lww<Option<String>>>,
+    created: Option<Timestamp>,
+    modified: Option<Timestamp>,
+    transitions: Vec<Transition>,
+}
+
+impl ItemState {
+    fn new(id: ItemId, item: &ItemDesc, stamp: &Stamp) -> Self {
+        let mut state = Self {
+            origin: stamp.clone(),
+            id,
+            name: Lww::new(item.name.clone(), stamp.clone()),
+            description: Lww::new(item.description.clone(), stamp.clone()),
+            status: Lww::new(item.status, stamp.clone()),
+            alive: Lww::new(true, stamp.clone()),
+            tags: OrSet::default(),
+            attributes: BTreeMap::new(),
+            created: None,
+            modified: None,
+            transitions: vec![],
+        };
+        state.write(item, stamp);
+        state
+    }
+
+    fn desc(&self) -> ItemDesc {
+        let mut item = ItemDesc::new(
+            self.name.value.clone(),
+            self.description.value.clone(),
+            self.tags.values(),
+        );
+        item.status = self.status.value;
+        item.attributes = self.attributes
+            .iter()
+            .filter_map(|(key, value)| value.value.clone().map(|value| (key.clone(), value)))
+            .collect();
+        item.created = self.created;
+        item.modified = self.modified;
+        item.transitions = self.transitions.clone();
+        item
+    }
+
+    /// Makes the item match `item`, stamping whatever that changes.
+    fn write(&mut self, item: &ItemDesc, stamp: &Stamp) {
+        self.name.set(item.name.clone(), stamp);
+        self.description.set(item.description.clone(), stamp);
+        self.status.set(item.status, stamp);
+        self.alive.set(true, stamp);
+
+        for tag in self.tags.values().into_iter().map(String::from).collect::<Vec<_>>() {
+            if !item.has_tag(&tag) {
+                self.tags.remove(&tag);
+            }
+        }
+        for tag in &item.tags {
+            if !self.tags.contains(tag) {
+                self.tags.add(tag, stamp);
+            }
+        }
+
+        for (key, value) in &mut self.attributes {
+            value.set(item.attributes.get(key).cloned(), stamp);
+        }
+        for (key, value) in &item.attributes {
+            self.attributes
+                .entry(key.clone())
+                .or_insert_with(|| Lww::new(None, stamp.clone()))
+                .set(Some(value.clone()), stamp);
+        }
+
+        self.merge_times(&item.created, &item.modified, &item.transitions);
+    }
+
+    /// Takes the earliest creation, the latest modification and every
+    /// transition either side knows of.
+    fn merge_times(
+        &mut self,
+        created: &Option<Timestamp>,
+        modified: &Option<Timestamp>,
+        transitions: &[Transition],
+    ) {
+        self.created = self.created.into_iter().chain(*created).min();
+        self.modified = self.modified.into_iter().chain(*modified).max();
+        for transition in transitions {
+            if !self.transitions.contains(transition) {
+                self.transitions.push(transition.clone());
+            }
+        }
+        self.transitions.sort_by_key(|transition| transition.at);
+    }
+
+    fn merge(&mut self, other: &Self) {
+        self.name.merge(&other.name);
+        self.description.merge(&other.description);
+        self.status.merge(&other.status);
+        self.alive.merge(&other.alive);
+        self.tags.merge(&other.tags);
+        for (key, value) in &other.attributes {
+            match self.attributes.entry(key.clone()) {
+                Entry::Occupied(mut entry) => entry.get_mut().merge(value),
+                Entry::Vacant(entry) => {
+                    entry.insert(value.clone());
+                }
+            }
+        }
+        self.merge_times(&other.created, &other.modified, &other.transitions);
+    }
+}
+
+/// A place in the queue an item was put, just after the place another item
+/// was put, or at the front. Each move of an item adds a new one, and the
+/// latest wins.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Placement {
+    stamp: Stamp,
+    /// The origin of the item put there.
+    item: Stamp,
+    after: Option<Stamp>,
+}
+
+/// A replica's copy of a list, as handed to other replicas to merge.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct State {
+    clock: u64,
+    /// Every item ever added, in the order of their origins.
+    items: Vec<ItemState>,
+    order: Vec<Placement>,
+}
+
+impl State {
+    pub fn merge(&mut self, other: &State) {
+        self.clock = self.clock.max(other.clock);
+        for item in &other.items {
+            match self.find(&item.origin) {
+                Ok(at) => self.items[at].merge(item),
+                Err(at) => self.items.insert(at, item.clone()),
+            }
+        }
+        for placement in &other.order {
+            if self.order.iter().all(|mine| mine.stamp != placement.stamp) {
+                self.order.push(placement.clone());
+            }
+        }
+    }
+
+    /// Where the item added at `origin` is, or would go among the items.
+    fn find(&self, origin: &Stamp) -> Result<usize, usize> {
+        self.items.binary_search_by(|item| item.origin.cmp(origin))
+    }
+
+    fn item_mut(&mut self, origin: &Stamp) -> Option<&mut ItemState> {
+        let at = self.find(origin).ok()?;
+        Some(&mut self.items[at])
+    }
+
+    /// The item each id stands for, removed ones included. Items keep the
+    /// id they were added with unless an item added before them has it, and
+    /// then go by the next id no item was added with, so replicas which
+    /// merged the same states agree on it.
+    fn ids(&self) -> BTreeMap<ItemId, &Stamp> {
+        let added: BTreeSet<ItemId> = self.items.iter().map(|item| item.id).collect();
+        let mut ids = BTreeMap::new();
+        for item in &self.items {
+            let id = if ids.contains_key(&item.id) {
+                iter::successors(Some(item.id.next()), |id| Some(id.next()))
+                    .take(1 << 16)
+                    .find(|id| !added.contains(id) && !ids.contains_key(id))
+                    .expect("every id is in use")
+            } else {
+                item.id
+            };
+            ids.insert(id, &item.origin);
+        }
+        ids
+    }
+
+    /// Where the item added at `origin` was last put in the queue.
+    fn placement(&self, origin: &Stamp) -> Option<&Stamp> {
+        self.order
+            .iter()
+            .filter(|placement| placement.item == *origin)
+            .map(|placement| &placement.stamp)
+            .max()
+    }
+
+    /// The origins of the items which haven't been removed, in queue order.
+    fn ordered(&self) -> Vec<&Stamp> {
+        let mut children: BTreeMap<Option<&Stamp>, Vec<&Placement>> = BTreeMap::new();
+        for placement in &self.order {
+            children
+                .entry(placement.after.as_ref())
+                .or_default()
+                .push(placement);
+        }
+        // Whatever was put in the same place last comes first, so the
+        // children are visited newest first
+        for siblings in children.values_mut() {
+            siblings.sort_by(|a, b| a.stamp.cmp(&b.stamp));
+        }
+
+        let mut ids = vec![];
+        let mut stack: Vec<&Placement> = children.get(&None).cloned().unwrap_or_default();
+        while let Some(placement) = stack.pop() {
+            let alive = self.find(&placement.item).is_ok_and(|at| self.items[at].alive.value);
+            if alive && self.placement(&placement.item) == Some(&placement.stamp) {
+                ids.push(&placement.item);
+            }
+            if let Some(siblings) = children.get(&Some(&placement.stamp)) {
+                stack.extend(siblings);
+            }
+        }
+        ids
+    }
+}
+
+/// A list kept as a `State`, which can be edited like any other list and
+/// merged with the states of other replicas.
+#[derive(Debug)]
+pub struct CrdtList {
+    replica: String,
+    state: State,
+    /// The items as they are now, in queue order.
+    items: Vec<(ItemId, ItemDesc)>,
+    /// The origin of the item each id stands for, removed ones included.
+    origins: BTreeMap<ItemId, Stamp>,
+    /// Items handed out to be modified, which still have to be written to
+    /// the state.
+    touched: BTreeSet<ItemId>,
+}
+
+impl CrdtList {
+    /// An empty list edited by the replica named `replica`, which has to be
+    /// unique among the replicas sharing the list.
+    pub fn new<R: Into<String>>(replica: R) -> Self {
+        Self {
+            replica: replica.into(),
+            state: State::default(),
+            items: vec![],
+            origins: BTreeMap::new(),
+            touched: BTreeSet::new(),
+        }
+    }
+
+    pub fn replica(&self) -> &str {
+        &self.replica
+    }
+
+    /// Takes in the edits made by another replica.
+    pub fn merge(&mut self, other: &State) {
+        self.flush();
+        self.state.merge(other);
+        self.refresh();
+    }
+
+    /// The state of the list, with every edit made so far, for other
+    /// replicas to merge.
+    pub fn state(&mut self) -> &State {
+        self.flush();
+        &self.state
+    }
+
+    pub fn items(&self) -> &[(ItemId, ItemDesc)] {
+        &self.items
+    }
+
+    fn tick(&mut self) -> Stamp {
+        self.state.clock += 1;
+        Stamp {
+            counter: self.state.clock,
+            replica: self.replica.clone(),
+        }
+    }
+
+    /// Writes the items which were handed out to be modified to the state.
+    fn flush(&mut self) {
+        let touched = ::std::mem::take(&mut self.touched);
+        if touched.is_empty() {
+            return;
+        }
+        let stamp = self.tick();
+        let (items, origins, states) = (&self.items, &self.origins, &mut self.state);
+        for id in touched {
+            if let Some((_, item)) = items.iter().find(|me| me.0 == id) {
+                if let Some(state) = origins.get(&id).and_then(|at| states.item_mut(at)) {
+                    state.write(item, &stamp);
+                }
+            }
+        }
+    }
+
+    fn refresh(&mut self) {
+        self.flush();
+        let ids = self.state.ids();
+        let by_origin: BTreeMap<&Stamp, ItemId> =
+            ids.iter().map(|(&id, &origin)| (origin, id)).collect();
+        self.items = self.state
+            .ordered()
+            .into_iter()
+            .map(|origin| {
+                let at = self.state.find(origin).unwrap();
+                (by_origin[origin], self.state.items[at].desc())
+            })
+            .collect();
+        self.origins = ids.into_iter().map(|(id, origin)| (id, origin.clone())).collect();
+    }
+}
+
+impl List for CrdtList {
+    type Item = ItemDesc;
+
+    fn add(&mut self, item: ItemDesc) -> ItemId {
+        let id = loop {
+            let id = rand::random();
+            if !self.origins.contains_key(&id) && self.state.items.iter().all(|item| item.id != id) {
+                break id;
+            }
+        };
+        self.insert(id, item);
+        id
+    }
+
+    /// Writes `item` over whatever `id` held, bringing it back if it was
+    /// removed. Items new to the list go at the end of the queue.
+    fn insert(&mut self, id: ItemId, item: ItemDesc) {
+        self.flush();
+        let stamp = self.tick();
+        let origin = match self.origins.get(&id) {
+            Some(origin) => origin.clone(),
+            None => {
+                let at = self.state.find(&stamp).unwrap_err();
+                self.state.items.insert(at, ItemState::new(id, &item, &stamp));
+                stamp.clone()
+            }
+        };
+        if let Some(state) = self.state.item_mut(&origin) {
+            state.write(&item, &stamp);
+        }
+        if self.state.placement(&origin).is_none() {
+            let after = self.items
+                .last()
+                .and_then(|(last, _)| self.origins.get(last))
+                .and_then(|last| self.state.placement(last).cloned());
+            self.state.order.push(Placement {
+                stamp,
+                item: origin,
+                after,
+            });
+        }
+        self.refresh();
+    }
+
+    fn remove(&mut self, item: &ItemId) {
+        self.touched.remove(item);
+        let stamp = self.tick();
+        let states = &mut self.state;
+        if let Some(state) = self.origins.get(item).and_then(|at| states.item_mut(at)) {
+            state.alive.set(false, &stamp);
+        }
+        self.items.retain(|&(id, _)| id != *item);
+    }
+
+    fn move_after(&mut self, id: &ItemId, after: Option<&ItemId>) {
+        let origin = match self.origins.get(id) {
+            Some(origin) => origin.clone(),
+            None => return,
+        };
+        let after = after
+            .and_then(|after| self.origins.get(after))
+            .and_then(|after| self.state.placement(after).cloned());
+        let stamp = self.tick();
+        self.state.order.push(Placement {
+            stamp,
+            item: origin,
+            after,
+        });
+        self.refresh();
+    }
+
+    fn get(&self, item: &ItemId) -> Option<&ItemDesc> {
+        self.items.iter().find(|me| me.0 == *item).map(|me| &me.1)
+    }
+
+    fn get_mut(&mut self, item: &ItemId) -> Option<&mut ItemDesc> {
+        let found = self.items.iter_mut().find(|me| me.0 == *item)?;
+        self.touched.insert(*item);
+        Some(&mut found.1)
+    }
+
+    fn select(&self, filter: &Filter) -> Vec<ItemId> {
+        self.items
+            .iter()
+            .filter(|&(id, item)| filter.matches(id, item))
+            .map(|me| me.0)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crdt::CrdtList;
+    use list::{Item, ItemId, List, Status};
+    use query::Filter;
+
+    /// Has each replica take in everyone else's edits.
+    fn sync(replicas: &mut [&mut CrdtList]) {
+        let states: Vec<_> = replicas.iter_mut().map(|list| list.state().clone()).collect();
+        for list in replicas.iter_mut() {
+            for state in &states {
+                list.merge(state);
+            }
+        }
+    }
+
+    #[test]
+    fn replicas_converge() {
+        let mut laptop = CrdtList::new("laptop");
+        let mut desktop = CrdtList::new("desktop");
+        let deploy = laptop.add("deploy #ops #urgent".parse().unwrap());
+        let docs = laptop.add("write docs".parse().unwrap());
+        sync(&mut [&mut laptop, &mut desktop]);
+        assert_eq!(laptop.items(), desktop.items());
+
+        laptop.get_mut(&deploy).unwrap().set_name("deploy to prod");
+        laptop.get_mut(&deploy).unwrap().set_tag("urgent", false);
+        desktop.get_mut(&deploy).unwrap().set_status(Status::Completed);
+        desktop.get_mut(&deploy).unwrap().set_tag("release", true);
+        desktop.remove(&docs);
+        let review = laptop.add("review".parse().unwrap());
+        sync(&mut [&mut laptop, &mut desktop]);
+
+        assert_eq!(laptop.items(), desktop.items());
+        let item = laptop.get(&deploy).unwrap();
+        assert_eq!(item.name, "deploy to prod");
+        assert_eq!(item.status, Status::Completed);
+        assert_eq!(item.get_tags(), vec!["ops", "release"]);
+        assert_eq!(laptop.select(&Filter::All), vec![deploy, review]);
+    }
+
+    #[test]
+    fn adding_a_tag_beats_removing_it() {
+        let mut laptop = CrdtList::new("laptop");
+        let mut desktop = CrdtList::new("desktop");
+        let deploy = laptop.add("deploy #ops".parse().unwrap());
+        sync(&mut [&mut laptop, &mut desktop]);
+
+        laptop.get_mut(&deploy).unwrap().set_tag("ops", false);
+        desktop.get_mut(&deploy).unwrap().set_tag("ops", false);
+        desktop.state();
+        desktop.get_mut(&deploy).unwrap().set_tag("ops", true);
+        sync(&mut [&mut laptop, &mut desktop]);
+        assert!(laptop.get(&deploy).unwrap().has_tag("ops"));
+        assert_eq!(laptop.items(), desktop.items());
+    }
+
+    #[test]
+    fn queue_order_converges() {
+        let mut laptop = CrdtList::new("laptop");
+        let mut desktop = CrdtList::new("desktop");
+        let a = laptop.add("a".parse().unwrap());
+        let b = laptop.add("b".parse().unwrap());
+        let c = laptop.add("c".parse().unwrap());
+        sync(&mut [&mut laptop, &mut desktop]);
+
+        laptop.move_after(&c, None);
+        let d = desktop.add("d".parse().unwrap());
+        desktop.move_after(&a, Some(&b));
+        sync(&mut [&mut laptop, &mut desktop]);
+
+        assert_eq!(laptop.select(&Filter::All), vec![c, b, a, d]);
+        assert_eq!(desktop.select(&Filter::All), laptop.select(&Filter::All));
+    }
+
+    #[test]
+    fn items_added_with_one_id_stay_apart() {
+        let mut laptop = CrdtList::new("laptop");
+        let mut desktop = CrdtList::new("desktop");
+        let id = ItemId::from(7);
+        laptop.insert(id, "deploy".parse().unwrap());
+        desktop.insert(id, "write docs".parse().unwrap());
+        sync(&mut [&mut laptop, &mut desktop]);
+
+        // Both were added at the same time, and desktop sorts first
+        assert_eq!(laptop.items(), desktop.items());
+        assert_eq!(laptop.get(&id).unwrap().name, "write docs");
+        let moved = ItemId::from(8);
+        assert_eq!(laptop.get(&moved).unwrap().name, "deploy");
+
+        laptop.get_mut(&moved).unwrap().set_status(Status::Queuing);
+        desktop.remove(&id);
+        sync(&mut [&mut laptop, &mut desktop]);
+        assert_eq!(laptop.items(), desktop.items());
+        assert_eq!(desktop.select(&Filter::All), vec![moved]);
+        assert_eq!(desktop.get(&moved).unwrap().status, Status::Queuing);
+    }
+}
@@ -0,0 +1,380 @@
+//! Conversion between lists and spreadsheets saved as CSV or TSV.
+//!
+//! The first row of a file names its columns, and a `Mapping` says which
+//! part of an item each column fills in. Fields may be quoted with `"`, in
+//! which case they can hold the delimiter, line breaks, and quotes written
+//! twice (`""`).
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use list::{Item, ItemDesc, ItemId, List};
+use script;
+
+/// The part of an item a column holds.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Column {
+    Id,
+    Name,
+    Description,
+    Status,
+    /// Tags, separated by commas or whitespace, with or without a `#`.
+    Tags,
+    Attribute(String),
+    /// A column which is left out of imported items.
+    Ignore,
+}
+
+impl FromStr for Column {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.trim().to_lowercase().as_str() {
+            "id" => Column::Id,
+            "name" => Column::Name,
+            "description" => Column::Description,
+            "status" => Column::Status,
+            "tags" => Column::Tags,
+            "ignore" => Column::Ignore,
+            "" => return Err("a column needs a name".into()),
+            key if key.contains(char::is_whitespace) || key.contains(':') => {
+                return Err(format!("'{}' can't be used as an attribute name", key))
+            }
+            key => Column::Attribute(key.into()),
+        })
+    }
+}
+
+impl Display for Column {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            Column::Id => write!(f, "id"),
+            Column::Name => write!(f, "name"),
+            Column::Description => write!(f, "description"),
+            Column::Status => write!(f, "status"),
+            Column::Tags => write!(f, "tags"),
+            Column::Attribute(ref key) => write!(f, "{}", key),
+            Column::Ignore => write!(f, "ignore"),
+        }
+    }
+}
+
+/// Which column of an imported file fills in which part of an item.
+///
+/// Columns without an explicit mapping are matched to an item field by
+/// their header, ignoring case, and otherwise become attributes named after
+/// the header, with spaces replaced by `_`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Mapping {
+    columns: BTreeMap<String, Column>,
+}
+
+impl Mapping {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Maps the column with the given header to `column`.
+    pub fn map<H: Into<String>>(mut self, header: H, column: Column) -> Self {
+        self.columns.insert(header.into().trim().to_lowercase(), column);
+        self
+    }
+
+    pub fn column(&self, header: &str) -> Column {
+        let header = header.trim().to_lowercase();
+        if let Some(column) = self.columns.get(&header) {
+            return column.clone();
+        }
+        let key = header.split_whitespace().collect::<Vec<_>>().join("_");
+        key.parse().unwrap_or(Column::Ignore)
+    }
+}
+
+/// A problem with one row of an imported file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RowError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl RowError {
+    fn new<M: Into<String>>(line: usize, message: M) -> Self {
+        Self {
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for RowError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for RowError {
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
+
+/// Splits `text` into records, along with the line each starts on.
+fn records(text: &str, delimiter: char) -> Vec<Result<(usize, Vec<String>), RowError>> {
+    let mut records = vec![];
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+
+    while chars.peek().is_some() {
+        let start = line;
+        let mut fields = vec![];
+        let mut field = String::new();
+        let mut quoted = false;
+        let mut error = None;
+
+        loop {
+            let c = match chars.next() {
+                Some(c) => c,
+                None if quoted => {
+                    error = Some(RowError::new(start, "unterminated quote"));
+                    break;
+                }
+                None => break,
+            };
+            if c == '\n' {
+                line += 1;
+            }
+            match c {
+                '"' if quoted && chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' if quoted => quoted = false,
+                '"' if field.trim().is_empty() => {
+                    field.clear();
+                    quoted = true;
+                }
+                c if quoted => field.push(c),
+                c if c == delimiter => fields.push(field.split_off(0)),
+                '\r' if chars.peek() == Some(&'\n') => {}
+                '\n' => break,
+                c => field.push(c),
+            }
+        }
+        fields.push(field);
+
+        match error {
+            Some(error) => records.push(Err(error)),
+            None if fields.iter().all(|field| field.trim().is_empty()) => {}
+            None => records.push(Ok((start, fields))),
+        }
+    }
+    records
+}
+
+fn parse_row(line: usize, columns: &[Column], fields: Vec<String>) -> Result<ItemDesc, RowError> {
+    if fields.len() > columns.len() {
+        return Err(RowError::new(
+            line,
+            format!(
+                "expected {} fields but found {}",
+                columns.len(),
+                fields.len()
+            ),
+        ));
+    }
+
+    let mut item = ItemDesc::new("", "", Vec::<String>::new());
+    for (column, field) in columns.iter().zip(fields) {
+        let field = field.trim();
+        match *column {
+            Column::Id | Column::Ignore => {}
+            Column::Name => item.name = field.into(),
+            Column::Description => item.description = field.into(),
+            Column::Status if field.is_empty() => {}
+            Column::Status => {
+                let status = format!("%{}", field.trim_start_matches('%').to_lowercase());
+                item.status = script::status_parser(&status)
+                    .map_err(|_| RowError::new(line, format!("unknown status '{}'", field)))?;
+            }
+            Column::Tags => {
+                for tag in field.split(|c: char| c == ',' || c.is_whitespace()) {
+                    let tag = tag.trim_start_matches('#');
+                    if !tag.is_empty() && !item.has_tag(tag) {
+                        item.tags.push(tag.into());
+                    }
+                }
+            }
+            Column::Attribute(ref key) if field.is_empty() => {
+                item.attributes.remove(key);
+            }
+            Column::Attribute(ref key) => {
+                item.attributes.insert(key.clone(), field.into());
+            }
+        }
+    }
+
+    if item.name.is_empty() {
+        return Err(RowError::new(line, "an item needs a name"));
+    }
+    Ok(item)
+}
+
+/// The items read from a file, along with the line each row starts on, and
+/// the rows which couldn't be read.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Rows {
+    pub items: Vec<(usize, ItemDesc)>,
+    pub errors: Vec<RowError>,
+}
+
+/// Reads the items in a file with fields separated by `delimiter`.
+///
+/// Only a file without a header row naming a `name` column fails as a
+/// whole. Rows which can't be read are left out and reported in
+/// `Rows::errors`.
+pub fn parse(text: &str, delimiter: char, mapping: &Mapping) -> Result<Rows, RowError> {
+    let mut records = records(text, delimiter).into_iter();
+    let columns: Vec<Column> = match records.next() {
+        Some(header) => header?.1.iter().map(|header| mapping.column(header)).collect(),
+        None => return Err(RowError::new(1, "the file has no header row")),
+    };
+    if !columns.contains(&Column::Name) {
+        return Err(RowError::new(1, "no column is mapped to the item name"));
+    }
+
+    let mut rows = Rows::default();
+    for record in records {
+        match record.and_then(|(line, fields)| {
+            parse_row(line, &columns, fields).map(|item| (line, item))
+        }) {
+            Ok(row) => rows.items.push(row),
+            Err(error) => rows.errors.push(error),
+        }
+    }
+    Ok(rows)
+}
+
+fn quote(field: &str, delimiter: char) -> String {
+    let needs_quotes = field.contains(delimiter) || field.contains('"') || field.contains('\n')
+        || field.contains('\r');
+    if needs_quotes {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.into()
+    }
+}
+
+/// Writes the items of `list` with the given ids as rows of the given
+/// columns, after a header row.
+pub fn export<L>(list: &L, ids: &[ItemId], columns: &[Column], delimiter: char) -> String
+where
+    L: List,
+{
+    let separator = delimiter.to_string();
+    let header: Vec<_> = columns
+        .iter()
+        .map(|column| quote(&column.to_string(), delimiter))
+        .collect();
+    let mut out = header.join(&separator) + "\n";
+
+    for id in ids {
+        let item = match list.get(id) {
+            Some(item) => item,
+            None => continue,
+        };
+        let fields: Vec<_> = columns
+            .iter()
+            .map(|column| {
+                let field = match *column {
+                    Column::Id => id.to_string(),
+                    Column::Name => item.get_name().into(),
+                    Column::Description => item.get_description().into(),
+                    Column::Status => format!("{:?}", item.get_status()),
+                    Column::Tags => item.get_tags().join(","),
+                    Column::Attribute(ref key) => item.get_attribute(key).unwrap_or("").into(),
+                    Column::Ignore => String::new(),
+                };
+                quote(&field, delimiter)
+            })
+            .collect();
+        out.push_str(&fields.join(&separator));
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use csv::{export, parse, Column, Mapping, RowError};
+    use list::{Item, ItemDesc, List, MemoryList, Status};
+    use query::Filter;
+
+    #[test]
+    fn parse_with_mapping() {
+        let text = "Task,Notes,State,Labels,Due Date,Owner\n\
+                    deploy,\"push the build,\n then tag it\",working,\"ops, #release\",2026-10-05,sam\n\
+                    \n\
+                    ,no name,,,,\n\
+                    write docs,,finished,,,\n\
+                    \"say \"\"hi\"\"\",,,docs,,\n\
+                    too,many,fields,,,,,\n";
+        let mapping = Mapping::new()
+            .map("Task", Column::Name)
+            .map("notes", Column::Description)
+            .map("State", Column::Status)
+            .map("Labels", Column::Tags)
+            .map("Owner", Column::Ignore);
+
+        let rows = parse(text, ',', &mapping).unwrap();
+        assert_eq!(rows.items.len(), 2);
+
+        let (line, ref deploy) = rows.items[0];
+        assert_eq!(line, 2);
+        assert_eq!(deploy.name, "deploy");
+        assert_eq!(deploy.description, "push the build,\n then tag it");
+        assert_eq!(deploy.status, Status::Working);
+        assert_eq!(deploy.tags, vec!["ops", "release"]);
+        assert_eq!(deploy.get_attribute("due_date"), Some("2026-10-05"));
+        assert_eq!(deploy.get_attribute("owner"), None);
+
+        assert_eq!((rows.items[1].0, rows.items[1].1.name.as_str()), (7, "say \"hi\""));
+        assert_eq!(
+            rows.errors,
+            vec![
+                RowError::new(5, "an item needs a name"),
+                RowError::new(6, "unknown status 'finished'"),
+                RowError::new(8, "expected 6 fields but found 8"),
+            ]
+        );
+
+        assert_eq!(
+            parse("Task\tDue\n", '\t', &Mapping::new()),
+            Err(RowError::new(1, "no column is mapped to the item name"))
+        );
+    }
+
+    #[test]
+    fn csv_round_trip() {
+        let mut list = MemoryList::default();
+        let mut item = ItemDesc::new("say \"hi\", twice", "line one\nline two", vec!["a", "b"]);
+        item.status = Status::Queuing;
+        item.attributes.insert("due".into(), "2026-10-05".into());
+        list.add(item.clone());
+
+        let columns = vec![
+            Column::Id,
+            Column::Name,
+            Column::Description,
+            Column::Status,
+            Column::Tags,
+            Column::Attribute("due".into()),
+        ];
+        for &delimiter in &[',', '\t'] {
+            let text = export(&list, &list.select(&Filter::All), &columns, delimiter);
+            let rows = parse(&text, delimiter, &Mapping::new()).unwrap();
+            assert!(rows.errors.is_empty());
+            assert_eq!(rows.items, vec![(2, item.clone())]);
+        }
+    }
+}
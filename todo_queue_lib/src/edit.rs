@@ -0,0 +1,301 @@
+//! Writing items out to be edited by hand, and reading the edits back.
+//!
+//! Each item gets a header of fields between `---` lines, with its
+//! description underneath:
+//!
+//! ```text
+//! --- @00042
+//! name: deploy
+//! tags: #ops #release
+//! ---
+//! Push the build, then
+//! watch the dashboards.
+//! ```
+//!
+//! Lines starting with `#` before the first item or in a header are
+//! comments, as are the ones pointing out mistakes wherever they are.
+
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+use list::{Item, ItemId, List};
+
+const START: &str = "--- ";
+const END: &str = "---";
+/// How comments pointing out mistakes start, so they can be taken out
+/// again once the mistake is fixed.
+const ERROR_COMMENT: &str = "# error: ";
+
+const PREAMBLE: &str = "\
+# Change the names, tags and descriptions of the items below, then save
+# and close the editor. Items taken out of the file are left as they are,
+# so emptying it changes nothing.
+";
+
+/// An item as it was left in the file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub id: ItemId,
+    pub name: String,
+    pub description: String,
+    pub tags: Vec<String>,
+}
+
+/// A mistake in an edited file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EditError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl EditError {
+    fn new<M: Into<String>>(line: usize, message: M) -> Self {
+        Self {
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for EditError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for EditError {
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
+
+/// Writes the items of `list` with the given ids to be edited.
+pub fn write<L: List>(list: &L, ids: &[ItemId]) -> String {
+    let mut out = PREAMBLE.to_string();
+    for id in ids {
+        let item = match list.get(id) {
+            Some(item) => item,
+            None => continue,
+        };
+        let tags: Vec<_> = item.get_tags().iter().map(|tag| format!("#{}", tag)).collect();
+        out += &format!("\n{}{}\nname: {}\ntags: {}\n{}\n", START, id, item.get_name(), tags.join(" "), END);
+        if !item.get_description().is_empty() {
+            out += item.get_description();
+            out += "\n";
+        }
+    }
+    out
+}
+
+fn is_comment(line: &str) -> bool {
+    line.trim().is_empty() || line.starts_with('#')
+}
+
+/// Reads the items back out of `text`, which may only hold the items with
+/// the given ids.
+pub fn parse(text: &str, ids: &[ItemId]) -> Result<Vec<Edit>, EditError> {
+    let mut edits: Vec<Edit> = vec![];
+    let mut seen = BTreeSet::new();
+    let mut lines = text.lines().enumerate().map(|(idx, line)| (idx + 1, line)).peekable();
+
+    while let Some((number, line)) = lines.next() {
+        if is_comment(line) {
+            continue;
+        }
+        let id: ItemId = line
+            .strip_prefix(START)
+            .ok_or_else(|| EditError::new(number, "expected an item starting with --- @id"))?
+            .trim()
+            .trim_start_matches('@')
+            .parse()
+            .map_err(|_| EditError::new(number, format!("bad id in {}", line)))?;
+        if !ids.contains(&id) {
+            return Err(EditError::new(number, format!("{} isn't one of the items being edited", id)));
+        }
+        if !seen.insert(id) {
+            return Err(EditError::new(number, format!("{} is in the file twice", id)));
+        }
+
+        let unfinished = || EditError::new(number, format!("the header of {} has no --- after it", id));
+        let (mut name, mut tags) = (None, vec![]);
+        loop {
+            let (field_number, field) = lines.next().ok_or_else(unfinished)?;
+            if field == END {
+                break;
+            }
+            if field.starts_with(START) {
+                return Err(unfinished());
+            }
+            if is_comment(field) {
+                continue;
+            }
+            let (key, value) = field
+                .split_once(':')
+                .ok_or_else(|| EditError::new(field_number, "expected a field, written as key: value"))?;
+            match key.trim() {
+                "name" => name = Some(value.trim().to_string()),
+                "tags" => {
+                    tags = value
+                        .split_whitespace()
+                        .map(|tag| tag.trim_start_matches('#').to_string())
+                        .filter(|tag| !tag.is_empty())
+                        .collect()
+                }
+                key => {
+                    return Err(EditError::new(
+                        field_number,
+                        format!("unknown field {}, expected name or tags", key),
+                    ))
+                }
+            }
+        }
+        let name = match name {
+            Some(ref name) if !name.is_empty() => name.clone(),
+            _ => return Err(EditError::new(number, format!("{} needs a name", id))),
+        };
+
+        let mut description = vec![];
+        while let Some(&(_, line)) = lines.peek() {
+            if line.starts_with(START) {
+                break;
+            }
+            if !line.starts_with(ERROR_COMMENT) {
+                description.push(line);
+            }
+            lines.next();
+        }
+        edits.push(Edit {
+            id,
+            name,
+            description: description.join("\n").trim_start_matches('\n').trim_end().to_string(),
+            tags,
+        });
+    }
+    Ok(edits)
+}
+
+/// `text` with a comment pointing out `error` above the line it's on, in
+/// place of any left from before. A mistake in the line starting an item
+/// is pointed out just below it instead, inside the item's header, rather
+/// than at the end of the description before it.
+pub fn annotate(text: &str, error: &EditError) -> String {
+    let comment = format!("{}{}\n", ERROR_COMMENT, error.message);
+    let mut out = String::new();
+    for (idx, line) in text.lines().enumerate() {
+        let here = idx + 1 == error.line;
+        let below = here && line.starts_with(START);
+        if here && !below {
+            out += &comment;
+        }
+        if line.starts_with(ERROR_COMMENT) {
+            continue;
+        }
+        out += line;
+        out += "\n";
+        if below {
+            out += &comment;
+        }
+    }
+    out
+}
+
+/// Applies the edits to `list` through the item setters, returning the ids
+/// of the items which changed.
+pub fn apply<L: List>(list: &mut L, edits: &[Edit]) -> Vec<ItemId> {
+    let mut changed = vec![];
+    for edit in edits {
+        let item = match list.get_mut(&edit.id) {
+            Some(item) => item,
+            None => continue,
+        };
+        let old_tags: Vec<String> = item.get_tags().iter().map(|tag| tag.to_string()).collect();
+        if item.get_name() == edit.name && item.get_description() == edit.description && old_tags == edit.tags {
+            continue;
+        }
+
+        item.set_name(&edit.name);
+        item.set_description(&edit.description);
+        for tag in old_tags.iter().filter(|tag| !edit.tags.contains(tag)) {
+            item.set_tag(tag, false);
+        }
+        for tag in &edit.tags {
+            item.set_tag(tag, true);
+        }
+        changed.push(edit.id);
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use edit::{annotate, apply, parse, write};
+    use list::{Item, List, MemoryList};
+
+    #[test]
+    fn edits_round_trip() {
+        let mut list = MemoryList::default();
+        let deploy = list.add("deploy #ops -- push the build".parse().unwrap());
+        let docs = list.add("write docs".parse().unwrap());
+        let ids = [deploy, docs];
+
+        let text = write(&list, &ids);
+        assert_eq!(apply(&mut list, &parse(&text, &ids).unwrap()), vec![]);
+
+        let text = text
+            .replace("name: deploy", "name: deploy it")
+            .replace("tags: #ops", "tags: #ops #release")
+            .replace("push the build", "push the build\n\nthen watch it");
+        assert_eq!(apply(&mut list, &parse(&text, &ids).unwrap()), vec![deploy]);
+        let item = list.get(&deploy).unwrap();
+        assert_eq!(item.get_name(), "deploy it");
+        assert_eq!(item.get_tags(), vec!["ops", "release"]);
+        assert_eq!(item.get_description(), "push the build\n\nthen watch it");
+        assert_eq!(list.get(&docs).unwrap().get_name(), "write docs");
+    }
+
+    #[test]
+    fn mistakes_are_annotated() {
+        let mut list = MemoryList::default();
+        let deploy = list.add("deploy".parse().unwrap());
+        let text = write(&list, &[deploy]).replace("tags:", "tag:");
+
+        let error = parse(&text, &[deploy]).unwrap_err();
+        let line = text.lines().position(|line| line.starts_with("tag:")).unwrap() + 1;
+        assert_eq!(error.line, line);
+
+        let annotated = annotate(&text, &error);
+        assert!(annotated.contains("# error: unknown field tag"));
+        let fixed = annotated.replace("tag:", "tags:");
+        let edits = parse(&fixed, &[deploy]).unwrap();
+        assert_eq!(edits[0].name, "deploy");
+
+        let fixed = annotate(&fixed, &error);
+        assert_eq!(fixed.matches("# error:").count(), 1);
+        assert!(parse(&text, &[]).is_err());
+    }
+
+    #[test]
+    fn mistakes_stay_out_of_descriptions() {
+        let mut list = MemoryList::default();
+        let deploy = list.add("deploy -- push the build".parse().unwrap());
+        let docs = list.add("docs".parse().unwrap());
+        let ids = [deploy, docs];
+        let text = write(&list, &ids).replace("name: docs", "name:");
+
+        let error = parse(&text, &ids).unwrap_err();
+        assert_eq!(error.message, format!("{} needs a name", docs));
+        let annotated = annotate(&text, &error);
+        let header = format!("--- {}\n# error: {} needs a name\nname:", docs, docs);
+        assert!(annotated.contains(&header), "{}", annotated);
+
+        let fixed = annotated.replace("name:\n", "name: docs\n");
+        assert_eq!(apply(&mut list, &parse(&fixed, &ids).unwrap()), vec![]);
+        assert_eq!(list.get(&deploy).unwrap().get_description(), "push the build");
+
+        // Comments left below a description by older versions are dropped
+        let stale = write(&list, &ids)
+            .replace("push the build\n", "push the build\n\n# error: oops\n");
+        assert_eq!(apply(&mut list, &parse(&stale, &ids).unwrap()), vec![]);
+    }
+}
@@ -0,0 +1,354 @@
+//! Undoing and redoing changes to lists.
+//!
+//! A `Changeset` records what one command did to a list as the before and
+//! after of every item it touched, along with where it was in the queue,
+//! so that it can be reverted or applied again later.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display, Formatter};
+
+use list::{Item, ItemDesc, ItemId, List, Status};
+
+/// What happened to one item. An item which didn't exist before was added,
+/// and one which doesn't exist after was removed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ItemChange {
+    pub id: ItemId,
+    pub before: Option<ItemDesc>,
+    pub after: Option<ItemDesc>,
+    /// The items just before this one in the list, before and after the
+    /// change, with `None` for the front. Only kept when the item was
+    /// moved, added or removed.
+    #[serde(default)]
+    pub moved: Option<(Option<ItemId>, Option<ItemId>)>,
+}
+
+impl ItemChange {
+    /// The name of the item, as it is after the change if it still exists.
+    fn name(&self) -> &str {
+        self.after
+            .as_ref()
+            .or(self.before.as_ref())
+            .map(|item| item.get_name())
+            .unwrap_or_default()
+    }
+
+    /// Describes the change in a few words, such as
+    /// `complete @00042 'deploy'`.
+    pub fn summary(&self) -> String {
+        let verb = match (&self.before, &self.after) {
+            (None, _) => "add",
+            (_, None) => "delete",
+            (Some(before), Some(after)) if before == after => "move",
+            (Some(before), Some(after)) if before.status != after.status => {
+                if after.status != Status::Completed {
+                    return format!("mark {} '{}' as {}", self.id, self.name(), after.status);
+                }
+                "complete"
+            }
+            (Some(before), Some(after)) if before.name != after.name => "rename",
+            _ => "edit",
+        };
+        format!("{} {} '{}'", verb, self.id, self.name())
+    }
+}
+
+impl Display for ItemChange {
+    /// Shows the change as done: `+` for added, `-` for removed and `~` for
+    /// modified.
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let sign = match (&self.before, &self.after) {
+            (None, _) => '+',
+            (_, None) => '-',
+            _ => '~',
+        };
+        write!(f, "{} {}: '{}'", sign, self.id, self.name())
+    }
+}
+
+/// Everything one command changed in a list.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Changeset {
+    /// The name of the list which was changed.
+    pub list: String,
+    /// The command which made the changes, for showing to the user.
+    pub command: String,
+    pub changes: Vec<ItemChange>,
+}
+
+impl Changeset {
+    /// Works out the changes between two versions of a list.
+    pub fn between<N, C>(
+        list: N,
+        command: C,
+        before: &[(ItemId, ItemDesc)],
+        after: &[(ItemId, ItemDesc)],
+    ) -> Self
+    where
+        N: Into<String>,
+        C: Into<String>,
+    {
+        let find = |items: &[(ItemId, ItemDesc)], id: ItemId| {
+            items.iter().find(|me| me.0 == id).map(|me| me.1.clone())
+        };
+        let previous = |items: &[(ItemId, ItemDesc)], id: ItemId| {
+            let at = items.iter().position(|me| me.0 == id)?;
+            at.checked_sub(1).map(|at| items[at].0)
+        };
+        let moved = moved(before, after);
+
+        let mut changes: Vec<ItemChange> = before
+            .iter()
+            .filter_map(|&(id, ref old)| {
+                let new = find(after, id);
+                let moved = if new.is_none() || moved.contains(&id) {
+                    Some((previous(before, id), previous(after, id)))
+                } else {
+                    None
+                };
+                if new.as_ref() == Some(old) && moved.is_none() {
+                    None
+                } else {
+                    Some(ItemChange {
+                        id,
+                        before: Some(old.clone()),
+                        after: new,
+                        moved,
+                    })
+                }
+            })
+            .collect();
+        changes.extend(
+            after
+                .iter()
+                .filter(|&&(id, _)| find(before, id).is_none())
+                .map(|&(id, ref new)| ItemChange {
+                    id,
+                    before: None,
+                    after: Some(new.clone()),
+                    moved: Some((None, previous(after, id))),
+                }),
+        );
+
+        Self {
+            list: list.into(),
+            command: command.into(),
+            changes,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Describes the changes as a commit message: a single change on its
+    /// own, or a count of them followed by each on a line of its own.
+    pub fn summary(&self) -> String {
+        match self.changes.len() {
+            1 => self.changes[0].summary(),
+            count => {
+                let mut summary = format!("{} changes to {}\n", count, self.list);
+                for change in &self.changes {
+                    summary.push_str(&format!("\n{}", change.summary()));
+                }
+                summary
+            }
+        }
+    }
+
+    /// The changeset which takes a list back to how it was before this one.
+    pub fn reversed(&self) -> Self {
+        Self {
+            list: self.list.clone(),
+            command: self.command.clone(),
+            changes: self.changes
+                .iter()
+                .map(|change| ItemChange {
+                    id: change.id,
+                    before: change.after.clone(),
+                    after: change.before.clone(),
+                    moved: change.moved.map(|(before, after)| (after, before)),
+                })
+                .collect(),
+        }
+    }
+
+    /// The items which have changed in `list` since this changeset was
+    /// made, and which applying it again would overwrite.
+    pub fn conflicts<L: List>(&self, list: &L) -> Vec<ItemId> {
+        self.changes
+            .iter()
+            .filter(|change| {
+                let current = list.get(&change.id).map(|item| item.to_desc());
+                current != change.before
+            })
+            .map(|change| change.id)
+            .collect()
+    }
+
+    /// Makes the changes to `list`, putting the items which moved or came
+    /// back into their places.
+    pub fn apply<L: List>(&self, list: &mut L) {
+        let mut places = HashMap::new();
+        for change in &self.changes {
+            match change.after {
+                Some(ref item) => {
+                    list.insert(change.id, item.clone());
+                    if let Some((_, after)) = change.moved {
+                        places.insert(change.id, after);
+                    }
+                }
+                None => list.remove(&change.id),
+            }
+        }
+
+        // An item goes after the one before it once that one is in its own
+        // place, so that a run of items comes back in order
+        for change in &self.changes {
+            let mut run = vec![];
+            let mut next = Some(change.id);
+            while let Some(id) = next {
+                match places.remove(&id) {
+                    Some(after) => {
+                        run.push((id, after));
+                        next = after;
+                    }
+                    None => break,
+                }
+            }
+            for (id, after) in run.into_iter().rev() {
+                list.move_after(&id, after.as_ref());
+            }
+        }
+    }
+}
+
+/// The items in both lists which changed places: all but the longest run of
+/// them which kept its order.
+fn moved(before: &[(ItemId, ItemDesc)], after: &[(ItemId, ItemDesc)]) -> HashSet<ItemId> {
+    let positions: HashMap<ItemId, usize> = before
+        .iter()
+        .enumerate()
+        .map(|(at, &(id, _))| (id, at))
+        .collect();
+    let kept: Vec<(ItemId, usize)> = after
+        .iter()
+        .filter_map(|&(id, _)| positions.get(&id).map(|&at| (id, at)))
+        .collect();
+
+    // `ends[len]` is the item ending the lowest run of `len + 1` increasing
+    // positions found so far, and `links` the item before each in its run
+    let mut ends: Vec<usize> = vec![];
+    let mut links = vec![None; kept.len()];
+    for (idx, &(_, at)) in kept.iter().enumerate() {
+        let len = ends.partition_point(|&end| kept[end].1 < at);
+        links[idx] = len.checked_sub(1).map(|len| ends[len]);
+        if len == ends.len() {
+            ends.push(idx);
+        } else {
+            ends[len] = idx;
+        }
+    }
+
+    let mut in_order = HashSet::new();
+    let mut next = ends.last().cloned();
+    while let Some(idx) = next {
+        in_order.insert(kept[idx].0);
+        next = links[idx];
+    }
+    kept.into_iter()
+        .map(|(id, _)| id)
+        .filter(|id| !in_order.contains(id))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use history::Changeset;
+    use list::{Item, ItemDesc, ItemId, List, MemoryList, Status};
+
+    #[test]
+    fn undo_restores_list() {
+        let mut list = MemoryList::default();
+        let deploy = list.add("deploy #ops".parse().unwrap());
+        let docs = list.add("write docs".parse().unwrap());
+        let before = list.items.clone();
+
+        list.remove(&deploy);
+        list.get_mut(&docs).unwrap().set_tag("docs", true);
+        let review = list.add("review".parse().unwrap());
+        let changeset = Changeset::between("work", "todo", &before, &list.items);
+        assert_eq!(changeset.changes.len(), 3);
+        let after = list.items.clone();
+
+        let undo = changeset.reversed();
+        assert!(undo.conflicts(&list).is_empty());
+        undo.apply(&mut list);
+        assert!(list.get(&review).is_none());
+        let mut items = list.items.clone();
+        items.sort_by_key(|me| me.0);
+        assert_eq!(items, before);
+
+        assert!(changeset.conflicts(&list).is_empty());
+        changeset.apply(&mut list);
+        assert_eq!(list.get(&docs), after.iter().find(|me| me.0 == docs).map(|me| &me.1));
+
+        list.get_mut(&review)
+            .unwrap()
+            .assign(&"review again".parse::<ItemDesc>().unwrap());
+        assert_eq!(undo.conflicts(&list), vec![review]);
+    }
+
+    #[test]
+    fn undo_restores_positions() {
+        let mut list = MemoryList::default();
+        let ids: Vec<_> = ["a", "b", "c", "d", "e"]
+            .iter()
+            .map(|name| list.add(name.parse().unwrap()))
+            .collect();
+        let order = |list: &MemoryList| -> Vec<ItemId> {
+            list.items.iter().map(|me| me.0).collect()
+        };
+        let original = order(&list);
+
+        let before = list.items.clone();
+        list.remove(&ids[1]);
+        list.remove(&ids[2]);
+        let deleted = Changeset::between("work", "delete", &before, &list.items);
+        deleted.reversed().apply(&mut list);
+        assert_eq!(order(&list), original);
+        deleted.apply(&mut list);
+        assert_eq!(order(&list), vec![ids[0], ids[3], ids[4]]);
+        deleted.reversed().apply(&mut list);
+
+        let before = list.items.clone();
+        list.move_after(&ids[4], Some(&ids[0]));
+        let moved = Changeset::between("work", "board", &before, &list.items);
+        assert_eq!(moved.changes.len(), 1);
+        assert_eq!(moved.summary(), "move @00005 'e'");
+        let after = order(&list);
+        moved.reversed().apply(&mut list);
+        assert_eq!(order(&list), original);
+        moved.apply(&mut list);
+        assert_eq!(order(&list), after);
+    }
+
+    #[test]
+    fn summaries() {
+        let mut list = MemoryList::default();
+        let deploy = list.add("deploy".parse().unwrap());
+        let before = list.items.clone();
+        list.get_mut(&deploy).unwrap().set_status(Status::Completed);
+        let changeset = Changeset::between("work", "todo", &before, &list.items);
+        assert_eq!(changeset.summary(), "complete @00001 'deploy'");
+
+        let before = list.items.clone();
+        list.get_mut(&deploy).unwrap().set_status(Status::Working);
+        list.add("review".parse().unwrap());
+        let changeset = Changeset::between("work", "todo", &before, &list.items);
+        assert_eq!(
+            changeset.summary(),
+            "2 changes to work\n\nmark @00001 'deploy' as %Working\nadd @00002 'review'"
+        );
+    }
+}
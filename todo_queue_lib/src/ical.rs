@@ -0,0 +1,519 @@
+//! Conversion between lists and [RFC 5545] iCalendar `VTODO` components.
+//!
+//! | iCalendar                            | item                                 |
+//! |--------------------------------------|--------------------------------------|
+//! | `SUMMARY`                            | name                                 |
+//! | `DESCRIPTION`                        | description                          |
+//! | `STATUS:NEEDS-ACTION`                | `Status::Waiting`                    |
+//! | `STATUS:NEEDS-ACTION` and            | `Status::Queuing`                    |
+//! | `X-TODO-QUEUE-STATUS:QUEUING`        |                                      |
+//! | `STATUS:IN-PROCESS`                  | `Status::Working`                    |
+//! | `STATUS:COMPLETED` or `CANCELLED`    | `Status::Completed`                  |
+//! | `CATEGORIES`                         | tags                                 |
+//! | `DUE`                                | `due` attribute, as `YYYY-MM-DD`     |
+//! | `X-TODO-QUEUE-ATTRIBUTE:key=value`   | any other attribute                  |
+//!
+//! Items get the UID `<id>-<list name>@todo_queue`, so exporting a list twice
+//! gives the same UIDs and importing a calendar back into the list it came
+//! from updates the items in place. UIDs from other calendars are kept in the
+//! `uid` attribute for the same reason.
+//!
+//! [RFC 5545]: https://tools.ietf.org/html/rfc5545
+
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+use list::{Imported, Item, ItemDesc, ItemId, List, Status};
+use query::Filter;
+
+const DUE: &str = "due";
+const UID: &str = "uid";
+const UID_DOMAIN: &str = "@todo_queue";
+const QUEUING: &str = "QUEUING";
+
+/// The longest a content line may be, in octets, before it has to be folded.
+const LINE_LENGTH: usize = 75;
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | ';' | ',' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => unescaped.push('\n'),
+            Some(c) => unescaped.push(c),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+/// Splits a list of values on the commas which aren't escaped.
+fn split_values(text: &str) -> Vec<String> {
+    let mut values = vec![];
+    let mut start = 0;
+    let mut escaped = false;
+    for (idx, c) in text.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            ',' => {
+                values.push(unescape(&text[start..idx]));
+                start = idx + 1;
+            }
+            _ => {}
+        }
+    }
+    values.push(unescape(&text[start..]));
+    values
+}
+
+/// Writes a content line, folding it onto continuation lines if it is too
+/// long.
+fn push_line(out: &mut String, line: &str) {
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > LINE_LENGTH {
+            out.push_str("\r\n ");
+            length = 1;
+        }
+        out.push(c);
+        length += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+fn uid(id: ItemId, list_name: &str) -> String {
+    format!(
+        "{}-{}{}",
+        id.to_string().trim_start_matches('@'),
+        list_name,
+        UID_DOMAIN
+    )
+}
+
+/// Reads the id back out of a UID made by `uid` for the same list.
+fn parse_uid(uid: &str, list_name: &str) -> Option<ItemId> {
+    let rest = uid.strip_suffix(UID_DOMAIN)?;
+    let sep = rest.find('-')?;
+    if &rest[sep + 1..] == list_name {
+        rest[..sep].parse().ok()
+    } else {
+        None
+    }
+}
+
+fn is_date(text: &str) -> bool {
+    let bytes = text.as_bytes();
+    bytes.len() == 10
+        && bytes.iter().enumerate().all(|(idx, b)| match idx {
+            4 | 7 => *b == b'-',
+            _ => b.is_ascii_digit(),
+        })
+}
+
+fn write_todo(out: &mut String, id: ItemId, item: &ItemDesc, list_name: &str, stamp: &str) {
+    push_line(out, "BEGIN:VTODO");
+    let uid = item.attributes
+        .get(UID)
+        .cloned()
+        .unwrap_or_else(|| uid(id, list_name));
+    push_line(out, &format!("UID:{}", escape(&uid)));
+    push_line(out, &format!("DTSTAMP:{}", stamp));
+    push_line(out, &format!("SUMMARY:{}", escape(&item.name)));
+    if !item.description.is_empty() {
+        push_line(out, &format!("DESCRIPTION:{}", escape(&item.description)));
+    }
+
+    let status = match item.status {
+        Status::Waiting | Status::Queuing => "NEEDS-ACTION",
+        Status::Working => "IN-PROCESS",
+        Status::Completed => "COMPLETED",
+    };
+    push_line(out, &format!("STATUS:{}", status));
+    if item.status == Status::Queuing {
+        push_line(out, &format!("X-TODO-QUEUE-STATUS:{}", QUEUING));
+    }
+
+    if !item.tags.is_empty() {
+        let tags: Vec<_> = item.tags.iter().map(|tag| escape(tag)).collect();
+        push_line(out, &format!("CATEGORIES:{}", tags.join(",")));
+    }
+
+    for (key, value) in &item.attributes {
+        match key.as_str() {
+            UID => {}
+            DUE if is_date(value) => {
+                push_line(out, &format!("DUE;VALUE=DATE:{}", value.replace('-', "")));
+            }
+            _ => push_line(
+                out,
+                &format!("X-TODO-QUEUE-ATTRIBUTE:{}={}", escape(key), escape(value)),
+            ),
+        }
+    }
+    push_line(out, "END:VTODO");
+}
+
+/// Writes the items of `list` with the given ids as an iCalendar file.
+///
+/// `stamp` is the time the file is written, as a UTC date-time like
+/// `20261019T120000Z`, which every `VTODO` needs as its `DTSTAMP`.
+pub fn export<L>(list: &L, list_name: &str, ids: &[ItemId], stamp: &str) -> String
+where
+    L: List,
+{
+    let mut out = String::new();
+    push_line(&mut out, "BEGIN:VCALENDAR");
+    push_line(&mut out, "VERSION:2.0");
+    push_line(&mut out, "PRODID:-//todo_queue//todo_queue//EN");
+    for id in ids {
+        if let Some(item) = list.get(id) {
+            write_todo(&mut out, *id, &item.to_desc(), list_name, stamp);
+        }
+    }
+    push_line(&mut out, "END:VCALENDAR");
+    out
+}
+
+/// An error in an iCalendar file being imported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IcsError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl IcsError {
+    fn new<M: Into<String>>(line: usize, message: M) -> Self {
+        Self {
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for IcsError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for IcsError {
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
+
+/// A content line, split into its upper-cased name and its still escaped
+/// value. None of the parameters we read change how a value is read.
+struct ContentLine<'a> {
+    number: usize,
+    name: String,
+    value: &'a str,
+}
+
+/// Joins folded lines back together, keeping the number each starts on.
+fn unfold(text: &str) -> Vec<(usize, String)> {
+    let mut lines: Vec<(usize, String)> = vec![];
+    for (idx, line) in text.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        match (line.chars().next(), lines.last_mut()) {
+            (Some(' '), Some(last)) | (Some('\t'), Some(last)) => last.1.push_str(&line[1..]),
+            _ if line.is_empty() => {}
+            _ => lines.push((idx + 1, line.into())),
+        }
+    }
+    lines
+}
+
+fn parse_line(number: usize, line: &str) -> Result<ContentLine<'_>, IcsError> {
+    // The value starts at the first colon outside of a quoted parameter.
+    let mut quoted = false;
+    let colon = line.char_indices()
+        .find(|&(_, c)| {
+            if c == '"' {
+                quoted = !quoted;
+            }
+            c == ':' && !quoted
+        })
+        .map(|(idx, _)| idx)
+        .ok_or_else(|| IcsError::new(number, "expected a ':' after the property name"))?;
+
+    let (head, value) = (&line[..colon], &line[colon + 1..]);
+    let name = head.split(';').next().unwrap_or(head);
+    Ok(ContentLine {
+        number,
+        name: name.to_ascii_uppercase(),
+        value,
+    })
+}
+
+/// Turns a `DUE` value, either a date or a date-time, into a `YYYY-MM-DD`
+/// date.
+fn parse_due(line: &ContentLine) -> Result<String, IcsError> {
+    let date = line.value.get(..8).filter(|date| date.bytes().all(|b| b.is_ascii_digit()));
+    match date {
+        Some(date) => Ok(format!("{}-{}-{}", &date[..4], &date[4..6], &date[6..])),
+        None => Err(IcsError::new(
+            line.number,
+            format!("'{}' is not a date", line.value),
+        )),
+    }
+}
+
+struct Todo {
+    uid: Option<String>,
+    item: ItemDesc,
+}
+
+fn parse_todo(lines: &[ContentLine], end: usize) -> Result<Todo, IcsError> {
+    let mut uid = None;
+    let mut name = None;
+    let mut status = None;
+    let mut queuing = false;
+    let mut item = ItemDesc::new("", "", Vec::<String>::new());
+    // Properties of components nested in the to-do, like alarms, are skipped.
+    let mut depth = 0;
+
+    for line in lines {
+        match line.name.as_str() {
+            "BEGIN" => depth += 1,
+            "END" => depth -= 1,
+            _ if depth > 0 => {}
+            "UID" => uid = Some(unescape(line.value)),
+            "SUMMARY" => name = Some(unescape(line.value)),
+            "DESCRIPTION" => item.description = unescape(line.value),
+            "STATUS" => {
+                status = Some(match line.value.to_ascii_uppercase().as_str() {
+                    "NEEDS-ACTION" => Status::Waiting,
+                    "IN-PROCESS" => Status::Working,
+                    "COMPLETED" | "CANCELLED" => Status::Completed,
+                    other => {
+                        return Err(IcsError::new(
+                            line.number,
+                            format!("unknown to-do status '{}'", other),
+                        ))
+                    }
+                })
+            }
+            "X-TODO-QUEUE-STATUS" => queuing = line.value.eq_ignore_ascii_case(QUEUING),
+            "CATEGORIES" => {
+                for category in split_values(line.value) {
+                    let tag = category.split_whitespace().collect::<Vec<_>>().join("-");
+                    if !tag.is_empty() && !item.tags.contains(&tag) {
+                        item.tags.push(tag);
+                    }
+                }
+            }
+            "DUE" => {
+                item.attributes.insert(DUE.into(), parse_due(line)?);
+            }
+            "X-TODO-QUEUE-ATTRIBUTE" => {
+                let value = unescape(line.value);
+                match value.find('=') {
+                    Some(sep) if sep > 0 => {
+                        item.attributes
+                            .insert(value[..sep].into(), value[sep + 1..].into());
+                    }
+                    _ => {
+                        return Err(IcsError::new(
+                            line.number,
+                            "expected an attribute written as 'key=value'",
+                        ))
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    item.name = name.filter(|name| !name.trim().is_empty())
+        .ok_or_else(|| IcsError::new(end, "a to-do needs a SUMMARY to use as its name"))?;
+    item.status = match status.unwrap_or_default() {
+        Status::Waiting if queuing => Status::Queuing,
+        status => status,
+    };
+    Ok(Todo { uid, item })
+}
+
+/// Adds the to-dos in the iCalendar file `text` to `list`.
+///
+/// To-dos exported from the list named `list_name`, or imported into it
+/// before, update the items they came from instead of adding new ones. Any
+/// other calendar components are ignored.
+pub fn import<L>(list: &mut L, list_name: &str, text: &str) -> Result<Imported, IcsError>
+where
+    L: List,
+{
+    let lines = unfold(text);
+    let lines = lines
+        .iter()
+        .map(|&(number, ref line)| parse_line(number, line))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut todos = vec![];
+    let mut start = None;
+    let mut depth = 0;
+    for (idx, line) in lines.iter().enumerate() {
+        match (line.name.as_str(), line.value.to_ascii_uppercase().as_str()) {
+            ("BEGIN", "VTODO") if start.is_none() => start = Some(idx),
+            ("BEGIN", _) if start.is_some() => depth += 1,
+            ("END", _) if start.is_some() && depth > 0 => depth -= 1,
+            ("END", "VTODO") => match start.take() {
+                Some(start) => todos.push(parse_todo(&lines[start + 1..idx], line.number)?),
+                None => return Err(IcsError::new(line.number, "unexpected END:VTODO")),
+            },
+            _ => {}
+        }
+    }
+    if let Some(start) = start {
+        return Err(IcsError::new(lines[start].number, "a to-do is never ended"));
+    }
+
+    let mut imported = Imported::default();
+    for Todo { uid, mut item } in todos {
+        let mut existing = uid.as_ref()
+            .and_then(|uid| parse_uid(uid, list_name))
+            .filter(|id| list.get(id).is_some());
+        if existing.is_none() {
+            if let Some(uid) = uid {
+                existing = list.select(&Filter::All)
+                    .into_iter()
+                    .find(|id| list.get(id).and_then(|item| item.get_attribute(UID)) == Some(&uid));
+                item.attributes.insert(UID.into(), uid);
+            }
+        }
+
+        match existing {
+            Some(id) => {
+                list.get_mut(&id).unwrap().assign(&item);
+                imported.updated.push(id);
+            }
+            None => imported.added.push(list.add(item)),
+        }
+    }
+
+    Ok(imported)
+}
+
+#[cfg(test)]
+mod tests {
+    use ical::{export, import, IcsError};
+    use list::{Item, List, MemoryList, Status};
+    use query::Filter;
+    use script::item_parser;
+
+    const STAMP: &str = "20261019T120000Z";
+
+    #[test]
+    fn export_vtodo() {
+        let mut list = MemoryList::default();
+        list.add(item_parser("%queue call mom #family due:2026-10-05 -- about the trip, again").unwrap());
+
+        assert_eq!(
+            export(&list, "home", &list.select(&Filter::All), STAMP),
+            "BEGIN:VCALENDAR\r\n\
+             VERSION:2.0\r\n\
+             PRODID:-//todo_queue//todo_queue//EN\r\n\
+             BEGIN:VTODO\r\n\
+             UID:00001-home@todo_queue\r\n\
+             DTSTAMP:20261019T120000Z\r\n\
+             SUMMARY:call mom\r\n\
+             DESCRIPTION:about the trip\\, again\r\n\
+             STATUS:NEEDS-ACTION\r\n\
+             X-TODO-QUEUE-STATUS:QUEUING\r\n\
+             CATEGORIES:family\r\n\
+             DUE;VALUE=DATE:20261005\r\n\
+             END:VTODO\r\n\
+             END:VCALENDAR\r\n"
+        );
+    }
+
+    #[test]
+    fn ics_round_trip() {
+        let mut list = MemoryList::default();
+        for item in &[
+            "%work deploy #ops #release-2 pri:A",
+            "%complete run the tests #ops -- all of them",
+            "write a much longer name for this item so that its summary line gets folded",
+        ] {
+            list.add(item_parser(item).unwrap());
+        }
+        let ids = list.select(&Filter::All);
+        let text = export(&list, "work", &ids, STAMP);
+        assert!(text.lines().all(|line| line.len() <= 75));
+
+        let before = list.items.clone();
+        let imported = import(&mut list, "work", &text).unwrap();
+        assert!(imported.added.is_empty());
+        assert_eq!(imported.updated, ids);
+        assert_eq!(list.items, before);
+
+        let mut other = MemoryList::default();
+        let imported = import(&mut other, "home", &text).unwrap();
+        assert_eq!(imported.added.len(), 3);
+        let deploy = other.get(&imported.added[0]).unwrap();
+        assert_eq!(deploy.get_name(), "deploy");
+        assert_eq!(*deploy.get_status(), Status::Working);
+        assert_eq!(deploy.get_tags(), vec!["ops", "release-2"]);
+        assert_eq!(deploy.get_attribute("pri"), Some("A"));
+        assert_eq!(deploy.get_attribute("uid"), Some("00001-work@todo_queue"));
+        assert!(import(&mut other, "home", &text).unwrap().added.is_empty());
+    }
+
+    #[test]
+    fn import_foreign_calendar() {
+        let text = "BEGIN:VCALENDAR\n\
+                    BEGIN:VEVENT\n\
+                    SUMMARY:not a to-do\n\
+                    END:VEVENT\n\
+                    BEGIN:VTODO\n\
+                    UID:abc@example.com\n\
+                    SUMMARY:Submit\n  report\n\
+                    CATEGORIES:Work Stuff,Q4\n\
+                    DUE:20261101T170000Z\n\
+                    STATUS:CANCELLED\n\
+                    BEGIN:VALARM\n\
+                    DESCRIPTION:reminder\n\
+                    END:VALARM\n\
+                    END:VTODO\n\
+                    END:VCALENDAR\n";
+
+        let mut list = MemoryList::default();
+        let imported = import(&mut list, "work", text).unwrap();
+        let item = list.get(&imported.added[0]).unwrap();
+        assert_eq!(item.get_name(), "Submit report");
+        assert_eq!(item.get_description(), "");
+        assert_eq!(item.get_tags(), vec!["Work-Stuff", "Q4"]);
+        assert_eq!(item.get_attribute("due"), Some("2026-11-01"));
+        assert_eq!(*item.get_status(), Status::Completed);
+
+        assert_eq!(
+            import(&mut list, "work", "BEGIN:VTODO\nDUE:soon\nEND:VTODO\n"),
+            Err(IcsError::new(2, "'soon' is not a date"))
+        );
+        assert_eq!(
+            import(&mut list, "work", "BEGIN:VTODO\nUID:x\n"),
+            Err(IcsError::new(1, "a to-do is never ended"))
+        );
+    }
+}
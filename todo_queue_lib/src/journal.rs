@@ -0,0 +1,336 @@
+//! Recording every change to a list as an event, one JSON object per line.
+//!
+//! A journal is replayed from the top to rebuild the list. Now and then a
+//! snapshot of the whole list is written into it, so replay only has to
+//! start from the last one, and compacting a journal replaces everything
+//! in it with a single snapshot.
+//!
+//! A line left half written by a crash can only be the last one, so replay
+//! leaves an unreadable last line out rather than failing.
+
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+use serde_json;
+
+use list::{self, Item, ItemDesc, ItemId, Status};
+use time::{Timestamp, Transition};
+
+/// One change to a list.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    Add { id: ItemId, item: ItemDesc },
+    Remove { id: ItemId },
+    Rename { id: ItemId, name: String },
+    Describe { id: ItemId, description: String },
+    SetStatus { id: ItemId, status: Status },
+    Tag { id: ItemId, tag: String, set: bool },
+    SetAttribute {
+        id: ItemId,
+        key: String,
+        value: Option<String>,
+    },
+    Stamp {
+        id: ItemId,
+        created: Option<Timestamp>,
+        modified: Option<Timestamp>,
+    },
+    /// The item's status changed, as recorded in its log.
+    Transition { id: ItemId, transition: Transition },
+    /// The item was moved to just after another, or to the front.
+    Move { id: ItemId, after: Option<ItemId> },
+    /// The whole list as it stood, replacing everything before it.
+    Snapshot { items: Vec<(ItemId, ItemDesc)> },
+}
+
+impl Event {
+    /// Applies the event to `items`. Events about items which aren't there
+    /// are ignored.
+    pub fn apply(&self, items: &mut Vec<(ItemId, ItemDesc)>) {
+        let target = match *self {
+            Event::Add { id, ref item } => {
+                match items.iter_mut().find(|me| me.0 == id) {
+                    Some(me) => me.1 = item.clone(),
+                    None => items.push((id, item.clone())),
+                }
+                return;
+            }
+            Event::Remove { id } => {
+                items.retain(|me| me.0 != id);
+                return;
+            }
+            Event::Move { id, after } => {
+                list::move_after(items, &id, after.as_ref());
+                return;
+            }
+            Event::Snapshot { items: ref snapshot } => {
+                *items = snapshot.clone();
+                return;
+            }
+            Event::Rename { id, .. }
+            | Event::Describe { id, .. }
+            | Event::SetStatus { id, .. }
+            | Event::Tag { id, .. }
+            | Event::SetAttribute { id, .. }
+            | Event::Stamp { id, .. }
+            | Event::Transition { id, .. } => id,
+        };
+        let item = match items.iter_mut().find(|me| me.0 == target) {
+            Some(me) => &mut me.1,
+            None => return,
+        };
+
+        match *self {
+            Event::Rename { ref name, .. } => item.set_name(name),
+            Event::Describe {
+                ref description, ..
+            } => item.set_description(description),
+            Event::SetStatus { status, .. } => item.set_status(status),
+            Event::Tag { ref tag, set, .. } => item.set_tag(tag, set),
+            Event::SetAttribute {
+                ref key, ref value, ..
+            } => item.set_attribute(key, value.as_deref()),
+            Event::Stamp {
+                created, modified, ..
+            } => {
+                item.created = created;
+                item.modified = modified;
+            }
+            Event::Transition { ref transition, .. } => {
+                item.transitions.push(transition.clone())
+            }
+            Event::Add { .. }
+            | Event::Remove { .. }
+            | Event::Move { .. }
+            | Event::Snapshot { .. } => unreachable!(),
+        }
+    }
+
+    pub fn to_line(&self) -> String {
+        // An event only holds strings, numbers and maps with string keys,
+        // none of which can fail to serialize.
+        serde_json::to_string(self).unwrap() + "\n"
+    }
+}
+
+/// The events which turn `old` into `new`, for the item `id`. With no
+/// `old` item, the item is added.
+pub fn diff(id: ItemId, old: Option<&ItemDesc>, new: &ItemDesc) -> Vec<Event> {
+    let old = match old {
+        Some(old) => old,
+        None => {
+            return vec![Event::Add {
+                id,
+                item: new.clone(),
+            }]
+        }
+    };
+
+    // A log which was rewritten rather than added to can only be recorded by
+    // putting the whole item again.
+    if !new.transitions.starts_with(&old.transitions) {
+        return vec![Event::Add {
+            id,
+            item: new.clone(),
+        }];
+    }
+
+    let mut events = vec![];
+    if old.name != new.name {
+        events.push(Event::Rename {
+            id,
+            name: new.name.clone(),
+        });
+    }
+    if old.description != new.description {
+        events.push(Event::Describe {
+            id,
+            description: new.description.clone(),
+        });
+    }
+    if old.status != new.status {
+        events.push(Event::SetStatus {
+            id,
+            status: new.status,
+        });
+    }
+    for tag in old.tags.iter().filter(|tag| !new.tags.contains(tag)) {
+        events.push(Event::Tag {
+            id,
+            tag: tag.clone(),
+            set: false,
+        });
+    }
+    for tag in new.tags.iter().filter(|tag| !old.tags.contains(tag)) {
+        events.push(Event::Tag {
+            id,
+            tag: tag.clone(),
+            set: true,
+        });
+    }
+    for key in old.attributes.keys() {
+        if !new.attributes.contains_key(key) {
+            events.push(Event::SetAttribute {
+                id,
+                key: key.clone(),
+                value: None,
+            });
+        }
+    }
+    for (key, value) in &new.attributes {
+        if old.attributes.get(key) != Some(value) {
+            events.push(Event::SetAttribute {
+                id,
+                key: key.clone(),
+                value: Some(value.clone()),
+            });
+        }
+    }
+    if old.created != new.created || old.modified != new.modified {
+        events.push(Event::Stamp {
+            id,
+            created: new.created,
+            modified: new.modified,
+        });
+    }
+    for transition in &new.transitions[old.transitions.len()..] {
+        events.push(Event::Transition {
+            id,
+            transition: transition.clone(),
+        });
+    }
+    events
+}
+
+/// A line of a journal which isn't an event, other than a torn last line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JournalError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for JournalError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for JournalError {
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
+
+/// The list a journal describes.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Replayed {
+    pub items: Vec<(ItemId, ItemDesc)>,
+    /// How many events come after the last snapshot.
+    pub since_snapshot: usize,
+    /// How many bytes at the start of the journal were read. Anything after
+    /// them is a torn line which should be cut off before appending, and if
+    /// they don't end in a newline, one should be added.
+    pub valid_len: usize,
+}
+
+/// Rebuilds a list from the text of its journal.
+pub fn replay(text: &str) -> Result<Replayed, JournalError> {
+    let mut replayed = Replayed::default();
+
+    for (idx, line) in text.split_inclusive('\n').enumerate() {
+        let complete = line.ends_with('\n');
+        if line.trim().is_empty() {
+            replayed.valid_len += line.len();
+            continue;
+        }
+
+        let event = match serde_json::from_str::<Event>(line) {
+            Ok(event) => event,
+            Err(_) if !complete => break,
+            Err(err) => {
+                return Err(JournalError {
+                    line: idx + 1,
+                    message: err.to_string(),
+                })
+            }
+        };
+        event.apply(&mut replayed.items);
+        replayed.since_snapshot = match event {
+            Event::Snapshot { .. } => 0,
+            _ => replayed.since_snapshot + 1,
+        };
+        replayed.valid_len += line.len();
+    }
+
+    Ok(replayed)
+}
+
+#[cfg(test)]
+mod tests {
+    use journal::{diff, replay, Event};
+    use list::{ItemDesc, ItemId};
+
+    #[test]
+    fn replay_rebuilds_changes() {
+        let id = ItemId::from(7);
+        let mut old: ItemDesc = "deploy #ops pri:B".parse().unwrap();
+        old.stamp(None, "2026-10-01T09:00:00Z".parse().unwrap());
+        let mut new: ItemDesc = "%Working ship it #release pri:A".parse().unwrap();
+        new.created = old.created;
+        new.transitions = old.transitions.clone();
+        new.stamp(Some(old.status), "2026-10-02T09:00:00Z".parse().unwrap());
+
+        let mut journal = String::new();
+        for event in diff(id, None, &old).iter().chain(&diff(id, Some(&old), &new)) {
+            journal += &event.to_line();
+        }
+        let replayed = replay(&journal).unwrap();
+        assert_eq!(replayed.items, vec![(id, new.clone())]);
+        assert_eq!(replayed.since_snapshot, 8);
+
+        journal += &Event::Snapshot {
+            items: replayed.items,
+        }.to_line();
+        journal += &Event::Remove { id }.to_line();
+        let replayed = replay(&journal).unwrap();
+        assert!(replayed.items.is_empty());
+        assert_eq!(replayed.since_snapshot, 1);
+    }
+
+    #[test]
+    fn replay_moves_items() {
+        let (deploy, docs) = (ItemId::from(1), ItemId::from(2));
+        let mut journal = String::new();
+        for &id in &[deploy, docs] {
+            journal += &Event::Add {
+                id,
+                item: "deploy".parse().unwrap(),
+            }.to_line();
+        }
+        journal += &Event::Move {
+            id: docs,
+            after: None,
+        }.to_line();
+        let replayed = replay(&journal).unwrap();
+        let order: Vec<_> = replayed.items.iter().map(|&(id, _)| id).collect();
+        assert_eq!(order, vec![docs, deploy]);
+    }
+
+    #[test]
+    fn replay_skips_torn_last_line() {
+        let id = ItemId::from(1);
+        let good = Event::Add {
+            id,
+            item: "deploy".parse().unwrap(),
+        }.to_line();
+        let torn = format!("{}{{\"event\":\"remo", good);
+
+        let replayed = replay(&torn).unwrap();
+        assert_eq!(replayed.items.len(), 1);
+        assert_eq!(replayed.valid_len, good.len());
+
+        let broken = format!("{{\"event\":\"remo\n{}", good);
+        assert_eq!(replay(&broken).unwrap_err().line, 1);
+    }
+}
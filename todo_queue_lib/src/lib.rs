@@ -1,3 +1,4 @@
+extern crate chrono;
 #[macro_use]
 extern crate lalrpop_util;
 extern crate rand;
@@ -6,8 +7,21 @@ extern crate rand_derive;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
+extern crate serde_json;
 
+pub mod crdt;
+pub mod csv;
+pub mod edit;
+pub mod history;
+pub mod ical;
+pub mod journal;
 pub mod list;
+pub mod markdown;
+pub mod merge;
 pub mod query;
 pub mod range;
 pub mod script;
+pub mod storage;
+pub mod taskwarrior;
+pub mod time;
+pub mod todotxt;
@@ -1,7 +1,12 @@
 use query::Filter;
+use range::RangeValue;
+use script::{self, SyntaxError};
+use std::collections::BTreeMap;
 use std::fmt::{self, Display, Formatter};
+use std::iter;
 use std::str::FromStr;
 use std::num::ParseIntError;
+use time::{Timestamp, Transition};
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Serialize, Deserialize)]
 pub enum Status {
@@ -11,17 +16,67 @@ pub enum Status {
     Completed,
 }
 
+/// Every status, in the order items move through them.
+pub const STATUSES: &[Status] = &[Status::Waiting, Status::Queuing, Status::Working, Status::Completed];
+
+impl Status {
+    /// Whether an item can go straight from this status to `to`. Items move
+    /// forward one step at a time, work can be put back in the queue, and
+    /// anything can be sent back to waiting.
+    pub fn can_become(self, to: Status) -> bool {
+        use self::Status::*;
+        match (self, to) {
+            (from, to) if from == to => true,
+            (_, Waiting) => true,
+            (Waiting, Queuing) | (Queuing, Working) | (Working, Queuing) | (Working, Completed) => {
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl RangeValue for Status {
+    fn any_between(low: &Status, high: &Status) -> bool {
+        STATUSES.iter().any(|status| low < status && status < high)
+    }
+
+    fn any_above(value: &Status) -> bool {
+        STATUSES.iter().any(|status| status > value)
+    }
+
+    fn any_below(value: &Status) -> bool {
+        STATUSES.iter().any(|status| status < value)
+    }
+}
+
 impl Default for Status {
     fn default() -> Self {
         Status::Waiting
     }
 }
 
+impl Display for Status {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "%{:?}", self)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ItemDesc {
     pub name: String,
     pub description: String,
     pub status: Status,
     pub tags: Vec<String>,
+    #[serde(default)]
+    pub attributes: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<Timestamp>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<Timestamp>,
+    /// Every status the item has had, oldest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub transitions: Vec<Transition>,
 }
 
 pub trait Item {
@@ -29,11 +84,76 @@ pub trait Item {
     fn get_description(&self) -> &str;
     fn get_status(&self) -> &Status;
     fn has_tag(&self, tag: &str) -> bool;
+    fn get_tags(&self) -> Vec<&str>;
+    fn get_attribute(&self, key: &str) -> Option<&str>;
+    fn get_attributes(&self) -> Vec<(&str, &str)>;
+    /// When the item was added, if that was recorded.
+    fn get_created(&self) -> Option<&Timestamp>;
+    /// When the item was last changed, if that was recorded.
+    fn get_modified(&self) -> Option<&Timestamp>;
+    fn get_transitions(&self) -> &[Transition];
+
+    /// When the item was completed, if it is.
+    fn get_completed(&self) -> Option<&Timestamp> {
+        if *self.get_status() != Status::Completed {
+            return None;
+        }
+        self.get_transitions()
+            .iter()
+            .rev()
+            .find(|transition| transition.to == Status::Completed)
+            .map(|transition| &transition.at)
+    }
 
     fn set_name(&mut self, name: &str);
     fn set_description(&mut self, description: &str);
     fn set_status(&mut self, status: Status);
     fn set_tag(&mut self, tag: &str, set: bool);
+    fn set_attribute(&mut self, key: &str, value: Option<&str>);
+
+    fn to_desc(&self) -> ItemDesc {
+        let mut desc = ItemDesc::new(self.get_name(), self.get_description(), self.get_tags());
+        desc.status = *self.get_status();
+        desc.attributes = self.get_attributes()
+            .into_iter()
+            .map(|(key, value)| (key.into(), value.into()))
+            .collect();
+        desc.created = self.get_created().cloned();
+        desc.modified = self.get_modified().cloned();
+        desc.transitions = self.get_transitions().to_vec();
+        desc
+    }
+
+    /// Makes this item match `desc`, dropping any tags and attributes it
+    /// doesn't have.
+    fn assign(&mut self, desc: &ItemDesc) {
+        self.set_name(&desc.name);
+        self.set_description(&desc.description);
+        self.set_status(desc.status);
+
+        let old_tags: Vec<String> = self.get_tags().into_iter().map(String::from).collect();
+        for tag in old_tags {
+            if !desc.tags.contains(&tag) {
+                self.set_tag(&tag, false);
+            }
+        }
+        for tag in &desc.tags {
+            self.set_tag(tag, true);
+        }
+
+        let old_keys: Vec<String> = self.get_attributes()
+            .into_iter()
+            .map(|(key, _)| key.into())
+            .collect();
+        for key in old_keys {
+            if !desc.attributes.contains_key(&key) {
+                self.set_attribute(&key, None);
+            }
+        }
+        for (key, value) in &desc.attributes {
+            self.set_attribute(key, Some(value));
+        }
+    }
 }
 
 impl ItemDesc {
@@ -49,6 +169,27 @@ impl ItemDesc {
             description: description.into(),
             status: Status::default(),
             tags: tags.into_iter().map(T::Item::into).collect(),
+            attributes: BTreeMap::new(),
+            created: None,
+            modified: None,
+            transitions: vec![],
+        }
+    }
+
+    /// Records that the item changed at `now`, and when its status differs
+    /// from `old_status`, that it moved to its current one. With no
+    /// `old_status`, the item is new.
+    pub fn stamp(&mut self, old_status: Option<Status>, now: Timestamp) {
+        if old_status.is_none() && self.created.is_none() {
+            self.created = Some(now);
+        }
+        self.modified = Some(now);
+        if old_status != Some(self.status) {
+            self.transitions.push(Transition {
+                from: old_status,
+                to: self.status,
+                at: now,
+            });
         }
     }
 }
@@ -66,6 +207,27 @@ impl Item for ItemDesc {
     fn has_tag(&self, tag: &str) -> bool {
         self.tags.contains(&tag.into())
     }
+    fn get_tags(&self) -> Vec<&str> {
+        self.tags.iter().map(String::as_str).collect()
+    }
+    fn get_attribute(&self, key: &str) -> Option<&str> {
+        self.attributes.get(key).map(String::as_str)
+    }
+    fn get_attributes(&self) -> Vec<(&str, &str)> {
+        self.attributes
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect()
+    }
+    fn get_created(&self) -> Option<&Timestamp> {
+        self.created.as_ref()
+    }
+    fn get_modified(&self) -> Option<&Timestamp> {
+        self.modified.as_ref()
+    }
+    fn get_transitions(&self) -> &[Transition] {
+        &self.transitions
+    }
 
     fn set_name(&mut self, name: &str) {
         self.name = name.into();
@@ -79,60 +241,38 @@ impl Item for ItemDesc {
     fn set_tag(&mut self, tag: &str, set: bool) {
         if set {
             // TODO: add sorting...?
-            if self.has_tag(tag) {
+            if !self.has_tag(tag) {
                 self.tags.push(tag.into())
             }
         } else if let Some(pos) = self.tags.iter().position(|s| s == &tag) {
             self.tags.remove(pos);
         }
     }
+    fn set_attribute(&mut self, key: &str, value: Option<&str>) {
+        match value {
+            Some(value) => self.attributes.insert(key.into(), value.into()),
+            None => self.attributes.remove(key),
+        };
+    }
 }
 
-impl From<String> for ItemDesc {
-    fn from(string: String) -> Self {
-        let string = string;
-
-        let mut tags = Vec::new();
-        let mut name = String::new();
-
-        let mut chars = string.chars();
-
-        while let Some(c) = chars.next() {
-            if c == '#' {
-                let mut tag = String::new();
-                while let Some(t) = chars.next() {
-                    if t.is_whitespace() || t == '-' {
-                        name.push(t);
-                        break;
-                    } else {
-                        tag.push(t)
-                    }
-                }
-                tags.push(tag)
-            } else {
-                name.push(c)
-            }
-        }
-
-        if let Some(name_sep_idx) = name.find("-") {
-            let name_description = &name.split_off(name_sep_idx)[1..];
-
-            let name = name.trim();
-            let name_description = name_description.trim();
-
-            println!("{:?} {:?}", name, name_description);
-
-            ItemDesc::new(name, name_description, tags)
-        } else {
-            name.trim();
-            ItemDesc::new(name, "", tags)
-        }
+impl FromStr for ItemDesc {
+    type Err = SyntaxError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        script::item_parser(s)
     }
 }
 
 #[derive(Rand, Debug, Hash, PartialEq, PartialOrd, Eq, Ord, Clone, Copy, Serialize, Deserialize)]
 pub struct ItemId(u16);
 
+impl ItemId {
+    /// The id after this one, going back round to the first after the last.
+    pub fn next(self) -> ItemId {
+        ItemId(self.0.wrapping_add(1))
+    }
+}
+
 impl Display for ItemId {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         write!(f, "@{:05}", self.0)
@@ -152,14 +292,159 @@ impl From<u16> for ItemId {
     }
 }
 
+impl From<ItemId> for u16 {
+    fn from(id: ItemId) -> Self {
+        id.0
+    }
+}
+
+/// The items an import added to or changed in a list.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Imported {
+    pub added: Vec<ItemId>,
+    pub updated: Vec<ItemId>,
+}
+
 pub trait List {
     type Item: ?Sized + Item;
 
     fn add(&mut self, item: ItemDesc) -> ItemId;
+    /// Puts `item` in the list under `id`, replacing any item already there.
+    fn insert(&mut self, id: ItemId, item: ItemDesc);
     fn remove(&mut self, item: &ItemId);
+    /// Moves the item `id` to just after `after`, or to the front. The
+    /// order items are kept in is the order they're queued in.
+    fn move_after(&mut self, id: &ItemId, after: Option<&ItemId>);
 
     fn get(&self, item: &ItemId) -> Option<&Self::Item>;
     fn get_mut(&mut self, item: &ItemId) -> Option<&mut Self::Item>;
 
     fn select(&self, &Filter) -> Vec<ItemId>;
 }
+
+/// Moves the item `id` to just after `after`, or to the front of `items`.
+/// Returns whether anything moved, which it doesn't if either item is
+/// missing.
+pub fn move_after(items: &mut Vec<(ItemId, ItemDesc)>, id: &ItemId, after: Option<&ItemId>)
+    -> bool {
+    let from = match items.iter().position(|me| me.0 == *id) {
+        Some(from) => from,
+        None => return false,
+    };
+    if after == Some(id) || after.is_some_and(|after| items.iter().all(|me| me.0 != *after)) {
+        return false;
+    }
+    let item = items.remove(from);
+    let to = after.map_or(0, |after| items.iter().position(|me| me.0 == *after).unwrap() + 1);
+    items.insert(to, item);
+    true
+}
+
+/// A list which is only kept in memory, handing out ids in order.
+#[derive(Debug, Default)]
+pub struct MemoryList {
+    pub items: Vec<(ItemId, ItemDesc)>,
+    next_id: u16,
+}
+
+impl From<Vec<(ItemId, ItemDesc)>> for MemoryList {
+    fn from(items: Vec<(ItemId, ItemDesc)>) -> Self {
+        let next_id = items.iter().map(|&(ItemId(id), _)| id).max().unwrap_or(0);
+        Self { items, next_id }
+    }
+}
+
+impl List for MemoryList {
+    type Item = ItemDesc;
+
+    fn add(&mut self, item: ItemDesc) -> ItemId {
+        // Carry on from the last id handed out, going round past the end
+        // to whichever ids are still free
+        let id = iter::successors(Some(ItemId(self.next_id).next()), |id| Some(id.next()))
+            .take(1 << 16)
+            .find(|id| self.items.iter().all(|me| me.0 != *id))
+            .expect("every id is in use");
+        self.next_id = id.0;
+        self.items.push((id, item));
+        id
+    }
+
+    fn insert(&mut self, id: ItemId, item: ItemDesc) {
+        self.next_id = self.next_id.max(id.0);
+        match self.items.iter_mut().find(|me| me.0 == id) {
+            Some(me) => me.1 = item,
+            None => self.items.push((id, item)),
+        }
+    }
+
+    fn remove(&mut self, item: &ItemId) {
+        self.items.retain(|&(id, _)| id != *item);
+    }
+
+    fn move_after(&mut self, id: &ItemId, after: Option<&ItemId>) {
+        move_after(&mut self.items, id, after);
+    }
+
+    fn get(&self, item: &ItemId) -> Option<&ItemDesc> {
+        self.items.iter().find(|me| me.0 == *item).map(|me| &me.1)
+    }
+
+    fn get_mut(&mut self, item: &ItemId) -> Option<&mut ItemDesc> {
+        self.items.iter_mut().find(|me| me.0 == *item).map(|me| &mut me.1)
+    }
+
+    fn select(&self, filter: &Filter) -> Vec<ItemId> {
+        self.items
+            .iter()
+            .filter(|&(id, item)| filter.matches(id, item))
+            .map(|me| me.0)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use list::{move_after, ItemDesc, ItemId, List, MemoryList, Status};
+
+    #[test]
+    fn statuses_change_one_step_at_a_time() {
+        assert!(Status::Waiting.can_become(Status::Queuing));
+        assert!(Status::Working.can_become(Status::Queuing));
+        assert!(Status::Completed.can_become(Status::Waiting));
+        assert!(Status::Queuing.can_become(Status::Queuing));
+        assert!(!Status::Waiting.can_become(Status::Completed));
+        assert!(!Status::Queuing.can_become(Status::Completed));
+        assert!(!Status::Completed.can_become(Status::Working));
+    }
+
+    #[test]
+    fn move_after_reorders_items() {
+        let mut items: Vec<(ItemId, ItemDesc)> = (1..4)
+            .map(|id| (ItemId::from(id), "deploy".parse().unwrap()))
+            .collect();
+        let order = |items: &[(ItemId, ItemDesc)]| -> Vec<u16> {
+            items.iter().map(|&(id, _)| id.into()).collect()
+        };
+
+        assert!(move_after(&mut items, &ItemId::from(1), Some(&ItemId::from(3))));
+        assert_eq!(order(&items), vec![2, 3, 1]);
+        assert!(move_after(&mut items, &ItemId::from(3), None));
+        assert_eq!(order(&items), vec![3, 2, 1]);
+        assert!(!move_after(&mut items, &ItemId::from(3), Some(&ItemId::from(9))));
+        assert!(!move_after(&mut items, &ItemId::from(9), None));
+        assert_eq!(order(&items), vec![3, 2, 1]);
+    }
+
+    #[test]
+    fn ids_go_round_past_the_last() {
+        let item = || -> ItemDesc { "deploy".parse().unwrap() };
+        let mut list = MemoryList::from(vec![
+            (ItemId::from(u16::MAX), item()),
+            (ItemId::from(0), item()),
+            (ItemId::from(2), item()),
+        ]);
+        assert_eq!(list.add(item()), ItemId::from(1));
+        assert_eq!(list.add(item()), ItemId::from(3));
+        assert_eq!(list.add(item()), ItemId::from(4));
+    }
+}
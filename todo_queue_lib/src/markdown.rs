@@ -0,0 +1,564 @@
+//! Conversion between lists and GitHub-style Markdown task lists.
+//!
+//! Each item is written as a checkbox followed by the item in the quick-add
+//! syntax of `script::item_parser`, and an HTML comment holding its id:
+//!
+//! ```markdown
+//! ## Working
+//!
+//! - [ ] deploy #ops -- push the build <!-- @00042 -->
+//!   - [x] run the tests <!-- @00043 -->
+//! ```
+//!
+//! Nested items are subtasks, which keep the id of their parent in the
+//! `parent` attribute. A description with more than one line is written
+//! below its item instead, indented to line up with the item's text.
+
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+use list::{Imported, Item, ItemDesc, ItemId, List, Status};
+use script::{self, SyntaxError};
+
+const PARENT: &str = "parent";
+const STATUSES: [Status; 4] = [
+    Status::Waiting,
+    Status::Queuing,
+    Status::Working,
+    Status::Completed,
+];
+
+/// How exported items are split up under headings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Grouping {
+    None,
+    Status,
+    Tag,
+}
+
+fn status_name(status: Status) -> String {
+    format!("{:?}", status)
+}
+
+fn parent_of<I: Item + ?Sized>(item: &I) -> Option<ItemId> {
+    item.get_attribute(PARENT)
+        .and_then(|parent| parent.trim_start_matches('@').parse().ok())
+}
+
+/// The parent an item is written under, if it's exported too. Items whose
+/// parents lead back to them have none, so they're written at the top level
+/// rather than not at all.
+fn exported_parent(items: &BTreeMap<ItemId, ItemDesc>, id: ItemId) -> Option<ItemId> {
+    let parent = parent_of(&items[&id]).filter(|parent| items.contains_key(parent))?;
+    let mut seen = HashSet::new();
+    let mut at = parent;
+    while seen.insert(at) {
+        if at == id {
+            return None;
+        }
+        match parent_of(&items[&at]).filter(|parent| items.contains_key(parent)) {
+            Some(next) => at = next,
+            None => break,
+        }
+    }
+    Some(parent)
+}
+
+/// Writes the items of `list` with the given ids as a task list.
+pub fn export<L>(list: &L, ids: &[ItemId], grouping: Grouping) -> String
+where
+    L: List,
+{
+    let items: BTreeMap<ItemId, ItemDesc> = ids.iter()
+        .filter_map(|id| list.get(id).map(|item| (*id, item.to_desc())))
+        .collect();
+    let order: Vec<ItemId> = ids.iter().filter(|id| items.contains_key(id)).cloned().collect();
+
+    // Subtasks are written under their parents when both are exported.
+    let parents: BTreeMap<ItemId, ItemId> = order
+        .iter()
+        .filter_map(|&id| exported_parent(&items, id).map(|parent| (id, parent)))
+        .collect();
+    let top_level: Vec<ItemId> = order
+        .iter()
+        .filter(|id| !parents.contains_key(id))
+        .cloned()
+        .collect();
+
+    let mut groups: Vec<(Option<String>, Vec<ItemId>)> = match grouping {
+        Grouping::None => vec![(None, top_level)],
+        Grouping::Status => STATUSES
+            .iter()
+            .map(|status| {
+                let group = top_level
+                    .iter()
+                    .filter(|id| items[id].status == *status)
+                    .cloned()
+                    .collect();
+                (Some(status_name(*status)), group)
+            })
+            .collect(),
+        Grouping::Tag => {
+            let mut groups: BTreeMap<Option<String>, Vec<ItemId>> = BTreeMap::new();
+            for id in &top_level {
+                let tag = items[id].tags.iter().min().cloned();
+                groups.entry(tag).or_default().push(*id);
+            }
+            let untagged = groups.remove(&None);
+            let mut groups: Vec<_> = groups.into_iter().collect();
+            if let Some(untagged) = untagged {
+                groups.push((Some("Untagged".into()), untagged));
+            }
+            groups
+        }
+    };
+    groups.retain(|(_, ids)| !ids.is_empty());
+
+    let mut out = String::new();
+    for (heading, ids) in groups {
+        if let Some(heading) = heading {
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            out.push_str(&format!("## {}\n\n", heading));
+        }
+        for id in ids {
+            write_item(&mut out, &items, &order, &parents, id, 0, grouping);
+        }
+    }
+    out
+}
+
+fn write_item(
+    out: &mut String,
+    items: &BTreeMap<ItemId, ItemDesc>,
+    order: &[ItemId],
+    parents: &BTreeMap<ItemId, ItemId>,
+    id: ItemId,
+    depth: usize,
+    grouping: Grouping,
+) {
+    let mut item = items[&id].clone();
+    let checked = item.status == Status::Completed;
+
+    // The checkbox or heading already says what the status is.
+    if checked || grouping == Grouping::Status && depth == 0 {
+        item.status = Status::default();
+    }
+    item.attributes.remove(PARENT);
+    let description = if item.description.contains('\n') {
+        Some(::std::mem::take(&mut item.description))
+    } else {
+        None
+    };
+
+    out.push_str(&format!(
+        "{:indent$}- [{}] {} <!-- {} -->\n",
+        "",
+        if checked { "x" } else { " " },
+        script::format_item(&item),
+        id,
+        indent = depth * 2
+    ));
+    for line in description.iter().flat_map(|description| description.trim_end().lines()) {
+        if line.trim().is_empty() {
+            out.push('\n');
+        } else {
+            out.push_str(&format!("{:indent$}{}\n", "", line, indent = depth * 2 + 2));
+        }
+    }
+
+    for child in order {
+        if parents.get(child) == Some(&id) {
+            write_item(out, items, order, parents, *child, depth + 1, grouping);
+        }
+    }
+}
+
+/// An error in a Markdown file being imported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarkdownError {
+    pub line: usize,
+    pub error: SyntaxError,
+}
+
+impl Display for MarkdownError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.error)
+    }
+}
+
+impl Error for MarkdownError {
+    fn description(&self) -> &str {
+        &self.error.message
+    }
+}
+
+struct Entry {
+    indent: usize,
+    id: Option<ItemId>,
+    item: ItemDesc,
+    heading: Option<Status>,
+}
+
+fn parse_heading(line: &str) -> Option<&str> {
+    let trimmed = line.trim_start_matches('#');
+    if trimmed.len() < line.len() && trimmed.starts_with(' ') {
+        Some(trimmed.trim())
+    } else {
+        None
+    }
+}
+
+fn parse_entry(
+    number: usize,
+    line: &str,
+    heading: Option<Status>,
+) -> Option<Result<Entry, MarkdownError>> {
+    let text = line.trim_start();
+    let indent = line.len() - text.len();
+
+    let text = text.strip_prefix("- ")
+        .or_else(|| text.strip_prefix("* "))?
+        .trim_start();
+    let checked = if text.starts_with("[ ]") {
+        false
+    } else if text.starts_with("[x]") || text.starts_with("[X]") {
+        true
+    } else {
+        return None;
+    };
+    let mut text = text[3..].trim();
+
+    let mut id = None;
+    if text.ends_with("-->") {
+        if let Some(start) = text.rfind("<!--") {
+            id = text[start + 4..text.len() - 3]
+                .trim()
+                .trim_start_matches('@')
+                .parse()
+                .ok();
+            text = text[..start].trim_end();
+        }
+    }
+
+    let offset = text.as_ptr() as usize - line.as_ptr() as usize;
+    let result = script::item_parser(text)
+        .map(|mut item| {
+            if checked {
+                item.status = Status::Completed;
+            }
+            Entry {
+                indent,
+                id,
+                item,
+                heading,
+            }
+        })
+        .map_err(|mut error| {
+            error.span = (error.span.0 + offset, error.span.1 + offset);
+            MarkdownError {
+                line: number,
+                error,
+            }
+        });
+    Some(result)
+}
+
+/// Brings `list` in line with the task list in `text`.
+///
+/// Items are matched by the id in their comment, so importing a file again
+/// after editing it only applies the edits. Items without one, or with the id
+/// of an item that's no longer in the list, are added. Items in the list but
+/// not in the file are left alone.
+pub fn import<L>(list: &mut L, text: &str) -> Result<Imported, MarkdownError>
+where
+    L: List,
+{
+    let mut entries: Vec<Entry> = vec![];
+    let mut heading = None;
+    // Whether the lines indented below the last entry are its description,
+    // whether any have been read yet, and how many blank lines came since.
+    let mut describing = false;
+    let mut described = false;
+    let mut blank_lines = 0;
+
+    for (idx, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            blank_lines += 1;
+            continue;
+        }
+        let indent = line.len() - line.trim_start().len();
+
+        if let Some(title) = parse_heading(line) {
+            heading = STATUSES
+                .iter()
+                .find(|status| status_name(**status).eq_ignore_ascii_case(title))
+                .cloned();
+            describing = false;
+        } else if let Some(entry) = parse_entry(idx + 1, line, heading) {
+            entries.push(entry?);
+            describing = true;
+            described = false;
+        } else if let Some(entry) = entries
+            .last_mut()
+            .filter(|entry| describing && indent > entry.indent)
+        {
+            // Lines are indented to match the item's text, past its "- "
+            let skip: usize = line.chars()
+                .take(entry.indent + 2)
+                .take_while(|c| c.is_whitespace())
+                .map(char::len_utf8)
+                .sum();
+            let description = &mut entry.item.description;
+            if described || !description.is_empty() {
+                description.push('\n');
+            }
+            for _ in 0..blank_lines {
+                description.push('\n');
+            }
+            description.push_str(line[skip..].trim_end());
+            described = true;
+        } else {
+            describing = false;
+        }
+        blank_lines = 0;
+    }
+
+    let mut imported = Imported::default();
+    // The ids and indents of the entries enclosing the current one.
+    let mut parents: Vec<(usize, ItemId)> = vec![];
+
+    for Entry {
+        indent,
+        id,
+        mut item,
+        heading,
+    } in entries
+    {
+        while parents.last().is_some_and(|&(parent, _)| parent >= indent) {
+            parents.pop();
+        }
+        match parents.last() {
+            Some(&(_, parent)) => {
+                item.attributes.insert(PARENT.into(), parent.to_string());
+            }
+            None => {
+                // Only top level items are written under status headings.
+                if let (Status::Waiting, Some(status)) = (item.status, heading) {
+                    if status != Status::Completed {
+                        item.status = status;
+                    }
+                }
+                item.attributes.remove(PARENT);
+            }
+        }
+
+        let existing = id.filter(|id| list.get(id).is_some());
+        let id = match existing {
+            Some(id) => {
+                let target = list.get_mut(&id).unwrap();
+                update(target, &item);
+                imported.updated.push(id);
+                id
+            }
+            None => {
+                let id = list.add(item);
+                imported.added.push(id);
+                id
+            }
+        };
+        parents.push((indent, id));
+    }
+
+    Ok(imported)
+}
+
+fn update<I: Item + ?Sized>(target: &mut I, item: &ItemDesc) {
+    target.set_name(&item.name);
+    target.set_description(&item.description);
+    target.set_status(item.status);
+
+    let old_tags: Vec<String> = target.get_tags().into_iter().map(String::from).collect();
+    for tag in old_tags {
+        if !item.tags.contains(&tag) {
+            target.set_tag(&tag, false);
+        }
+    }
+    for tag in &item.tags {
+        target.set_tag(tag, true);
+    }
+
+    if !item.attributes.contains_key(PARENT) {
+        target.set_attribute(PARENT, None);
+    }
+    for (key, value) in &item.attributes {
+        target.set_attribute(key, Some(value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use list::{Item, ItemId, List, MemoryList, Status};
+    use markdown::{export, import, Grouping};
+    use script::{item_parser, SyntaxError};
+    use query::Filter;
+
+    fn list() -> MemoryList {
+        let mut list = MemoryList::default();
+        for item in &[
+            "%work deploy #ops -- push the build",
+            "write docs #docs",
+            "%complete run the tests #ops",
+        ] {
+            list.add(item_parser(item).unwrap());
+        }
+        list
+    }
+
+    #[test]
+    fn export_grouped_by_status() {
+        let list = list();
+        let ids = list.select(&Filter::All);
+        assert_eq!(
+            export(&list, &ids, Grouping::Status),
+            "## Waiting\n\
+             \n\
+             - [ ] write docs #docs <!-- @00002 -->\n\
+             \n\
+             ## Working\n\
+             \n\
+             - [ ] deploy #ops -- push the build <!-- @00001 -->\n\
+             \n\
+             ## Completed\n\
+             \n\
+             - [x] run the tests #ops <!-- @00003 -->\n"
+        );
+        assert_eq!(
+            export(&list, &ids, Grouping::Tag),
+            "## docs\n\
+             \n\
+             - [ ] write docs #docs <!-- @00002 -->\n\
+             \n\
+             ## ops\n\
+             \n\
+             - [ ] %Working deploy #ops -- push the build <!-- @00001 -->\n\
+             - [x] run the tests #ops <!-- @00003 -->\n"
+        );
+    }
+
+    #[test]
+    fn import_is_idempotent() {
+        let mut list = list();
+        let ids = list.select(&Filter::All);
+        let text = export(&list, &ids, Grouping::Status);
+
+        let imported = import(&mut list, &text).unwrap();
+        assert!(imported.added.is_empty());
+        assert_eq!(imported.updated.len(), 3);
+        assert_eq!(export(&list, &ids, Grouping::Status), text);
+
+        let edited = text.replace("- [ ] write docs #docs", "- [x] write docs #docs #later")
+            .replace(
+                "- [ ] deploy #ops -- push the build <!-- @00001 -->\n",
+                "- [ ] deploy #ops -- push the build <!-- @00001 -->\n  \
+                 - [ ] tag the release #ops\n",
+            );
+        let imported = import(&mut list, &edited).unwrap();
+        assert_eq!(imported.added.len(), 1);
+        let docs = list.get(&ids[1]).unwrap();
+        assert_eq!(*docs.get_status(), Status::Completed);
+        assert!(docs.has_tag("later"));
+
+        let subtask = list.get(&imported.added[0]).unwrap();
+        assert_eq!(subtask.get_attribute("parent"), Some("@00001"));
+        assert_eq!(*subtask.get_status(), Status::Waiting);
+
+        let text = export(&list, &list.select(&Filter::All), Grouping::Status);
+        assert!(import(&mut list, &text).unwrap().added.is_empty());
+        assert_eq!(list.items.len(), 4);
+    }
+
+    #[test]
+    fn multi_line_descriptions_round_trip() {
+        let mut list = list();
+        list.get_mut(&ItemId::from(1))
+            .unwrap()
+            .set_description("push the build\n\n  then tag it\nand announce it");
+        let ids = list.select(&Filter::All);
+        let text = export(&list, &ids, Grouping::None);
+        assert_eq!(
+            text,
+            "- [ ] %Working deploy #ops <!-- @00001 -->\n  \
+             push the build\n\
+             \n  \
+             \x20 then tag it\n  \
+             and announce it\n\
+             - [ ] write docs #docs <!-- @00002 -->\n\
+             - [x] run the tests #ops <!-- @00003 -->\n"
+        );
+
+        let mut imported = MemoryList::default();
+        import(&mut imported, &text).unwrap();
+        assert_eq!(
+            imported.get(&ItemId::from(1)).unwrap().get_description(),
+            "push the build\n\n  then tag it\nand announce it"
+        );
+        import(&mut list, &text).unwrap();
+        assert_eq!(export(&list, &ids, Grouping::None), text);
+
+        import(&mut list, "- [ ] write docs -- first\n  second\n\nnot a description\n").unwrap();
+        assert_eq!(list.get(&ItemId::from(4)).unwrap().get_description(), "first\nsecond");
+    }
+
+    #[test]
+    fn import_matches_only_by_id() {
+        let mut list = list();
+        let imported = import(&mut list, "- [x] write docs -- for the release\n").unwrap();
+        assert_eq!(imported.added, vec![ItemId::from(4)]);
+        assert!(imported.updated.is_empty());
+        let docs = list.get(&ItemId::from(2)).unwrap();
+        assert_eq!(*docs.get_status(), Status::Waiting);
+        assert_eq!(docs.get_description(), "");
+    }
+
+    #[test]
+    fn export_items_in_parent_cycles() {
+        let mut list = list();
+        list.get_mut(&ItemId::from(1)).unwrap().set_attribute("parent", Some("@00001"));
+        list.get_mut(&ItemId::from(2)).unwrap().set_attribute("parent", Some("@00003"));
+        list.get_mut(&ItemId::from(3)).unwrap().set_attribute("parent", Some("@00002"));
+        let ids = list.select(&Filter::All);
+        assert_eq!(
+            export(&list, &ids, Grouping::None),
+            "- [ ] %Working deploy #ops -- push the build <!-- @00001 -->\n\
+             - [ ] write docs #docs <!-- @00002 -->\n\
+             - [x] run the tests #ops <!-- @00003 -->\n"
+        );
+
+        let id = list.add(item_parser("tag the release parent:@00002").unwrap());
+        assert!(export(&list, &[ItemId::from(2), id], Grouping::None)
+            .ends_with("\n  - [ ] tag the release <!-- @00004 -->\n"));
+    }
+
+    #[test]
+    fn import_keeps_other_attributes() {
+        let mut list = list();
+        list.get_mut(&ItemId::from(2)).unwrap().set_attribute("parent", Some("@00001"));
+        list.get_mut(&ItemId::from(2)).unwrap().set_attribute("due", Some("2026-11-01"));
+        import(&mut list, "- [ ] write docs #docs <!-- @00002 -->\n").unwrap();
+
+        let docs = list.get(&ItemId::from(2)).unwrap();
+        assert_eq!(docs.get_attribute("due"), Some("2026-11-01"));
+        assert_eq!(docs.get_attribute("parent"), None);
+    }
+
+    #[test]
+    fn import_reports_lines() {
+        let mut list = MemoryList::default();
+        let err = import(&mut list, "# Plan\n\n- [ ] ok\n- [ ] say \"hi\n").unwrap_err();
+        assert_eq!(err.line, 4);
+        assert_eq!(err.error, SyntaxError::new((10, 13), "unterminated quote"));
+    }
+}
@@ -0,0 +1,297 @@
+//! Merging two copies of a list which went their own ways from a common
+//! ancestor.
+//!
+//! Items are matched up by id, and then merged field by field, so that one
+//! side renaming an item and the other completing it both make it into the
+//! merge, however the list's file happens to lay them out.
+
+use std::collections::BTreeSet;
+use std::fmt::{self, Display, Formatter};
+
+use list::{Item, ItemDesc, ItemId, List};
+use query::Filter;
+
+/// A change the merge couldn't reconcile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Conflict {
+    /// Both sides changed a field of an item differently. Ours was kept.
+    Field {
+        id: ItemId,
+        field: String,
+        ours: String,
+        theirs: String,
+    },
+    /// One side removed an item which the other changed. The changed item
+    /// was kept.
+    Removed { id: ItemId, by_us: bool },
+}
+
+impl Conflict {
+    pub fn id(&self) -> ItemId {
+        match *self {
+            Conflict::Field { id, .. } | Conflict::Removed { id, .. } => id,
+        }
+    }
+}
+
+impl Display for Conflict {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            Conflict::Field {
+                id,
+                ref field,
+                ref ours,
+                ref theirs,
+            } => write!(
+                f,
+                "{} {} was changed on both sides, kept '{}' over '{}'",
+                id, field, ours, theirs
+            ),
+            Conflict::Removed { id, by_us: true } => {
+                write!(f, "{} was removed here but changed there, kept theirs", id)
+            }
+            Conflict::Removed { id, by_us: false } => {
+                write!(f, "{} was changed here but removed there, kept ours", id)
+            }
+        }
+    }
+}
+
+/// The result of a merge.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Merged {
+    pub items: Vec<(ItemId, ItemDesc)>,
+    pub conflicts: Vec<Conflict>,
+}
+
+fn descs<L: List>(list: &L) -> Vec<(ItemId, ItemDesc)> {
+    list.select(&Filter::All)
+        .into_iter()
+        .filter_map(|id| list.get(&id).map(|item| (id, item.to_desc())))
+        .collect()
+}
+
+fn find(items: &[(ItemId, ItemDesc)], id: ItemId) -> Option<&ItemDesc> {
+    items.iter().find(|me| me.0 == id).map(|me| &me.1)
+}
+
+/// Takes whichever side changed a value from `base`, or `None` when both
+/// changed it differently.
+fn pick<'a, T: PartialEq + ?Sized>(base: Option<&T>, ours: &'a T, theirs: &'a T) -> Option<&'a T> {
+    if ours == theirs || base == Some(theirs) {
+        Some(ours)
+    } else if base == Some(ours) {
+        Some(theirs)
+    } else {
+        None
+    }
+}
+
+/// Merges `ours` and `theirs`, which both started out as `base`. Whatever
+/// only one side changed is taken from that side, and items added on
+/// either side are kept. Items keep the order they have in `ours`,
+/// followed by the ones only `theirs` has.
+pub fn merge<B, O, T>(base: &B, ours: &O, theirs: &T) -> Merged
+where
+    B: List,
+    O: List,
+    T: List,
+{
+    let (base, ours, theirs) = (descs(base), descs(ours), descs(theirs));
+    let mut ids: Vec<ItemId> = ours.iter().map(|me| me.0).collect();
+    for &(id, _) in theirs.iter().chain(&base) {
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+
+    let mut merged = Merged::default();
+    for id in ids {
+        let old = find(&base, id);
+        let item = match (find(&ours, id), find(&theirs, id)) {
+            (Some(mine), Some(other)) => Some(merge_item(id, old, mine, other, &mut merged.conflicts)),
+            (Some(mine), None) if old.is_none() => Some(mine.clone()),
+            (None, Some(other)) if old.is_none() => Some(other.clone()),
+            // Removed on one side, so kept only if the other side changed it
+            (Some(mine), None) if Some(mine) != old => {
+                merged.conflicts.push(Conflict::Removed { id, by_us: false });
+                Some(mine.clone())
+            }
+            (None, Some(other)) if Some(other) != old => {
+                merged.conflicts.push(Conflict::Removed { id, by_us: true });
+                Some(other.clone())
+            }
+            _ => None,
+        };
+        if let Some(item) = item {
+            merged.items.push((id, item));
+        }
+    }
+    merged
+}
+
+/// Merges an item both sides still have, field by field.
+fn merge_item(
+    id: ItemId,
+    base: Option<&ItemDesc>,
+    ours: &ItemDesc,
+    theirs: &ItemDesc,
+    conflicts: &mut Vec<Conflict>,
+) -> ItemDesc {
+    let mut item = ours.clone();
+    {
+        let mut field = |name: &str, ours: String, theirs: String| {
+            conflicts.push(Conflict::Field {
+                id,
+                field: name.into(),
+                ours,
+                theirs,
+            })
+        };
+
+        match pick(base.map(|item| item.name.as_str()), &ours.name, &theirs.name) {
+            Some(name) => item.name = name.into(),
+            None => field("name", ours.name.clone(), theirs.name.clone()),
+        }
+        match pick(
+            base.map(|item| item.description.as_str()),
+            &ours.description,
+            &theirs.description,
+        ) {
+            Some(description) => item.description = description.into(),
+            None => field("description", ours.description.clone(), theirs.description.clone()),
+        }
+        match pick(base.map(|item| &item.status), &ours.status, &theirs.status) {
+            Some(&status) => item.status = status,
+            None => field("status", ours.status.to_string(), theirs.status.to_string()),
+        }
+
+        let keys: BTreeSet<&String> = ours.attributes.keys().chain(theirs.attributes.keys()).collect();
+        for key in keys {
+            let (mine, other) = (ours.attributes.get(key), theirs.attributes.get(key));
+            let old = base.map(|item| item.attributes.get(key));
+            match pick(old.as_ref(), &mine, &other) {
+                Some(&Some(value)) => item.set_attribute(key, Some(value)),
+                Some(&None) => item.set_attribute(key, None),
+                None => field(
+                    &format!("attribute {}", key),
+                    mine.cloned().unwrap_or_default(),
+                    other.cloned().unwrap_or_default(),
+                ),
+            }
+        }
+    }
+
+    // A tag is kept if neither side removed it, or if either side added it
+    let had = |tag: &String| base.is_some_and(|base| base.tags.contains(tag));
+    item.tags = ours.tags
+        .iter()
+        .filter(|tag| theirs.tags.contains(tag) || !had(tag))
+        .chain(theirs.tags.iter().filter(|tag| !ours.tags.contains(tag) && !had(tag)))
+        .cloned()
+        .collect();
+
+    item.created = ours.created.into_iter().chain(theirs.created).min();
+    item.modified = ours.modified.into_iter().chain(theirs.modified).max();
+    let mut transitions = ours.transitions.clone();
+    for transition in &theirs.transitions {
+        if !transitions.contains(transition) {
+            transitions.push(transition.clone());
+        }
+    }
+    transitions.sort_by_key(|transition| transition.at);
+    item.transitions = transitions;
+    item
+}
+
+#[cfg(test)]
+mod tests {
+    use list::{Item, ItemId, List, MemoryList, Status};
+    use merge::{merge, Conflict};
+
+    #[test]
+    fn merges_by_id() {
+        let mut base = MemoryList::default();
+        let deploy = base.add("deploy".parse().unwrap());
+        let docs = base.add("write docs".parse().unwrap());
+        let review = base.add("review".parse().unwrap());
+
+        let mut ours = MemoryList::from(base.items.clone());
+        ours.get_mut(&deploy).unwrap().set_status(Status::Completed);
+        ours.remove(&review);
+        let ours_added = ours.add("release".parse().unwrap());
+
+        let mut theirs = MemoryList::from(base.items.clone());
+        theirs.get_mut(&docs).unwrap().set_tag("docs", true);
+        let theirs_added = ItemId::from(100);
+        theirs.insert(theirs_added, "blog post".parse().unwrap());
+
+        let merged = merge(&base, &ours, &theirs);
+        assert!(merged.conflicts.is_empty());
+        let ids: Vec<_> = merged.items.iter().map(|me| me.0).collect();
+        assert_eq!(ids, vec![deploy, docs, ours_added, theirs_added]);
+        assert_eq!(merged.items[0].1.status, Status::Completed);
+        assert!(merged.items[1].1.has_tag("docs"));
+    }
+
+    #[test]
+    fn merges_fields() {
+        let mut base = MemoryList::default();
+        let deploy = base.add("deploy #ops #urgent".parse().unwrap());
+
+        let mut ours = MemoryList::from(base.items.clone());
+        {
+            let item = ours.get_mut(&deploy).unwrap();
+            item.set_name("deploy to prod");
+            item.set_tag("urgent", false);
+            item.set_attribute("owner", Some("sam"));
+        }
+
+        let mut theirs = MemoryList::from(base.items.clone());
+        {
+            let item = theirs.get_mut(&deploy).unwrap();
+            item.set_status(Status::Working);
+            item.set_tag("release", true);
+            item.set_attribute("due", Some("friday"));
+        }
+
+        let merged = merge(&base, &ours, &theirs);
+        assert!(merged.conflicts.is_empty());
+        let item = &merged.items[0].1;
+        assert_eq!(item.name, "deploy to prod");
+        assert_eq!(item.status, Status::Working);
+        assert_eq!(item.get_tags(), vec!["ops", "release"]);
+        assert_eq!(item.get_attributes(), vec![("due", "friday"), ("owner", "sam")]);
+    }
+
+    #[test]
+    fn reports_conflicts() {
+        let mut base = MemoryList::default();
+        let deploy = base.add("deploy".parse().unwrap());
+        let docs = base.add("write docs".parse().unwrap());
+
+        let mut ours = MemoryList::from(base.items.clone());
+        ours.get_mut(&deploy).unwrap().set_name("deploy to prod");
+        ours.remove(&docs);
+
+        let mut theirs = MemoryList::from(base.items.clone());
+        theirs.get_mut(&deploy).unwrap().set_name("deploy to staging");
+        theirs.get_mut(&docs).unwrap().set_status(Status::Working);
+
+        let merged = merge(&base, &ours, &theirs);
+        assert_eq!(
+            merged.conflicts,
+            vec![
+                Conflict::Field {
+                    id: deploy,
+                    field: "name".into(),
+                    ours: "deploy to prod".into(),
+                    theirs: "deploy to staging".into(),
+                },
+                Conflict::Removed { id: docs, by_us: true },
+            ]
+        );
+        assert_eq!(merged.items[0].1.name, "deploy to prod");
+        assert_eq!(merged.items[1].1.status, Status::Working);
+    }
+}
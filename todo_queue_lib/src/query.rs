@@ -1,28 +1,66 @@
 use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, Not};
+use std::fmt::{self, Display, Formatter};
 use std::mem;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::de::Error;
 
-use range::Range;
+use range::{Comparison, Range, RangeSet};
 use list::{Item, ItemId, List};
 use list::Status;
+use time::Timestamp;
 
-#[derive(Debug, PartialEq, Eq)]
+/// A condition on items.
+///
+/// Filters serialize to JSON as `"all"`, `"none"`, or an object with a single
+/// key naming the kind of filter:
+///
+/// * `{"id": 42}`
+/// * `{"status": range}`, where `range` is a `RangeSet` such as `"Working"`,
+///   `{"gte": "Queuing"}` or `[{"eq": "Waiting"}, {"eq": "Completed"}]`
+/// * `{"created": range}`, `{"modified": range}` and `{"completed": range}`,
+///   where `range` is a `RangeSet` of timestamps such as
+///   `{"gte": "2026-10-01T00:00:00Z"}`
+/// * `{"tag": "x"}` and `{"name": "x"}`
+/// * `{"archived": true}`, for items which were moved to a list's archive
+/// * `{"and": [filter, ...]}`, `{"or": [filter, ...]}` and `{"not": filter}`
+///
+/// For example `#x & status >= %queue` is
+/// `{"and": [{"tag": "x"}, {"status": {"gte": "Queuing"}}]}`.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Filter {
     All,
     None,
     Id(ItemId),
-    Status(Range<Status>),
+    Status(RangeSet<Status>),
+    Created(RangeSet<Timestamp>),
+    Modified(RangeSet<Timestamp>),
+    Completed(RangeSet<Timestamp>),
     Tag(String),
     Name(String),
+    Archived(bool),
     And(Vec<Filter>),
     Or(Vec<Filter>),
     Not(Box<Filter>),
 }
 
 impl Filter {
-    pub fn status<S: Into<Range<Status>>>(status: S) -> Self {
+    pub fn status<S: Into<RangeSet<Status>>>(status: S) -> Self {
         Filter::Status(status.into())
     }
 
+    pub fn created<R: Into<RangeSet<Timestamp>>>(range: R) -> Self {
+        Filter::Created(range.into())
+    }
+
+    pub fn modified<R: Into<RangeSet<Timestamp>>>(range: R) -> Self {
+        Filter::Modified(range.into())
+    }
+
+    pub fn completed<R: Into<RangeSet<Timestamp>>>(range: R) -> Self {
+        Filter::Completed(range.into())
+    }
+
     pub fn tag<T: Into<String>>(tag: T) -> Self {
         Filter::Tag(tag.into())
     }
@@ -34,6 +72,18 @@ impl Filter {
     pub fn id<I: Into<ItemId>>(id: I) -> Self {
         Filter::Id(id.into())
     }
+
+    /// Builds the filter for a comparison on any ordered field, where `field`
+    /// makes the filter matching a range of that field's values.
+    pub fn compare<T, F>(comparison: Comparison<T>, field: F) -> Self
+    where
+        F: FnOnce(Range<T>) -> Filter,
+    {
+        match comparison {
+            Comparison::Within(range) => field(range),
+            Comparison::Outside(range) => !field(range),
+        }
+    }
 }
 
 impl Not for Filter {
@@ -47,6 +97,11 @@ impl BitAndAssign for Filter {
     fn bitand_assign(&mut self, other: Filter) {
         use self::Filter::*;
 
+        if let (Status(me), Status(other)) = (&mut *self, &other) {
+            *me = me.intersection(other);
+            return;
+        }
+
         if let And(ref mut me) = *self {
             if let And(other) = other {
                 me.extend(other);
@@ -75,6 +130,11 @@ impl BitOrAssign for Filter {
     fn bitor_assign(&mut self, other: Filter) {
         use self::Filter::*;
 
+        if let (Status(me), Status(other)) = (&mut *self, &other) {
+            *me = me.union(other);
+            return;
+        }
+
         if let Or(ref mut me) = *self {
             if let Or(other) = other {
                 me.extend(other);
@@ -101,26 +161,203 @@ impl BitOr for Filter {
 
 impl Filter {
     pub fn matches(&self, item_id: &ItemId, item: &Item) -> bool {
+        self.matches_where(item_id, item, false)
+    }
+
+    /// Like `matches`, for an item which was moved to a list's archive.
+    pub fn matches_archived(&self, item_id: &ItemId, item: &dyn Item) -> bool {
+        self.matches_where(item_id, item, true)
+    }
+
+    fn matches_where(&self, item_id: &ItemId, item: &dyn Item, archived: bool) -> bool {
         use self::Filter::*;
         match *self {
             All => true,
             None => false,
             Status(ref status) => status.contains(item.get_status()),
+            Created(ref range) => item.get_created().is_some_and(|at| range.contains(at)),
+            Modified(ref range) => item.get_modified().is_some_and(|at| range.contains(at)),
+            Completed(ref range) => item.get_completed().is_some_and(|at| range.contains(at)),
             Tag(ref tag) => item.has_tag(tag),
             Name(ref name) => name == item.get_name(),
-            And(ref all) => all.iter().all(|cond| cond.matches(item_id, item)),
-            Or(ref any) => any.iter().any(|cond| cond.matches(item_id, item)),
-            Not(ref cond) => !cond.matches(item_id, item),
+            Archived(want) => want == archived,
+            And(ref all) => all.iter().all(|cond| cond.matches_where(item_id, item, archived)),
+            Or(ref any) => any.iter().any(|cond| cond.matches_where(item_id, item, archived)),
+            Not(ref cond) => !cond.matches_where(item_id, item, archived),
             Id(ref id) => item_id == id,
         }
     }
+
+    /// Whether the filter says anything about archived items, which are
+    /// otherwise left out.
+    pub fn mentions_archived(&self) -> bool {
+        match *self {
+            Filter::Archived(_) => true,
+            _ => self.children().iter().any(Filter::mentions_archived),
+        }
+    }
+
+    /// Evaluates the filter like `matches`, but records the result of every
+    /// sub-filter instead of short-circuiting.
+    pub fn trace<'a>(&'a self, item_id: &ItemId, item: &dyn Item) -> Trace<'a> {
+        use self::Filter::*;
+        let children: Vec<_> = match *self {
+            And(ref filters) | Or(ref filters) => filters
+                .iter()
+                .map(|cond| cond.trace(item_id, item))
+                .collect(),
+            Not(ref cond) => vec![cond.trace(item_id, item)],
+            _ => vec![],
+        };
+
+        let matched = match *self {
+            And(_) => children.iter().all(|trace| trace.matched),
+            Or(_) => children.iter().any(|trace| trace.matched),
+            Not(_) => !children[0].matched,
+            _ => self.matches(item_id, item),
+        };
+
+        Trace {
+            filter: self,
+            matched,
+            children,
+        }
+    }
+
+    /// Returns a value which displays this filter as an indented tree.
+    pub fn tree<'a>(&'a self) -> Tree<'a> {
+        Tree(self)
+    }
+
+    fn children(&self) -> &[Filter] {
+        use self::Filter::*;
+        match *self {
+            And(ref filters) | Or(ref filters) => filters,
+            Not(ref cond) => ::std::slice::from_ref(cond.as_ref()),
+            _ => &[],
+        }
+    }
+
+    fn fmt_node(&self, f: &mut Formatter) -> fmt::Result {
+        use self::Filter::*;
+        match *self {
+            And(_) => write!(f, "and"),
+            Or(_) => write!(f, "or"),
+            Not(_) => write!(f, "not"),
+            _ => write!(f, "{}", self),
+        }
+    }
+}
+
+impl Display for Filter {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        use self::Filter::*;
+
+        fn join(f: &mut Formatter, filters: &[Filter], sep: &str) -> fmt::Result {
+            write!(f, "(")?;
+            for (idx, filter) in filters.iter().enumerate() {
+                if idx > 0 {
+                    write!(f, "{}", sep)?;
+                }
+                write!(f, "{}", filter)?;
+            }
+            write!(f, ")")
+        }
+
+        match *self {
+            All => write!(f, "all"),
+            None => write!(f, "none"),
+            Id(ref id) => write!(f, "{}", id),
+            Status(ref status) => write!(f, "{}", status),
+            Created(ref range) => write!(f, "created in {}", range),
+            Modified(ref range) => write!(f, "modified in {}", range),
+            Completed(ref range) => write!(f, "completed in {}", range),
+            Tag(ref tag) => write!(f, "#{}", tag),
+            Name(ref name) => write!(f, "{}", name),
+            Archived(true) => write!(f, "archived:yes"),
+            Archived(false) => write!(f, "archived:no"),
+            And(ref all) => join(f, all, " & "),
+            Or(ref any) => join(f, any, " | "),
+            Not(ref cond) => write!(f, "!{}", cond),
+        }
+    }
+}
+
+/// Displays a `Filter` as an indented tree, one sub-filter per line.
+pub struct Tree<'a>(&'a Filter);
+
+impl<'a> Display for Tree<'a> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        fn write_tree(f: &mut Formatter, filter: &Filter, depth: usize) -> fmt::Result {
+            write!(f, "{:1$}", "", depth * 2)?;
+            filter.fmt_node(f)?;
+            writeln!(f)?;
+            for child in filter.children() {
+                write_tree(f, child, depth + 1)?;
+            }
+            Ok(())
+        }
+
+        write_tree(f, self.0, 0)
+    }
+}
+
+/// The result of evaluating a `Filter` against one item, along with the
+/// results of all of its sub-filters.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Trace<'a> {
+    pub filter: &'a Filter,
+    pub matched: bool,
+    pub children: Vec<Trace<'a>>,
+}
+
+impl<'a> Display for Trace<'a> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        fn write_trace(f: &mut Formatter, trace: &Trace, depth: usize) -> fmt::Result {
+            write!(
+                f,
+                "{:2$}{} ",
+                "",
+                if trace.matched { "✓" } else { "✗" },
+                depth * 2
+            )?;
+            trace.filter.fmt_node(f)?;
+            writeln!(f)?;
+            for child in &trace.children {
+                write_trace(f, child, depth + 1)?;
+            }
+            Ok(())
+        }
+
+        write_trace(f, self, 0)
+    }
 }
 
+/// A sequence of filters whose selections are joined together.
+///
+/// Serialized as the non-empty array of its filters, so `a => b` is
+/// `[a, b]`.
 #[derive(Debug, PartialEq, Eq)]
 pub struct Query {
     joins: Vec<Filter>,
 }
 
+impl Serialize for Query {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.joins.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Query {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let joins = Vec::<Filter>::deserialize(deserializer)?;
+        if joins.is_empty() {
+            return Err(D::Error::invalid_length(0, &"at least one filter"));
+        }
+        Ok(Query { joins })
+    }
+}
+
 impl From<Filter> for Query {
     fn from(filter: Filter) -> Query {
         Query {
@@ -129,7 +366,23 @@ impl From<Filter> for Query {
     }
 }
 
+impl Display for Query {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for (idx, filter) in self.joins.iter().enumerate() {
+            if idx > 0 {
+                write!(f, " => ")?;
+            }
+            write!(f, "{}", filter)?;
+        }
+        Ok(())
+    }
+}
+
 impl Query {
+    pub fn filters(&self) -> &[Filter] {
+        &self.joins
+    }
+
     pub fn then(self, filter: Filter) -> Self {
         let Self { mut joins } = self;
         joins.push(filter);
@@ -145,4 +398,153 @@ impl Query {
             .flat_map(|filter| list.select(filter))
             .collect()
     }
+
+    /// Like `select`, for a list's archive.
+    pub fn select_archived<L>(&self, archive: &L) -> Vec<ItemId>
+    where
+        L: List,
+        L::Item: Sized,
+    {
+        let ids = archive.select(&Filter::All);
+        self.joins
+            .iter()
+            .flat_map(|filter| {
+                ids.iter()
+                    .filter(|id| archive.get(id).is_some_and(|item| filter.matches_archived(id, item)))
+                    .cloned()
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// Whether the query asks for archived items with `archived:yes` or
+    /// `archived:no`.
+    pub fn mentions_archived(&self) -> bool {
+        self.joins.iter().any(Filter::mentions_archived)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+    use list::{ItemDesc, ItemId, List, MemoryList};
+    use query::{Filter, Query};
+    use script::query_parser;
+
+    #[test]
+    fn trace_records_every_sub_filter() {
+        let item = ItemDesc::new("deploy", "", vec!["ops"]);
+        let id = ItemId::from(42);
+        let filter = Filter::name("deploy") & !Filter::tag("ops");
+
+        let trace = filter.trace(&id, &item);
+        assert!(!trace.matched);
+        assert_eq!(trace.matched, filter.matches(&id, &item));
+        assert_eq!(
+            trace.children.iter().map(|t| t.matched).collect::<Vec<_>>(),
+            vec![true, false]
+        );
+        assert_eq!(trace.children[1].children[0].filter, &Filter::tag("ops"));
+        assert!(trace.children[1].children[0].matched);
+
+        assert_eq!(
+            (Filter::name("deploy") | !Filter::tag("ops")).to_string(),
+            "(deploy | !#ops)"
+        );
+    }
+
+    #[test]
+    fn time_filters() {
+        let mut item: ItemDesc = "%complete deploy".parse().unwrap();
+        item.stamp(None, "2026-10-07T18:00:00Z".parse().unwrap());
+        let id = ItemId::from(1);
+        let matches = |text: &str| {
+            query_parser(text).unwrap().filters()[0].matches(&id, &item)
+        };
+
+        assert!(matches("completed in [2026-10-01...2026-10-07]"));
+        assert!(!matches("completed in [2026-10-01...2026-10-07)"));
+        assert!(matches("created <= 2026-10-07 & modified > 2026-10-06T12:00"));
+        assert!(!matches("created < 7d"));
+        assert!(matches("created > 7d"));
+        assert!(query_parser("created < 2026-13-01").is_err());
+
+        let query = query_parser("completed in (2026-10-01...]").unwrap();
+        let filter = &query.filters()[0];
+        assert_eq!(filter.to_string(), "completed in [2026-10-02T00:00:00Z ...)");
+        assert_eq!(
+            serde_json::to_string(filter).unwrap(),
+            r#"{"completed":{"gte":"2026-10-02T00:00:00Z"}}"#
+        );
+        assert_eq!(&query_parser(&filter.to_string()).unwrap().filters()[0], filter);
+    }
+
+    #[test]
+    fn archived_items() {
+        let mut archive = MemoryList::default();
+        let deploy = archive.add("%complete deploy #ops".parse().unwrap());
+        archive.add("%complete write docs".parse().unwrap());
+
+        let query = query_parser("#ops").unwrap();
+        assert!(!query.mentions_archived());
+        assert_eq!(query.select_archived(&archive), vec![deploy]);
+
+        let query = query_parser("#ops & archived:yes").unwrap();
+        assert!(query.mentions_archived());
+        assert_eq!(query.select_archived(&archive), vec![deploy]);
+        assert!(query.select(&archive).is_empty());
+        assert!(query_parser("archived:no").unwrap().select_archived(&archive).is_empty());
+    }
+
+    #[test]
+    fn json_matches_text_syntax() {
+        let cases = vec![
+            (
+                r#"{"and": [{"tag": "x"}, {"status": {"gte": "Queuing"}}]}"#,
+                "#x & status >= %queue",
+            ),
+            (r#""all""#, "all"),
+            (r#"{"archived": true}"#, "archived:yes"),
+            (r#"{"not": {"name": "hello"}}"#, "!hello"),
+            (r#"{"or": [{"id": 123}, {"status": "Working"}]}"#, "@123 %work"),
+            (
+                r#"{"status": [{"eq": "Waiting"}, {"eq": "Completed"}]}"#,
+                "%wait | %complete",
+            ),
+            (
+                r#"{"status": {"gt": "Waiting", "lte": "Completed"}}"#,
+                "( %Waiting ... %Completed ]",
+            ),
+        ];
+
+        for (json, text) in cases {
+            let parsed = query_parser(text).unwrap();
+            let filter: Filter = serde_json::from_str(json).unwrap();
+            assert_eq!(Query::from(filter), parsed, "{}", text);
+
+            let round_trip: Query =
+                serde_json::from_str(&serde_json::to_string(&parsed).unwrap()).unwrap();
+            assert_eq!(round_trip, parsed, "{}", text);
+        }
+
+        assert_eq!(
+            serde_json::to_string(&query_parser("#x => status < %work").unwrap()).unwrap(),
+            r#"[{"tag":"x"},{"status":{"lt":"Working"}}]"#
+        );
+        assert!(serde_json::from_str::<Query>("[]").is_err());
+        assert!(
+            serde_json::from_str::<Filter>(r#"{"status": {"gt": "Waiting", "gte": "Waiting"}}"#)
+                .is_err()
+        );
+        assert!(
+            serde_json::from_str::<Filter>(r#"{"status": {"eq": "Waiting", "lt": "Working"}}"#)
+                .is_err()
+        );
+        // A misspelled limit mustn't leave a range which matches everything
+        assert!(serde_json::from_str::<Filter>(r#"{"status": {"gtee": "Queuing"}}"#).is_err());
+        let created = r#"{"created": [{"lte": "2026-10-01T00:00:00Z"}, {"gt": "2026-10-09T00:00:00Z"}]}"#;
+        assert!(serde_json::from_str::<Filter>(created).is_ok());
+        let created = created.replace(r#""gt""#, r#""after""#);
+        assert!(serde_json::from_str::<Filter>(&created).is_err());
+    }
 }
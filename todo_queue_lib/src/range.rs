@@ -1,7 +1,12 @@
-use std::ops::{Add, Mul};
-use std::cmp::PartialOrd;
+use std::ops::{Add, BitAnd, BitOr, Mul, Not};
+use std::cmp::{Ordering, PartialOrd};
+use std::fmt::{self, Display, Formatter};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::de::Error;
 
-#[derive(Debug, PartialEq, Eq)]
+/// Serialized as `{"includes": v}`, `{"excludes": v}` or `"inf"`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Limit<T> {
     Includes(T),
     Excludes(T),
@@ -58,12 +63,106 @@ impl<T> Limit<T> {
     }
 }
 
+/// A comparison of a field against a range of values, as written in a query
+/// (`status >= %queue`, `status != %wait`).
 #[derive(Debug, PartialEq, Eq)]
+pub enum Comparison<T> {
+    Within(Range<T>),
+    Outside(Range<T>),
+}
+
+/// Serialized as `{"eq": v}`, or as an object holding at most one of `gt` and
+/// `gte` and at most one of `lt` and `lte`, such as `{"gte": v, "lt": w}`. A
+/// missing limit is infinite, so `{}` is the range of every value.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Range<T> {
     low: Limit<T>,
     high: Limit<T>,
 }
 
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RangeRepr<T> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    eq: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    gt: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    gte: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lt: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lte: Option<T>,
+}
+
+impl<T> Serialize for Range<T>
+where
+    T: Serialize + PartialEq,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut repr = RangeRepr {
+            eq: None,
+            gt: None,
+            gte: None,
+            lt: None,
+            lte: None,
+        };
+
+        match (&self.low, &self.high) {
+            (Limit::Includes(low), Limit::Includes(high)) if low == high => repr.eq = Some(low),
+            (low, high) => {
+                match low {
+                    Limit::Includes(low) => repr.gte = Some(low),
+                    Limit::Excludes(low) => repr.gt = Some(low),
+                    Limit::Inf => {}
+                }
+                match high {
+                    Limit::Includes(high) => repr.lte = Some(high),
+                    Limit::Excludes(high) => repr.lt = Some(high),
+                    Limit::Inf => {}
+                }
+            }
+        }
+
+        repr.serialize(serializer)
+    }
+}
+
+impl<'de, T> Deserialize<'de> for Range<T>
+where
+    T: Deserialize<'de> + Clone,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = RangeRepr::<T>::deserialize(deserializer)?;
+
+        if let Some(value) = repr.eq {
+            if repr.gt.is_some() || repr.gte.is_some() || repr.lt.is_some() || repr.lte.is_some()
+            {
+                return Err(D::Error::custom("`eq` cannot be combined with other limits"));
+            }
+            return Ok(Range {
+                low: Limit::Includes(value.clone()),
+                high: Limit::Includes(value),
+            });
+        }
+
+        let low = match (repr.gt, repr.gte) {
+            (Some(_), Some(_)) => return Err(D::Error::custom("both `gt` and `gte` were given")),
+            (Some(low), None) => Limit::Excludes(low),
+            (None, Some(low)) => Limit::Includes(low),
+            (None, None) => Limit::Inf,
+        };
+        let high = match (repr.lt, repr.lte) {
+            (Some(_), Some(_)) => return Err(D::Error::custom("both `lt` and `lte` were given")),
+            (Some(high), None) => Limit::Excludes(high),
+            (None, Some(high)) => Limit::Includes(high),
+            (None, None) => Limit::Inf,
+        };
+
+        Ok(Range { low, high })
+    }
+}
+
 impl<T> From<T> for Range<T>
 where
     T: PartialOrd + Clone,
@@ -83,8 +182,25 @@ where
         T: Clone,
     {
         let value = o.into();
-        Self::new(Limit::Includes(value.clone()), Limit::Excludes(value))
+        Self::new(Limit::Includes(value.clone()), Limit::Includes(value))
+    }
+
+    pub fn gt<O: Into<T>>(o: O) -> Self {
+        Self::new(Limit::Excludes(o.into()), Limit::Inf)
+    }
+
+    pub fn gte<O: Into<T>>(o: O) -> Self {
+        Self::new(Limit::Includes(o.into()), Limit::Inf)
     }
+
+    pub fn lt<O: Into<T>>(o: O) -> Self {
+        Self::new(Limit::Inf, Limit::Excludes(o.into()))
+    }
+
+    pub fn lte<O: Into<T>>(o: O) -> Self {
+        Self::new(Limit::Inf, Limit::Includes(o.into()))
+    }
+
     pub fn new(low: Limit<T>, high: Limit<T>) -> Self {
         Self { low, high }
     }
@@ -147,3 +263,433 @@ where
         self.union(rhs)
     }
 }
+
+impl<T> Display for Range<T>
+where
+    T: Display + PartialEq,
+{
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if let (Limit::Includes(low), Limit::Includes(high)) = (&self.low, &self.high) {
+            if low == high {
+                return write!(f, "{}", low);
+            }
+        }
+
+        match self.low {
+            Limit::Includes(ref low) => write!(f, "[{} ", low)?,
+            Limit::Excludes(ref low) => write!(f, "({} ", low)?,
+            Limit::Inf => write!(f, "(")?,
+        }
+        write!(f, "...")?;
+        match self.high {
+            Limit::Includes(ref high) => write!(f, " {}]", high),
+            Limit::Excludes(ref high) => write!(f, " {})", high),
+            Limit::Inf => write!(f, ")"),
+        }
+    }
+}
+
+/// A type of value a `RangeSet` can hold.
+///
+/// Values are taken to be dense, with another one between any two, unless the
+/// type says otherwise. Discrete types such as `Status` have to, or `%wait |
+/// %queue` would keep an empty gap between its two ranges, and compare as a
+/// different set from `[%wait...%queue]`.
+pub trait RangeValue: Ord + Clone {
+    /// Whether any value lies strictly between `low` and `high`.
+    fn any_between(low: &Self, high: &Self) -> bool {
+        low < high
+    }
+
+    /// Whether any value is greater than `value`.
+    fn any_above(_value: &Self) -> bool {
+        true
+    }
+
+    /// Whether any value is less than `value`.
+    fn any_below(_value: &Self) -> bool {
+        true
+    }
+}
+
+impl RangeValue for u8 {
+    fn any_between(low: &u8, high: &u8) -> bool {
+        low.checked_add(1).is_some_and(|next| next < *high)
+    }
+
+    fn any_above(value: &u8) -> bool {
+        *value < u8::MAX
+    }
+
+    fn any_below(value: &u8) -> bool {
+        *value > u8::MIN
+    }
+}
+
+/// A set of values made up of disjoint, non-adjacent ranges sorted by their
+/// lower limits.
+///
+/// Unlike `Range::union`, which returns the smallest single range covering
+/// both of its inputs, every operation on a `RangeSet` is exact.
+///
+/// Serialized as a single range when it holds exactly one, and as an array of
+/// ranges otherwise. A bare value is also accepted as the range holding only
+/// that value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RangeSet<T> {
+    ranges: Vec<Range<T>>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged, bound(deserialize = "T: Deserialize<'de> + Clone"))]
+enum RangeSetRepr<T> {
+    Many(Vec<Range<T>>),
+    One(Range<T>),
+    Value(T),
+}
+
+impl<T> Serialize for RangeSet<T>
+where
+    T: Serialize + PartialEq,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.ranges.len() == 1 {
+            self.ranges[0].serialize(serializer)
+        } else {
+            self.ranges.serialize(serializer)
+        }
+    }
+}
+
+impl<'de, T> Deserialize<'de> for RangeSet<T>
+where
+    T: Deserialize<'de> + RangeValue,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(match RangeSetRepr::deserialize(deserializer)? {
+            RangeSetRepr::Many(ranges) => RangeSet::new(ranges),
+            RangeSetRepr::One(range) => range.into(),
+            RangeSetRepr::Value(value) => value.into(),
+        })
+    }
+}
+
+impl<T> From<T> for RangeSet<T>
+where
+    T: RangeValue,
+{
+    fn from(value: T) -> Self {
+        Range::eq(value).into()
+    }
+}
+
+impl<T> From<Range<T>> for RangeSet<T>
+where
+    T: RangeValue,
+{
+    fn from(range: Range<T>) -> Self {
+        Self::new(vec![range])
+    }
+}
+
+impl<T> RangeSet<T>
+where
+    T: RangeValue,
+{
+    pub fn new(ranges: Vec<Range<T>>) -> Self {
+        let mut ranges: Vec<_> = ranges.into_iter().filter(|r| !r.is_empty()).collect();
+        ranges.sort_by(|a, b| cmp_low(&a.low, &b.low));
+
+        let mut normalized: Vec<Range<T>> = Vec::with_capacity(ranges.len());
+        for range in ranges {
+            if let Some(last) = normalized.last_mut() {
+                if !is_gap(&last.high, &range.low) {
+                    if cmp_high(&range.high, &last.high) == Ordering::Greater {
+                        last.high = range.high;
+                    }
+                    continue;
+                }
+            }
+            normalized.push(range);
+        }
+
+        Self { ranges: normalized }
+    }
+
+    pub fn empty() -> Self {
+        Self { ranges: vec![] }
+    }
+
+    pub fn all() -> Self {
+        Self {
+            ranges: vec![Range::new(Limit::Inf, Limit::Inf)],
+        }
+    }
+
+    pub fn ranges(&self) -> &[Range<T>] {
+        &self.ranges
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn contains(&self, v: &T) -> bool {
+        self.ranges.iter().any(|range| range.contains(v))
+    }
+
+    pub fn union(&self, other: &RangeSet<T>) -> Self {
+        Self::new(self.ranges.iter().chain(&other.ranges).cloned().collect())
+    }
+
+    pub fn intersection(&self, other: &RangeSet<T>) -> Self {
+        let mut ranges = vec![];
+        for a in &self.ranges {
+            for b in &other.ranges {
+                let low = if cmp_low(&a.low, &b.low) == Ordering::Greater {
+                    &a.low
+                } else {
+                    &b.low
+                };
+                let high = if cmp_high(&a.high, &b.high) == Ordering::Less {
+                    &a.high
+                } else {
+                    &b.high
+                };
+                ranges.push(Range::new(low.clone(), high.clone()));
+            }
+        }
+        Self::new(ranges)
+    }
+
+    pub fn complement(&self) -> Self {
+        let mut ranges = vec![];
+        let mut low = Some(Limit::Inf);
+
+        for range in &self.ranges {
+            if let (Some(gap_low), Some(gap_high)) = (low.take(), range.low.flip()) {
+                ranges.push(Range::new(gap_low, gap_high));
+            }
+            low = range.high.flip();
+            if low.is_none() {
+                break;
+            }
+        }
+        if let Some(low) = low {
+            ranges.push(Range::new(low, Limit::Inf));
+        }
+
+        // Gaps at the ends can hold no values, such as the one below the
+        // first status
+        Self::new(ranges)
+    }
+
+    pub fn is_subset(&self, other: &RangeSet<T>) -> bool {
+        self.intersection(&other.complement()).is_empty()
+    }
+}
+
+impl<T> Limit<T>
+where
+    T: Clone,
+{
+    /// The limit on the other side of the same value, so that the two limits
+    /// together cover every value exactly once. `Inf` has no such limit.
+    fn flip(&self) -> Option<Limit<T>> {
+        match *self {
+            Limit::Includes(ref v) => Some(Limit::Excludes(v.clone())),
+            Limit::Excludes(ref v) => Some(Limit::Includes(v.clone())),
+            Limit::Inf => None,
+        }
+    }
+}
+
+impl<T> Range<T>
+where
+    T: RangeValue,
+{
+    fn is_empty(&self) -> bool {
+        match (&self.low, &self.high) {
+            (Limit::Inf, Limit::Inf) => false,
+            (Limit::Excludes(low), Limit::Inf) => !T::any_above(low),
+            (Limit::Inf, Limit::Excludes(high)) => !T::any_below(high),
+            (Limit::Inf, _) | (_, Limit::Inf) => false,
+            (Limit::Includes(low), Limit::Includes(high)) => low > high,
+            (Limit::Includes(low), Limit::Excludes(high))
+            | (Limit::Excludes(low), Limit::Includes(high)) => low >= high,
+            (Limit::Excludes(low), Limit::Excludes(high)) => !T::any_between(low, high),
+        }
+    }
+}
+
+/// Orders two lower limits by the smallest value each allows.
+fn cmp_low<T: Ord>(a: &Limit<T>, b: &Limit<T>) -> Ordering {
+    match (a, b) {
+        (Limit::Inf, Limit::Inf) => Ordering::Equal,
+        (Limit::Inf, _) => Ordering::Less,
+        (_, Limit::Inf) => Ordering::Greater,
+        (Limit::Includes(a), Limit::Includes(b)) | (Limit::Excludes(a), Limit::Excludes(b)) => {
+            a.cmp(b)
+        }
+        (Limit::Includes(a), Limit::Excludes(b)) => a.cmp(b).then(Ordering::Less),
+        (Limit::Excludes(a), Limit::Includes(b)) => a.cmp(b).then(Ordering::Greater),
+    }
+}
+
+/// Orders two upper limits by the largest value each allows.
+fn cmp_high<T: Ord>(a: &Limit<T>, b: &Limit<T>) -> Ordering {
+    match (a, b) {
+        (Limit::Inf, Limit::Inf) => Ordering::Equal,
+        (Limit::Inf, _) => Ordering::Greater,
+        (_, Limit::Inf) => Ordering::Less,
+        (Limit::Includes(a), Limit::Includes(b)) | (Limit::Excludes(a), Limit::Excludes(b)) => {
+            a.cmp(b)
+        }
+        (Limit::Includes(a), Limit::Excludes(b)) => a.cmp(b).then(Ordering::Greater),
+        (Limit::Excludes(a), Limit::Includes(b)) => a.cmp(b).then(Ordering::Less),
+    }
+}
+
+/// Whether some value lies between a range ending at `high` and a later range
+/// starting at `low`.
+fn is_gap<T: RangeValue>(high: &Limit<T>, low: &Limit<T>) -> bool {
+    match (high, low) {
+        (Limit::Inf, _) | (_, Limit::Inf) => false,
+        (Limit::Excludes(high), Limit::Excludes(low)) => high <= low,
+        (Limit::Includes(high), Limit::Includes(low)) => T::any_between(high, low),
+        (Limit::Includes(high), Limit::Excludes(low))
+        | (Limit::Excludes(high), Limit::Includes(low)) => high < low,
+    }
+}
+
+impl<T> BitOr for RangeSet<T>
+where
+    T: RangeValue,
+{
+    type Output = Self;
+    fn bitor(self, rhs: Self) -> Self {
+        self.union(&rhs)
+    }
+}
+
+impl<T> BitAnd for RangeSet<T>
+where
+    T: RangeValue,
+{
+    type Output = Self;
+    fn bitand(self, rhs: Self) -> Self {
+        self.intersection(&rhs)
+    }
+}
+
+impl<T> Not for RangeSet<T>
+where
+    T: RangeValue,
+{
+    type Output = Self;
+    fn not(self) -> Self {
+        self.complement()
+    }
+}
+
+impl<T> Display for RangeSet<T>
+where
+    T: Display + PartialEq,
+{
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if self.ranges.is_empty() {
+            return write!(f, "none");
+        }
+        for (idx, range) in self.ranges.iter().enumerate() {
+            if idx > 0 {
+                write!(f, " | ")?;
+            }
+            write!(f, "{}", range)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use list::Status;
+    use list::Status::*;
+    use range::{Limit, Range, RangeSet};
+
+    fn set(range: Range<Status>) -> RangeSet<Status> {
+        range.into()
+    }
+
+    #[test]
+    fn range_set_union_is_exact() {
+        let statuses = RangeSet::from(Waiting) | RangeSet::from(Completed);
+        assert!(statuses.contains(&Waiting));
+        assert!(!statuses.contains(&Queuing));
+        assert!(!statuses.contains(&Working));
+        assert!(statuses.contains(&Completed));
+        assert_eq!(statuses.ranges().len(), 2);
+
+        let merged = set(Range::lte(Queuing)) | set(Range::gt(Queuing));
+        assert_eq!(merged, RangeSet::all());
+
+        let overlapping = set(Range::new(
+            Limit::Includes(Waiting),
+            Limit::Includes(Working),
+        )) | set(Range::gte(Queuing));
+        assert_eq!(overlapping, set(Range::gte(Waiting)));
+    }
+
+    #[test]
+    fn range_set_intersection_and_complement() {
+        let statuses = set(Range::gte(Queuing)) & set(Range::lt(Completed));
+        assert_eq!(
+            statuses,
+            set(Range::new(
+                Limit::Includes(Queuing),
+                Limit::Excludes(Completed)
+            ))
+        );
+
+        let not_working = !RangeSet::from(Working);
+        assert_eq!(
+            not_working,
+            RangeSet::new(vec![Range::lt(Working), Range::gt(Working)])
+        );
+        assert!(!not_working.contains(&Working));
+        assert!(not_working.contains(&Waiting));
+        assert!(not_working.contains(&Completed));
+        assert_eq!(!not_working.clone(), RangeSet::from(Working));
+        assert!((not_working & RangeSet::from(Working)).is_empty());
+
+        assert!(RangeSet::<u8>::all().complement().is_empty());
+        assert_eq!(RangeSet::<u8>::empty().complement(), RangeSet::all());
+    }
+
+    #[test]
+    fn range_set_subset() {
+        let waiting = RangeSet::from(Waiting);
+        let open = set(Range::lt(Completed));
+        assert!(waiting.is_subset(&open));
+        assert!(!open.is_subset(&waiting));
+        assert!(RangeSet::empty().is_subset(&waiting));
+        assert!(open.is_subset(&RangeSet::all()));
+    }
+
+    #[test]
+    fn range_set_merges_neighbouring_statuses() {
+        let waiting_or_queuing = RangeSet::from(Waiting) | RangeSet::from(Queuing);
+        let span = set(Range::new(Limit::Includes(Waiting), Limit::Includes(Queuing)));
+        assert_eq!(waiting_or_queuing, span);
+        assert!(span.is_subset(&waiting_or_queuing));
+        assert!(waiting_or_queuing.is_subset(&span));
+
+        let every = RangeSet::from(Waiting)
+            | RangeSet::from(Queuing)
+            | RangeSet::from(Working)
+            | RangeSet::from(Completed);
+        assert!(RangeSet::all().is_subset(&every));
+        assert!(every.complement().is_empty());
+        assert!(set(Range::gt(Completed)).is_empty());
+        assert!(set(Range::new(Limit::Excludes(Queuing), Limit::Excludes(Working))).is_empty());
+    }
+}
@@ -0,0 +1,186 @@
+use std::fmt::{self, Display, Formatter};
+use std::str::CharIndices;
+use std::iter::Peekable;
+
+use list::Status;
+use script::SyntaxError;
+use super::parser::parse_status;
+
+/// The tokens of the quick-add syntax used to describe new items.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Tok {
+    /// A `%status`.
+    Status(Status),
+    /// A word of the item's name, or a whole `"quoted name"`.
+    Word(String),
+    /// A `#tag`.
+    Tag(String),
+    /// A `key:value` attribute.
+    Attribute((String, String)),
+    /// Everything following a `--` separator.
+    Description(String),
+}
+
+impl Display for Tok {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            Tok::Status(ref status) => write!(f, "status {}", status),
+            Tok::Word(ref word) => write!(f, "word '{}'", word),
+            Tok::Tag(ref tag) => write!(f, "tag #{}", tag),
+            Tok::Attribute((ref key, ref value)) => write!(f, "attribute {}:{}", key, value),
+            Tok::Description(_) => write!(f, "description"),
+        }
+    }
+}
+
+pub type Spanned = Result<(usize, Tok, usize), SyntaxError>;
+
+/// Splits quick-add text into tokens.
+///
+/// Tokens are separated by whitespace. A backslash makes the character after
+/// it literal, and double quotes group text, including whitespace, into a
+/// single word, or into the value of a tag or attribute when they follow
+/// its `#` or `key:`.
+pub struct Lexer<'input> {
+    text: &'input str,
+    chars: Peekable<CharIndices<'input>>,
+}
+
+impl<'input> Lexer<'input> {
+    pub fn new(text: &'input str) -> Self {
+        Self {
+            text,
+            chars: text.char_indices().peekable(),
+        }
+    }
+
+    /// Reads the rest of the current token, returning its unescaped text,
+    /// where it ends, and where in the text the first quoted or escaped
+    /// character is, if any.
+    fn read_token(&mut self, start: usize) -> Result<(String, usize, Option<usize>), SyntaxError> {
+        let mut text = String::new();
+        let mut literal = None;
+        let mut end = start;
+
+        while let Some(&(idx, c)) = self.chars.peek() {
+            if c.is_whitespace() {
+                break;
+            }
+            self.chars.next();
+            end = idx + c.len_utf8();
+
+            match c {
+                '\\' => {
+                    literal = literal.or(Some(text.len()));
+                    let (idx, c) = self.chars
+                        .next()
+                        .ok_or_else(|| SyntaxError::new((idx, end), "nothing to escape"))?;
+                    text.push(c);
+                    end = idx + c.len_utf8();
+                }
+                '"' => {
+                    literal = literal.or(Some(text.len()));
+                    end = self.read_quoted(idx, &mut text)?;
+                }
+                c => text.push(c),
+            }
+        }
+
+        Ok((text, end, literal))
+    }
+
+    fn read_quoted(&mut self, start: usize, text: &mut String) -> Result<usize, SyntaxError> {
+        while let Some((idx, c)) = self.chars.next() {
+            match c {
+                '"' => return Ok(idx + 1),
+                '\\' => match self.chars.next() {
+                    Some((_, c)) => text.push(c),
+                    None => break,
+                },
+                c => text.push(c),
+            }
+        }
+
+        Err(SyntaxError::new(
+            (start, self.text.len()),
+            "unterminated quote",
+        ))
+    }
+}
+
+fn is_key(key: &str) -> bool {
+    let mut key_chars = key.chars();
+    key_chars.next().is_some_and(char::is_alphabetic)
+        && key_chars.all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+}
+
+fn split_attribute(word: &str) -> Option<(String, String)> {
+    let sep = word.find(':')?;
+    let (key, value) = (&word[..sep], &word[sep + 1..]);
+
+    if is_key(key) && !value.is_empty() && !value.starts_with('/') {
+        Some((key.into(), value.into()))
+    } else {
+        None
+    }
+}
+
+/// Reads a token which was partly quoted or escaped, from `literal` on.
+/// It's a tag or attribute if its `#` or `key:` came before that, such as
+/// `#"two words"` or `due:"next friday"`, and otherwise a word.
+fn split_literal(text: String, literal: usize) -> Tok {
+    let plain = &text[..literal];
+    if plain.starts_with('#') && text.len() > 1 {
+        return Tok::Tag(text[1..].into());
+    }
+    if let Some(sep) = plain.find(':') {
+        if is_key(&text[..sep]) && sep + 1 < text.len() {
+            return Tok::Attribute((text[..sep].into(), text[sep + 1..].into()));
+        }
+    }
+    Tok::Word(text)
+}
+
+impl<'input> Iterator for Lexer<'input> {
+    type Item = Spanned;
+
+    fn next(&mut self) -> Option<Spanned> {
+        while let Some(&(_, c)) = self.chars.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.chars.next();
+        }
+
+        let start = match self.chars.peek() {
+            Some(&(start, _)) => start,
+            None => return None,
+        };
+
+        let (text, end, literal) = match self.read_token(start) {
+            Ok(token) => token,
+            Err(err) => return Some(Err(err)),
+        };
+
+        let token = if let Some(literal) = literal {
+            split_literal(text, literal)
+        } else if text == "--" {
+            let description = self.text[end..].trim().to_string();
+            while self.chars.next().is_some() {}
+            return Some(Ok((start, Tok::Description(description), self.text.len())));
+        } else if let Some(tag) = text.strip_prefix('#') {
+            if tag.is_empty() {
+                return Some(Err(SyntaxError::new((start, end), "empty tag")));
+            }
+            Tok::Tag(tag.into())
+        } else if let Ok(status) = parse_status(&text) {
+            Tok::Status(status)
+        } else if let Some(attribute) = split_attribute(&text) {
+            Tok::Attribute(attribute)
+        } else {
+            Tok::Word(text)
+        };
+
+        Some(Ok((start, token, end)))
+    }
+}
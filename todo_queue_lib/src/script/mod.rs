@@ -1,6 +1,13 @@
 #[cfg_attr(rustfmt, rustfmt_skip)] mod parser;
+#[cfg_attr(rustfmt, rustfmt_skip)] mod quick_add;
+mod lexer;
 
-use self::parser::parse_query;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+use self::parser::{parse_query, parse_status};
+use self::lexer::{Lexer, Tok};
+use list::{ItemDesc, Status};
 use query::Query;
 
 use lalrpop_util::ParseError;
@@ -9,11 +16,132 @@ pub fn query_parser(text: &str) -> Result<Query, ParseError<usize, String, Strin
     parse_query(text).map_err(|err| err.map_error(|s| s.into()).map_token(|t| t.1.into()))
 }
 
+/// Parses a `%status`, such as `%work` or `%Completed`.
+pub fn status_parser(text: &str) -> Result<Status, SyntaxError> {
+    parse_status(text).map_err(|_| SyntaxError::new((0, text.len()), "unknown status"))
+}
+
+/// Parses the quick-add syntax for describing a new item:
+///
+/// ```text
+/// [%status] name words #tags key:value... [-- description]
+/// ```
+///
+/// Double quotes group words, including ones that would otherwise be read as
+/// tags, attributes or separators, and a backslash escapes the next character.
+/// Quotes after a `#` or `key:` group the tag or value, as in
+/// `due:"next friday"`.
+pub fn item_parser(text: &str) -> Result<ItemDesc, SyntaxError> {
+    quick_add::parse_item(Lexer::new(text)).map_err(|err| match err {
+        ParseError::InvalidToken { location } => {
+            SyntaxError::new((location, location), "invalid token")
+        }
+        ParseError::UnrecognizedToken {
+            token: Some((lo, Tok::Status(status), hi)),
+            ..
+        } => SyntaxError::new(
+            (lo, hi),
+            format!("unexpected status {}, the status must come first", status),
+        ),
+        ParseError::UnrecognizedToken {
+            token: Some((lo, token, hi)),
+            ..
+        }
+        | ParseError::ExtraToken {
+            token: (lo, token, hi),
+        } => SyntaxError::new((lo, hi), format!("unexpected {}", token)),
+        ParseError::UnrecognizedToken { token: None, .. } => {
+            SyntaxError::new((text.len(), text.len()), "unexpected end of input")
+        }
+        ParseError::User { error } => error,
+    })
+}
+
+/// Writes an item in the quick-add syntax read by `item_parser`, leaving out
+/// the status if it is the default one.
+pub fn format_item(item: &ItemDesc) -> String {
+    fn reads_as(text: &str, token: Tok) -> bool {
+        let tokens: Vec<_> = Lexer::new(text).collect();
+        tokens == vec![Ok((0, token, text.len()))]
+    }
+    fn quote(text: &str) -> String {
+        format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+
+    let mut words = vec![];
+    if item.status != Status::default() {
+        words.push(item.status.to_string());
+    }
+
+    let name_words: Vec<_> = item.name.split_whitespace().collect();
+    if !name_words.is_empty() && name_words.join(" ") == item.name
+        && name_words.iter().all(|word| reads_as(word, Tok::Word(word.to_string())))
+    {
+        words.push(item.name.clone());
+    } else {
+        words.push(quote(&item.name));
+    }
+
+    // Tags and attribute values are quoted the same way when they wouldn't
+    // read back as one
+    for tag in &item.tags {
+        let plain = format!("#{}", tag);
+        if reads_as(&plain, Tok::Tag(tag.clone())) {
+            words.push(plain);
+        } else {
+            words.push(format!("#{}", quote(tag)));
+        }
+    }
+    for (key, value) in &item.attributes {
+        let plain = format!("{}:{}", key, value);
+        if reads_as(&plain, Tok::Attribute((key.clone(), value.clone()))) {
+            words.push(plain);
+        } else {
+            words.push(format!("{}:{}", key, quote(value)));
+        }
+    }
+    if !item.description.is_empty() {
+        words.push(format!("-- {}", item.description));
+    }
+
+    words.join(" ")
+}
+
+/// An error in a piece of script, along with the byte range of the text it
+/// was found in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxError {
+    pub span: (usize, usize),
+    pub message: String,
+}
+
+impl SyntaxError {
+    pub fn new<M: Into<String>>(span: (usize, usize), message: M) -> Self {
+        Self {
+            span,
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for SyntaxError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{} at {}..{}", self.message, self.span.0, self.span.1)
+    }
+}
+
+impl Error for SyntaxError {
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::parser::*;
-    use list::Status;
-    use range::{Limit, Range};
+    use super::{format_item, item_parser, SyntaxError};
+    use list::{ItemDesc, Status};
+    use range::{Limit, Range, RangeSet};
     use query::{Filter, Query};
 
     #[test]
@@ -87,6 +215,72 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parser_status_comparison() {
+        assert_eq!(
+            parse_filter("status >= %queue"),
+            Ok(Filter::status(Range::gte(Status::Queuing)))
+        );
+        assert_eq!(
+            parse_filter("status > %queue"),
+            Ok(Filter::status(Range::gt(Status::Queuing)))
+        );
+        assert_eq!(
+            parse_filter("status < %complete"),
+            Ok(Filter::status(Range::lt(Status::Completed)))
+        );
+        assert_eq!(
+            parse_filter("status <= %work"),
+            Ok(Filter::status(Range::lte(Status::Working)))
+        );
+        assert_eq!(
+            parse_filter("status != %wait"),
+            Ok(!Filter::status(Status::Waiting))
+        );
+        assert_eq!(
+            parse_filter("status == %wait"),
+            Ok(Filter::status(Status::Waiting))
+        );
+        assert_eq!(
+            parse_filter("status = [%queue ... %complete)"),
+            Ok(Filter::status(Range::new(
+                Limit::Includes(Status::Queuing),
+                Limit::Excludes(Status::Completed)
+            )))
+        );
+        assert_eq!(
+            parse_filter("status >= %queue & status != %complete"),
+            Ok(Filter::status(Range::gte(Status::Queuing)) & !Filter::status(Status::Completed))
+        );
+        assert!(parse_filter("status >= [%queue ... %complete)").is_err());
+    }
+
+    #[test]
+    fn parser_status_set() {
+        let waiting_or_completed =
+            RangeSet::from(Status::Waiting) | RangeSet::from(Status::Completed);
+        assert_eq!(
+            parse_filter("%wait | %complete"),
+            Ok(Filter::status(waiting_or_completed.clone()))
+        );
+        assert_eq!(
+            parse_filter("%wait %complete"),
+            Ok(Filter::status(waiting_or_completed))
+        );
+        assert_eq!(
+            parse_filter("status >= %queue & status <= %work"),
+            Ok(Filter::status(Range::new(
+                Limit::Includes(Status::Queuing),
+                Limit::Includes(Status::Working)
+            )))
+        );
+        assert_eq!(
+            parse_filter("%wait | #tag | %complete"),
+            Ok(Filter::status(Status::Waiting)
+                | (Filter::tag("tag") | Filter::status(Status::Completed)))
+        );
+    }
+
     #[test]
     fn parser_query() {
         assert!(parse_query("").is_err());
@@ -108,4 +302,96 @@ mod tests {
             )
         );
     }
+
+    #[test]
+    fn parser_item() {
+        let item = item_parser("re-run tests #ci").unwrap();
+        assert_eq!(item.name, "re-run tests");
+        assert_eq!(item.description, "");
+        assert_eq!(item.tags, vec!["ci"]);
+        assert_eq!(item.status, Status::Waiting);
+
+        let item = item_parser(r#"%work "deploy #2" #ops due:2026-10-01 -- push the build"#)
+            .unwrap();
+        assert_eq!(item.name, "deploy #2");
+        assert_eq!(item.description, "push the build");
+        assert_eq!(item.tags, vec!["ops"]);
+        assert_eq!(item.status, Status::Working);
+        assert_eq!(
+            item.attributes.get("due").map(String::as_str),
+            Some("2026-10-01")
+        );
+
+        let item = item_parser(r"fix \#3 in 50% of\ cases see http://example.com").unwrap();
+        assert_eq!(item.name, "fix #3 in 50% of cases see http://example.com");
+        assert!(item.tags.is_empty());
+        assert!(item.attributes.is_empty());
+
+        let item: ItemDesc = "write -- docs -- twice".parse().unwrap();
+        assert_eq!(item.name, "write");
+        assert_eq!(item.description, "docs -- twice");
+    }
+
+    #[test]
+    fn format_item_round_trip() {
+        let items = vec![
+            "re-run tests #ci",
+            r#"%work "deploy #2" #ops due:2026-10-01 -- push the build"#,
+            r#""say \"hi\"  twice" -- a description"#,
+            r"fix \#3 \%queue",
+            r#"deploy #"on call" due:"next friday" url:"/srv/app" -- soon"#,
+        ];
+
+        for text in items {
+            let item = item_parser(text).unwrap();
+            assert_eq!(item_parser(&format_item(&item)), Ok(item.clone()), "{}", text);
+        }
+
+        assert_eq!(
+            format_item(&item_parser("%work deploy #ops -- now").unwrap()),
+            "%Working deploy #ops -- now"
+        );
+
+        let mut item = item_parser("deploy").unwrap();
+        item.tags.push("on call".into());
+        item.attributes.insert("due".into(), "next friday".into());
+        item.attributes.insert("note".into(), r#"say "hi""#.into());
+        let text = format_item(&item);
+        assert_eq!(
+            text,
+            r#"deploy #"on call" due:"next friday" note:"say \"hi\"""#
+        );
+        assert_eq!(item_parser(&text), Ok(item));
+    }
+
+    #[test]
+    fn parser_item_errors() {
+        assert_eq!(
+            item_parser(r#"say "hello"#),
+            Err(SyntaxError::new((4, 10), "unterminated quote"))
+        );
+        assert_eq!(
+            item_parser("deploy # now"),
+            Err(SyntaxError::new((7, 8), "empty tag"))
+        );
+        assert_eq!(
+            item_parser("deploy %work"),
+            Err(SyntaxError::new(
+                (7, 12),
+                "unexpected status %Working, the status must come first"
+            ))
+        );
+        assert_eq!(
+            item_parser("trailing \\"),
+            Err(SyntaxError::new((9, 10), "nothing to escape"))
+        );
+        assert_eq!(
+            item_parser("trailing \\\\").map(|item| item.name),
+            Ok("trailing \\".to_string())
+        );
+        assert_eq!(
+            item_parser("#ops -- notes"),
+            Err(SyntaxError::new((0, 13), "an item needs a name"))
+        );
+    }
 }
@@ -0,0 +1,381 @@
+//! Keeping lists somewhere more lasting than memory.
+//!
+//! A `Storage` is a place items can be read from and written back to, such
+//! as a file. A `StoredList` keeps the items of one in memory, remembers
+//! which of them change, and hands just those changes back to the storage
+//! when it is saved, so backends which can update items one at a time don't
+//! have to rewrite the whole list. Saving is also when items which were
+//! added or changed get their timestamps.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+use rand;
+
+use list::{self, ItemDesc, ItemId, List};
+use query::Filter;
+use time::Timestamp;
+
+/// A change to one item since a list was loaded or last saved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// The item was added or modified, and now looks like this.
+    Put(ItemId, ItemDesc),
+    Remove(ItemId),
+    /// The item was moved to just after another, or to the front.
+    Move(ItemId, Option<ItemId>),
+}
+
+/// An error reading from or writing to a storage backend.
+#[derive(Debug)]
+pub struct StorageError {
+    message: String,
+    cause: Option<Box<dyn Error + Send + Sync>>,
+}
+
+impl StorageError {
+    pub fn new<M: Into<String>>(message: M) -> Self {
+        Self {
+            message: message.into(),
+            cause: None,
+        }
+    }
+
+    pub fn with_cause<M, E>(message: M, cause: E) -> Self
+    where
+        M: Into<String>,
+        E: Into<Box<dyn Error + Send + Sync>>,
+    {
+        Self {
+            message: message.into(),
+            cause: Some(cause.into()),
+        }
+    }
+}
+
+impl Display for StorageError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.cause {
+            Some(ref cause) => write!(f, "{}: {}", self.message, cause),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl Error for StorageError {
+    fn description(&self) -> &str {
+        &self.message
+    }
+
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.cause.as_ref().map(|cause| &**cause as &(dyn Error + 'static))
+    }
+}
+
+pub trait Storage {
+    /// Reads every item kept in the storage.
+    fn load(&mut self) -> Result<Vec<(ItemId, ItemDesc)>, StorageError>;
+
+    /// Brings the storage up to date with a list.
+    ///
+    /// `items` is the whole list and `changes` is what changed in it since
+    /// it was loaded or last stored. Backends are free to use whichever of
+    /// the two suits them.
+    fn store(&mut self, items: &[(ItemId, ItemDesc)], changes: &[Change])
+        -> Result<(), StorageError>;
+}
+
+impl<S: Storage + ?Sized> Storage for Box<S> {
+    fn load(&mut self) -> Result<Vec<(ItemId, ItemDesc)>, StorageError> {
+        (**self).load()
+    }
+
+    fn store(&mut self, items: &[(ItemId, ItemDesc)], changes: &[Change])
+        -> Result<(), StorageError> {
+        (**self).store(items, changes)
+    }
+}
+
+/// A list kept in memory on top of a `Storage`, which only writes to it
+/// when saved.
+#[derive(Debug)]
+pub struct StoredList<S> {
+    items: Vec<(ItemId, ItemDesc)>,
+    changed: BTreeSet<ItemId>,
+    removed: BTreeSet<ItemId>,
+    /// Items added since the list was loaded or saved.
+    added: BTreeSet<ItemId>,
+    /// Items handed out to be modified, as they were before.
+    touched: BTreeMap<ItemId, ItemDesc>,
+    /// Items moved since the list was loaded or saved, in the order they
+    /// were moved.
+    moved: Vec<(ItemId, Option<ItemId>)>,
+    /// Set until a list made with `with_items` is first saved, so that it's
+    /// written even if it has no items.
+    fresh: bool,
+    storage: S,
+}
+
+impl<S: Storage> StoredList<S> {
+    /// Loads the list kept in `storage`.
+    pub fn open(mut storage: S) -> Result<Self, StorageError> {
+        Ok(Self {
+            items: storage.load()?,
+            changed: BTreeSet::new(),
+            removed: BTreeSet::new(),
+            added: BTreeSet::new(),
+            touched: BTreeMap::new(),
+            moved: vec![],
+            fresh: false,
+            storage,
+        })
+    }
+
+    /// Makes a list of `items` which will be written to `storage` in full
+    /// when it is first saved, replacing whatever it held before.
+    pub fn with_items(items: Vec<(ItemId, ItemDesc)>, storage: S) -> Self {
+        Self {
+            changed: items.iter().map(|&(id, _)| id).collect(),
+            removed: BTreeSet::new(),
+            added: BTreeSet::new(),
+            touched: BTreeMap::new(),
+            moved: vec![],
+            fresh: true,
+            items,
+            storage,
+        }
+    }
+
+    /// Whether anything has changed since the list was loaded or saved.
+    pub fn is_dirty(&self) -> bool {
+        self.fresh || !self.changed.is_empty() || !self.removed.is_empty() || !self.moved.is_empty()
+    }
+
+    /// Writes whatever has changed to the storage.
+    pub fn save(&mut self) -> Result<(), StorageError> {
+        self.stamp();
+        if !self.is_dirty() {
+            return Ok(());
+        }
+
+        let mut changes: Vec<_> = self.removed.iter().map(|id| Change::Remove(*id)).collect();
+        changes.extend(
+            self.items
+                .iter()
+                .filter(|&(id, _)| self.changed.contains(id))
+                .map(|(id, item)| Change::Put(*id, item.clone())),
+        );
+        changes.extend(self.moved.iter().map(|&(id, after)| Change::Move(id, after)));
+        self.storage.store(&self.items, &changes)?;
+
+        self.changed.clear();
+        self.removed.clear();
+        self.moved.clear();
+        self.fresh = false;
+        Ok(())
+    }
+
+    /// Timestamps the items added or modified since the last save. Items
+    /// which were handed out but came back unchanged aren't saved at all.
+    /// Saving does this anyway; call it first to see the items as they'll
+    /// be written.
+    pub fn stamp(&mut self) {
+        let now = Timestamp::now();
+        let added = ::std::mem::take(&mut self.added);
+        let touched = ::std::mem::take(&mut self.touched);
+
+        for &mut (id, ref mut item) in &mut self.items {
+            if added.contains(&id) {
+                item.stamp(None, now);
+            } else if let Some(old) = touched.get(&id) {
+                if item == old {
+                    self.changed.remove(&id);
+                } else {
+                    item.stamp(Some(old.status), now);
+                }
+            }
+        }
+    }
+
+    pub fn items(&self) -> &[(ItemId, ItemDesc)] {
+        &self.items
+    }
+
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    /// Gives access to the storage itself, for work specific to one
+    /// backend. Save first, or the storage won't know about the latest
+    /// changes.
+    pub fn storage_mut(&mut self) -> &mut S {
+        &mut self.storage
+    }
+}
+
+impl<S> List for StoredList<S> {
+    type Item = ItemDesc;
+
+    fn add(&mut self, item: ItemDesc) -> ItemId {
+        let id = loop {
+            let id = rand::random();
+            if self.items.iter().all(|&(other, _)| other != id) {
+                break id;
+            }
+        };
+        self.items.push((id, item));
+        self.changed.insert(id);
+        self.added.insert(id);
+        id
+    }
+
+    /// Puts the item in verbatim, timestamps included, as when restoring it.
+    fn insert(&mut self, id: ItemId, item: ItemDesc) {
+        match self.items.iter_mut().find(|me| me.0 == id) {
+            Some(me) => me.1 = item,
+            None => self.items.push((id, item)),
+        }
+        self.changed.insert(id);
+        self.added.remove(&id);
+        self.touched.remove(&id);
+    }
+
+    fn remove(&mut self, item: &ItemId) {
+        if let Some(idx) = self.items.iter().position(|me| me.0 == *item) {
+            self.items.remove(idx);
+            self.changed.remove(item);
+            self.added.remove(item);
+            self.touched.remove(item);
+            self.moved.retain(|&(id, _)| id != *item);
+            self.removed.insert(*item);
+        }
+    }
+
+    fn move_after(&mut self, id: &ItemId, after: Option<&ItemId>) {
+        if list::move_after(&mut self.items, id, after) {
+            self.moved.push((*id, after.cloned()));
+        }
+    }
+
+    fn get(&self, item: &ItemId) -> Option<&ItemDesc> {
+        self.items.iter().find(|me| me.0 == *item).map(|me| &me.1)
+    }
+
+    fn get_mut(&mut self, item: &ItemId) -> Option<&mut ItemDesc> {
+        let found = self.items.iter_mut().find(|me| me.0 == *item)?;
+        // There is no telling what the caller does with the item, so it is
+        // kept as it was to compare against when saving.
+        if !self.added.contains(item) && !self.touched.contains_key(item) {
+            self.touched.insert(*item, found.1.clone());
+        }
+        self.changed.insert(*item);
+        Some(&mut found.1)
+    }
+
+    fn select(&self, filter: &Filter) -> Vec<ItemId> {
+        self.items
+            .iter()
+            .filter(|&(id, item)| filter.matches(id, item))
+            .map(|me| me.0)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use list::{Item, ItemDesc, ItemId, List, Status};
+    use storage::{Change, Storage, StorageError, StoredList};
+
+    #[derive(Default)]
+    struct Recorder {
+        items: Vec<(ItemId, ItemDesc)>,
+        stored: Vec<Vec<Change>>,
+    }
+
+    impl Storage for Recorder {
+        fn load(&mut self) -> Result<Vec<(ItemId, ItemDesc)>, StorageError> {
+            Ok(self.items.clone())
+        }
+
+        fn store(&mut self, items: &[(ItemId, ItemDesc)], changes: &[Change])
+            -> Result<(), StorageError> {
+            self.items = items.to_vec();
+            self.stored.push(changes.to_vec());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn save_writes_only_changes() {
+        let mut list = StoredList::open(Recorder::default()).unwrap();
+        let deploy = list.add("deploy".parse().unwrap());
+        let docs = list.add("write docs".parse().unwrap());
+        list.save().unwrap();
+        assert_eq!(list.storage().stored[0].len(), 2);
+
+        list.save().unwrap();
+        assert_eq!(list.storage().stored.len(), 1);
+
+        list.get_mut(&docs).unwrap().set_tag("docs", true);
+        list.remove(&deploy);
+        list.save().unwrap();
+        assert_eq!(
+            list.storage().stored[1],
+            vec![
+                Change::Remove(deploy),
+                Change::Put(docs, list.get(&docs).unwrap().clone()),
+            ]
+        );
+        assert_eq!(list.get(&docs).unwrap().tags, vec!["docs"]);
+        assert_eq!(list.storage().items, list.items().to_vec());
+
+        list.get_mut(&docs).unwrap().set_tag("docs", true);
+        list.save().unwrap();
+        assert_eq!(list.storage().stored.len(), 2);
+    }
+
+    #[test]
+    fn save_writes_moves_after_items() {
+        let mut list = StoredList::open(Recorder::default()).unwrap();
+        let deploy = list.add("deploy".parse().unwrap());
+        let docs = list.add("write docs".parse().unwrap());
+        list.save().unwrap();
+
+        list.move_after(&docs, None);
+        assert!(list.is_dirty());
+        list.save().unwrap();
+        assert_eq!(list.storage().stored[1], vec![Change::Move(docs, None)]);
+        assert_eq!(list.items()[0].0, docs);
+
+        list.move_after(&deploy, Some(&deploy));
+        assert!(!list.is_dirty());
+    }
+
+    #[test]
+    fn new_list_is_written_even_if_empty() {
+        let mut list = StoredList::with_items(vec![], Recorder::default());
+        list.save().unwrap();
+        assert_eq!(list.storage().stored, vec![vec![]]);
+
+        list.save().unwrap();
+        assert_eq!(list.storage().stored.len(), 1);
+    }
+
+    #[test]
+    fn save_stamps_changed_items() {
+        let mut list = StoredList::open(Recorder::default()).unwrap();
+        let deploy = list.add("deploy".parse().unwrap());
+        list.save().unwrap();
+        let created = list.get(&deploy).unwrap().created;
+        assert!(created.is_some());
+        assert_eq!(list.get(&deploy).unwrap().transitions.len(), 1);
+
+        list.get_mut(&deploy).unwrap().set_status(Status::Working);
+        list.save().unwrap();
+        let item = list.get(&deploy).unwrap();
+        assert_eq!(item.created, created);
+        assert_eq!(item.transitions[1].from, Some(Status::Waiting));
+        assert_eq!(item.transitions[1].to, Status::Working);
+    }
+}
@@ -0,0 +1,357 @@
+//! Importing the JSON written by Taskwarrior's `task export`.
+//!
+//! | Taskwarrior                          | item                                 |
+//! |--------------------------------------|--------------------------------------|
+//! | `description`                        | name                                 |
+//! | `annotations`                        | description, one line each           |
+//! | `status:pending`                     | `Status::Waiting`                    |
+//! | `status:pending` or `waiting` with   | `Status::Working`                    |
+//! | a `start` date                       |                                      |
+//! | `status:completed`                   | `Status::Completed`                  |
+//! | `end` of a completed task            | `completed` attribute                |
+//! | `tags`                               | tags                                 |
+//! | `project:home.garden`                | tags `home` and `home.garden`        |
+//! | `depends`                            | `depends` attribute, as item ids     |
+//! | `priority:H`, `M` or `L`             | `pri` attribute `A`, `B` or `C`      |
+//! | `entry`                              | `created` attribute                  |
+//! | `uuid`                               | `uuid` attribute                     |
+//! | any other text, number or date       | attribute of the same name           |
+//!
+//! Dates keep only their day, as `YYYY-MM-DD`. The values Taskwarrior works
+//! out for itself (`id`, `urgency`, `modified`, `mask`, `imask` and
+//! `parent`) are left out. Deleted tasks, recurring task templates, and
+//! anything else that can't be mapped are reported in `Report::unmapped`.
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+
+use serde_json::{self, Map, Value};
+
+use list::{Imported, Item, ItemDesc, ItemId, List, Status};
+use query::Filter;
+
+const UUID: &str = "uuid";
+const DEPENDS: &str = "depends";
+const DERIVED: &[&str] = &["id", "urgency", "modified", "mask", "imask", "parent"];
+
+/// Something in an export which couldn't be brought into the list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Unmapped {
+    /// The description of the task it belongs to.
+    pub task: String,
+    pub message: String,
+}
+
+impl Display for Unmapped {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "task '{}': {}", self.task, self.message)
+    }
+}
+
+/// What an import did, and what it had to leave out.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Report {
+    pub imported: Imported,
+    pub unmapped: Vec<Unmapped>,
+}
+
+/// Turns a date like `20261005T220000Z` into `2026-10-05`.
+fn parse_date(value: &str) -> Option<String> {
+    let date = value.get(..8)?;
+    if date.bytes().all(|b| b.is_ascii_digit()) && value[8..].starts_with('T') {
+        Some(format!("{}-{}-{}", &date[..4], &date[4..6], &date[6..]))
+    } else {
+        None
+    }
+}
+
+fn is_attribute_key(key: &str) -> bool {
+    let mut chars = key.chars();
+    chars.next().is_some_and(char::is_alphabetic)
+        && chars.all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+}
+
+/// A task read from the export, before its dependencies are resolved.
+struct Task {
+    uuid: Option<String>,
+    item: ItemDesc,
+    depends: Vec<String>,
+}
+
+fn parse_task(task: &Map<String, Value>, unmapped: &mut Vec<Unmapped>) -> Option<Task> {
+    let name = match task.get("description").and_then(Value::as_str) {
+        Some(name) if !name.trim().is_empty() => name.trim().to_string(),
+        _ => {
+            unmapped.push(Unmapped {
+                task: task.get(UUID).and_then(Value::as_str).unwrap_or("?").into(),
+                message: "the task has no description to use as its name".into(),
+            });
+            return None;
+        }
+    };
+    let mut report = |message: String| {
+        unmapped.push(Unmapped {
+            task: name.clone(),
+            message,
+        })
+    };
+
+    let mut item = ItemDesc::new(name.clone(), "", Vec::<String>::new());
+    let mut uuid = None;
+    let mut depends = vec![];
+    let mut started = false;
+
+    for (key, value) in task {
+        match (key.as_str(), value) {
+            ("description", _) => {}
+            (key, _) if DERIVED.contains(&key) => {}
+            ("status", Value::String(status)) => match status.as_str() {
+                "pending" | "waiting" => {}
+                "completed" => item.status = Status::Completed,
+                "deleted" => {
+                    report("deleted tasks aren't imported".into());
+                    return None;
+                }
+                "recurring" => {
+                    report("recurring task templates can't be imported".into());
+                    return None;
+                }
+                other => report(format!("unknown status '{}'", other)),
+            },
+            ("start", _) => started = true,
+            ("end", Value::String(end)) => {
+                if let Some(date) = parse_date(end) {
+                    item.attributes.insert("completed".into(), date);
+                }
+            }
+            ("entry", Value::String(entry)) => {
+                if let Some(date) = parse_date(entry) {
+                    item.attributes.insert("created".into(), date);
+                }
+            }
+            (UUID, Value::String(id)) => {
+                uuid = Some(id.clone());
+                item.attributes.insert(UUID.into(), id.clone());
+            }
+            ("tags", Value::Array(tags)) => {
+                for tag in tags.iter().filter_map(Value::as_str) {
+                    if !item.has_tag(tag) {
+                        item.tags.push(tag.into());
+                    }
+                }
+            }
+            ("project", Value::String(project)) => {
+                let parts: Vec<_> = project.split('.').collect();
+                for end in 1..=parts.len() {
+                    let tag = parts[..end].join(".");
+                    if !item.has_tag(&tag) {
+                        item.tags.push(tag);
+                    }
+                }
+            }
+            ("priority", Value::String(priority)) => {
+                let pri = match priority.as_str() {
+                    "H" => "A",
+                    "M" => "B",
+                    "L" => "C",
+                    other => {
+                        report(format!("unknown priority '{}'", other));
+                        continue;
+                    }
+                };
+                item.attributes.insert("pri".into(), pri.into());
+            }
+            (DEPENDS, Value::String(uuids)) => {
+                depends.extend(uuids.split(',').map(String::from));
+            }
+            (DEPENDS, Value::Array(uuids)) => {
+                depends.extend(uuids.iter().filter_map(Value::as_str).map(String::from));
+            }
+            ("annotations", Value::Array(annotations)) => {
+                let notes: Vec<_> = annotations
+                    .iter()
+                    .filter_map(|note| note.get("description").and_then(Value::as_str))
+                    .collect();
+                item.description = notes.join("\n");
+            }
+            (key, Value::String(text)) if is_attribute_key(key) => {
+                let value = parse_date(text).unwrap_or_else(|| text.clone());
+                item.attributes.insert(key.into(), value);
+            }
+            (key, Value::Number(number)) if is_attribute_key(key) => {
+                item.attributes.insert(key.into(), number.to_string());
+            }
+            (key, _) => report(format!("can't map '{}': {}", key, value)),
+        }
+    }
+
+    if started && item.status == Status::Waiting {
+        item.status = Status::Working;
+    }
+    Some(Task {
+        uuid,
+        item,
+        depends,
+    })
+}
+
+/// Reads the tasks of a Taskwarrior export.
+///
+/// `task export` writes a JSON array, or with older versions one JSON object
+/// per line, so both are accepted.
+fn parse_tasks(text: &str) -> Result<Vec<Value>, serde_json::Error> {
+    if text.trim_start().starts_with('[') {
+        serde_json::from_str(text)
+    } else {
+        text.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect()
+    }
+}
+
+/// Adds the tasks of a Taskwarrior export to `list`.
+///
+/// Tasks imported before, which have the same `uuid`, update the items they
+/// became instead of being added again.
+pub fn import<L>(list: &mut L, text: &str) -> Result<Report, serde_json::Error>
+where
+    L: List,
+{
+    let mut report = Report::default();
+    let mut tasks = vec![];
+    for value in parse_tasks(text)? {
+        match value {
+            Value::Object(ref task) => tasks.extend(parse_task(task, &mut report.unmapped)),
+            other => report.unmapped.push(Unmapped {
+                task: "?".into(),
+                message: format!("expected a task but found {}", other),
+            }),
+        }
+    }
+
+    let mut ids: BTreeMap<String, ItemId> = BTreeMap::new();
+    for id in list.select(&Filter::All) {
+        if let Some(uuid) = list.get(&id).and_then(|item| item.get_attribute(UUID)) {
+            ids.insert(uuid.into(), id);
+        }
+    }
+
+    let mut added = vec![];
+    for task in &tasks {
+        let existing = task.uuid.as_ref().and_then(|uuid| ids.get(uuid)).cloned();
+        let id = match existing {
+            Some(id) => {
+                list.get_mut(&id).unwrap().assign(&task.item);
+                report.imported.updated.push(id);
+                id
+            }
+            None => {
+                let id = list.add(task.item.clone());
+                report.imported.added.push(id);
+                id
+            }
+        };
+        if let Some(ref uuid) = task.uuid {
+            ids.insert(uuid.clone(), id);
+        }
+        added.push(id);
+    }
+
+    // Dependencies can point at tasks later in the export, so they are only
+    // filled in once every task has an id.
+    for (task, id) in tasks.iter().zip(added) {
+        let mut depends = vec![];
+        for uuid in &task.depends {
+            match ids.get(uuid) {
+                Some(dependency) => depends.push(dependency.to_string()),
+                None => report.unmapped.push(Unmapped {
+                    task: task.item.name.clone(),
+                    message: format!("depends on {}, which isn't in the export", uuid),
+                }),
+            }
+        }
+        if !depends.is_empty() {
+            list.get_mut(&id)
+                .unwrap()
+                .set_attribute(DEPENDS, Some(&depends.join(",")));
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use list::{Item, List, MemoryList, Status};
+    use taskwarrior::{import, Unmapped};
+
+    const EXPORT: &str = r#"[
+        {"id":1,"description":"deploy","entry":"20261001T090000Z","modified":"20261002T090000Z",
+         "project":"work.ops","start":"20261002T090000Z","status":"pending","tags":["release"],
+         "priority":"H","due":"20261005T220000Z","estimate":3,
+         "uuid":"a0000000-0000-0000-0000-000000000001",
+         "depends":["a0000000-0000-0000-0000-000000000002","b0000000-0000-0000-0000-000000000009"],
+         "annotations":[{"entry":"20261001T100000Z","description":"after the tests"},
+                        {"entry":"20261001T110000Z","description":"tag it too"}],
+         "urgency":12.3,"review":{"by":"sam"}},
+        {"id":0,"description":"run the tests","end":"20261003T120000Z","entry":"20261001T090000Z",
+         "status":"completed","uuid":"a0000000-0000-0000-0000-000000000002"},
+        {"id":0,"description":"old idea","status":"deleted","uuid":"a0000000-0000-0000-0000-000000000003"}
+    ]"#;
+
+    #[test]
+    fn import_taskwarrior_export() {
+        let mut list = MemoryList::default();
+        let report = import(&mut list, EXPORT).unwrap();
+        assert_eq!(report.imported.added.len(), 2);
+
+        let deploy = list.get(&report.imported.added[0]).unwrap();
+        assert_eq!(deploy.get_name(), "deploy");
+        assert_eq!(deploy.get_description(), "after the tests\ntag it too");
+        assert_eq!(*deploy.get_status(), Status::Working);
+        assert_eq!(deploy.get_tags(), vec!["work", "work.ops", "release"]);
+        assert_eq!(deploy.get_attribute("pri"), Some("A"));
+        assert_eq!(deploy.get_attribute("due"), Some("2026-10-05"));
+        assert_eq!(deploy.get_attribute("created"), Some("2026-10-01"));
+        assert_eq!(deploy.get_attribute("estimate"), Some("3"));
+        assert_eq!(deploy.get_attribute("depends"), Some("@00002"));
+
+        let tests = list.get(&report.imported.added[1]).unwrap();
+        assert_eq!(*tests.get_status(), Status::Completed);
+        assert_eq!(tests.get_attribute("completed"), Some("2026-10-03"));
+
+        let unmapped = |task: &str, message: &str| Unmapped {
+            task: task.into(),
+            message: message.into(),
+        };
+        assert_eq!(
+            report.unmapped,
+            vec![
+                unmapped("deploy", r#"can't map 'review': {"by":"sam"}"#),
+                unmapped("old idea", "deleted tasks aren't imported"),
+                unmapped(
+                    "deploy",
+                    "depends on b0000000-0000-0000-0000-000000000009, which isn't in the export",
+                ),
+            ]
+        );
+
+        let report = import(&mut list, EXPORT).unwrap();
+        assert!(report.imported.added.is_empty());
+        assert_eq!(report.imported.updated.len(), 2);
+        assert_eq!(list.items.len(), 2);
+    }
+
+    #[test]
+    fn import_one_task_per_line() {
+        let mut list = MemoryList::default();
+        let text = "{\"description\":\"a\",\"status\":\"pending\"}\n\n\
+                    {\"description\":\"b\",\"status\":\"waiting\",\"wait\":\"20261101T000000Z\"}\n";
+        let report = import(&mut list, text).unwrap();
+        assert_eq!(report.imported.added.len(), 2);
+        let b = list.get(&report.imported.added[1]).unwrap();
+        assert_eq!(b.get_attribute("wait"), Some("2026-11-01"));
+        assert!(import(&mut list, "{").is_err());
+    }
+}
@@ -0,0 +1,259 @@
+//! Points in time, as recorded on items and compared against in queries.
+
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::de::Error;
+
+use list::Status;
+use range::{Comparison, Limit, Range, RangeValue};
+
+/// A moment in UTC, to the second.
+///
+/// Written, and serialized, as `2026-10-01T09:30:00Z`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp(DateTime<Utc>);
+
+const FORMAT: &str = "%Y-%m-%dT%H:%M:%SZ";
+
+impl Timestamp {
+    pub fn now() -> Self {
+        Timestamp(Utc.timestamp_opt(Utc::now().timestamp(), 0).unwrap())
+    }
+
+    pub fn from_datetime(datetime: DateTime<Utc>) -> Self {
+        Timestamp(datetime)
+    }
+
+    pub fn datetime(&self) -> DateTime<Utc> {
+        self.0
+    }
+}
+
+/// Timestamps are read and written to the second, but may be made from any
+/// moment, so ranges of them are taken to be dense.
+impl RangeValue for Timestamp {}
+
+impl Display for Timestamp {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.0.format(FORMAT))
+    }
+}
+
+/// A timestamp which couldn't be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BadTimestamp(pub String);
+
+impl Display for BadTimestamp {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "bad timestamp {}", self.0)
+    }
+}
+
+impl FromStr for Timestamp {
+    type Err = BadTimestamp;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        NaiveDateTime::parse_from_str(s, FORMAT)
+            .map(|datetime| Timestamp(Utc.from_utc_datetime(&datetime)))
+            .map_err(|_| BadTimestamp(s.into()))
+    }
+}
+
+impl Serialize for Timestamp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_string().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Timestamp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        text.parse().map_err(|err: BadTimestamp| D::Error::custom(err.to_string()))
+    }
+}
+
+/// A change of an item's status. The first transition of an item is the
+/// one it was created with, coming from no status at all.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Transition {
+    pub from: Option<Status>,
+    pub to: Status,
+    pub at: Timestamp,
+}
+
+impl Display for Transition {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.from {
+            Some(from) => write!(f, "{} {} -> {}", self.at, from, self.to),
+            None => write!(f, "{} created as {}", self.at, self.to),
+        }
+    }
+}
+
+/// The stretch of time a date or time in a query stands for: a whole day
+/// for a date such as `2026-10-01`, or a single moment for a full
+/// timestamp.
+///
+/// This is what makes `completed <= 2026-10-07` include everything done on
+/// the 7th.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Span {
+    Day(Timestamp),
+    Moment(Timestamp),
+}
+
+impl Span {
+    /// Reads a date (`2026-10-01`), a time in minutes (`2026-10-01T09:30`)
+    /// or a full timestamp.
+    pub fn parse(text: &str) -> Result<Span, BadTimestamp> {
+        if let Ok(date) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+            let start = date.and_hms_opt(0, 0, 0).unwrap();
+            return Ok(Span::Day(Timestamp(Utc.from_utc_datetime(&start))));
+        }
+        let text = text.trim_end_matches('Z');
+        NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S")
+            .or_else(|_| NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M"))
+            .map(|datetime| Span::Moment(Timestamp(Utc.from_utc_datetime(&datetime))))
+            .map_err(|_| BadTimestamp(text.into()))
+    }
+
+    fn start(&self) -> Timestamp {
+        match *self {
+            Span::Day(start) | Span::Moment(start) => start,
+        }
+    }
+
+    /// The limit just after the span.
+    fn after(&self) -> Limit<Timestamp> {
+        match *self {
+            Span::Day(start) => Limit::Includes(Timestamp(start.0 + Duration::days(1))),
+            Span::Moment(moment) => Limit::Excludes(moment),
+        }
+    }
+
+    /// The limit at the end of the span, taking it in.
+    fn through(&self) -> Limit<Timestamp> {
+        match *self {
+            Span::Day(start) => Limit::Excludes(Timestamp(start.0 + Duration::days(1))),
+            Span::Moment(moment) => Limit::Includes(moment),
+        }
+    }
+
+    /// The lower limit of a range written starting with `[` (inclusive) or
+    /// `(`.
+    pub fn low(&self, inclusive: bool) -> Limit<Timestamp> {
+        if inclusive {
+            Limit::Includes(self.start())
+        } else {
+            self.after()
+        }
+    }
+
+    /// The upper limit of a range written ending with `]` (inclusive) or
+    /// `)`.
+    pub fn high(&self, inclusive: bool) -> Limit<Timestamp> {
+        if inclusive {
+            self.through()
+        } else {
+            Limit::Excludes(self.start())
+        }
+    }
+
+    /// The comparison written as `op` followed by this span.
+    pub fn compare(&self, op: &str) -> Comparison<Timestamp> {
+        let whole = Range::new(self.low(true), self.high(true));
+        match op {
+            "=" | "==" => Comparison::Within(whole),
+            "!=" => Comparison::Outside(whole),
+            "<" => Comparison::Within(Range::new(Limit::Inf, self.high(false))),
+            "<=" => Comparison::Within(Range::new(Limit::Inf, self.high(true))),
+            ">" => Comparison::Within(Range::new(self.low(false), Limit::Inf)),
+            _ => Comparison::Within(Range::new(self.low(true), Limit::Inf)),
+        }
+    }
+}
+
+/// How long ago something happened, written as a number and a unit of
+/// minutes, hours, days or weeks: `30m`, `12h`, `7d`, `2w`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Age(Duration);
+
+impl Age {
+    pub fn parse(text: &str) -> Result<Age, BadTimestamp> {
+        let (count, unit) = text.split_at(text.len() - 1);
+        let count: i64 = count.parse().map_err(|_| BadTimestamp(text.into()))?;
+        let duration = match unit {
+            "m" => Duration::try_minutes(count),
+            "h" => Duration::try_hours(count),
+            "d" => Duration::try_days(count),
+            "w" => Duration::try_weeks(count),
+            _ => None,
+        };
+        duration.map(Age).ok_or_else(|| BadTimestamp(text.into()))
+    }
+
+    /// The comparison written as `op` followed by this age, where smaller
+    /// ages are more recent: `created < 7d` is anything created within the
+    /// last seven days. Fails if the age goes back further than dates do.
+    pub fn compare(&self, op: &str, now: Timestamp) -> Result<Comparison<Timestamp>, BadTimestamp> {
+        let then = match now.0.checked_sub_signed(self.0) {
+            Some(then) => Timestamp(then),
+            None => return Err(BadTimestamp(format!("{} days ago", self.0.num_days()))),
+        };
+        Ok(match op {
+            "=" | "==" => Comparison::Within(Range::eq(then)),
+            "!=" => Comparison::Outside(Range::eq(then)),
+            "<" => Comparison::Within(Range::gt(then)),
+            "<=" => Comparison::Within(Range::gte(then)),
+            ">" => Comparison::Within(Range::lt(then)),
+            _ => Comparison::Within(Range::lte(then)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use range::Comparison;
+    use time::{Age, Span, Timestamp};
+
+    fn ts(text: &str) -> Timestamp {
+        text.parse().unwrap()
+    }
+
+    fn within(comparison: Comparison<Timestamp>, at: &str) -> bool {
+        match comparison {
+            Comparison::Within(range) => range.contains(&ts(at)),
+            Comparison::Outside(range) => !range.contains(&ts(at)),
+        }
+    }
+
+    #[test]
+    fn dates_cover_whole_days() {
+        let day = Span::parse("2026-10-07").unwrap();
+        assert!(within(day.compare("="), "2026-10-07T23:59:59Z"));
+        assert!(!within(day.compare("="), "2026-10-08T00:00:00Z"));
+        assert!(within(day.compare("<="), "2026-10-07T12:00:00Z"));
+        assert!(!within(day.compare("<"), "2026-10-07T12:00:00Z"));
+        assert!(within(day.compare(">"), "2026-10-08T00:00:00Z"));
+
+        let moment = Span::parse("2026-10-07T12:00").unwrap();
+        assert!(within(moment.compare(">="), "2026-10-07T12:00:00Z"));
+        assert!(!within(moment.compare(">"), "2026-10-07T12:00:00Z"));
+        assert_eq!(ts("2026-10-07T12:00:00Z").to_string(), "2026-10-07T12:00:00Z");
+    }
+
+    #[test]
+    fn ages_count_back_from_now() {
+        let now = ts("2026-10-19T12:00:00Z");
+        let week = Age::parse("7d").unwrap();
+        assert!(within(week.compare("<", now).unwrap(), "2026-10-15T00:00:00Z"));
+        assert!(!within(week.compare("<", now).unwrap(), "2026-10-01T00:00:00Z"));
+        assert!(within(week.compare(">", now).unwrap(), "2026-10-01T00:00:00Z"));
+        assert!(Age::parse("7y").is_err());
+
+        let ages_ago = Age::parse("15000000w").unwrap();
+        assert!(ages_ago.compare("<", now).is_err());
+    }
+}
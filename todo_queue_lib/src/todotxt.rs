@@ -0,0 +1,276 @@
+//! Conversion between items and lines of the [todo.txt] format.
+//!
+//! | todo.txt                 | item                                       |
+//! |--------------------------|--------------------------------------------|
+//! | `x` and completion date  | `Status::Completed`, `completed` attribute |
+//! | `(A)`                    | `pri` attribute                            |
+//! | creation date            | `created` attribute                        |
+//! | `+project`               | tag                                        |
+//! | `@context`               | `context` attribute, comma separated       |
+//! | `status:working`         | `Status::Queuing` or `Status::Working`     |
+//! | `key:value`              | attribute, space separated if repeated     |
+//! | text after a lone `--`   | description                                |
+//!
+//! Any other text is the item's name, and words of the name which would read
+//! as any of the above are written with a `\` in front. The description
+//! comes last and is taken as it is. Since a todo.txt task is a single line,
+//! line breaks in descriptions are written as spaces.
+//!
+//! [todo.txt]: https://github.com/todotxt/todo.txt
+
+use list::{ItemDesc, Status};
+
+const CONTEXT: &str = "context";
+const PRIORITY: &str = "pri";
+const CREATED: &str = "created";
+const COMPLETED: &str = "completed";
+const STATUS: &str = "status";
+
+fn is_date(word: &str) -> bool {
+    let bytes = word.as_bytes();
+    bytes.len() == 10
+        && bytes.iter().enumerate().all(|(idx, b)| match idx {
+            4 | 7 => *b == b'-',
+            _ => b.is_ascii_digit(),
+        })
+}
+
+fn is_priority(word: &str) -> bool {
+    let bytes = word.as_bytes();
+    bytes.len() == 3 && bytes[0] == b'(' && bytes[1].is_ascii_uppercase() && bytes[2] == b')'
+}
+
+/// Parses one line of a todo.txt file, returning `None` for blank lines.
+pub fn parse_line(line: &str) -> Option<ItemDesc> {
+    let mut words = line.split_whitespace().peekable();
+    words.peek()?;
+
+    let mut item = ItemDesc::new("", "", Vec::<String>::new());
+
+    if words.peek() == Some(&"x") {
+        words.next();
+        item.status = Status::Completed;
+        if let Some(date) = words.next_if(|word| is_date(word)) {
+            item.attributes.insert(COMPLETED.into(), date.into());
+        }
+    } else if let Some(priority) = words.next_if(|word| is_priority(word)) {
+        item.attributes.insert(PRIORITY.into(), priority[1..2].into());
+    }
+    if let Some(date) = words.next_if(|word| is_date(word)) {
+        item.attributes.insert(CREATED.into(), date.into());
+    }
+
+    let mut name = vec![];
+    let mut description = vec![];
+    let mut contexts = vec![];
+    let mut in_description = false;
+
+    for word in words {
+        if in_description {
+            description.push(word);
+        } else if let Some(word) = word.strip_prefix('\\') {
+            name.push(word);
+        } else if let Some(project) = word.strip_prefix('+').filter(|p| !p.is_empty()) {
+            item.tags.push(project.into());
+        } else if let Some(context) = word.strip_prefix('@').filter(|c| !c.is_empty()) {
+            contexts.push(context);
+        } else if let Some((key, value)) = split_extension(word) {
+            match (key, value) {
+                (STATUS, "queuing") if item.status != Status::Completed => {
+                    item.status = Status::Queuing
+                }
+                (STATUS, "working") if item.status != Status::Completed => {
+                    item.status = Status::Working
+                }
+                // Extensions can't hold spaces, so a repeated one keeps
+                // each of its values apart with one
+                _ => {
+                    item.attributes
+                        .entry(key.into())
+                        .and_modify(|values| {
+                            values.push(' ');
+                            values.push_str(value)
+                        })
+                        .or_insert_with(|| value.into());
+                }
+            }
+        } else if word == "--" {
+            in_description = true;
+        } else {
+            name.push(word);
+        }
+    }
+
+    if !contexts.is_empty() {
+        item.attributes.insert(CONTEXT.into(), contexts.join(","));
+    }
+    item.name = name.join(" ");
+    item.description = description.join(" ");
+
+    Some(item)
+}
+
+fn split_extension(word: &str) -> Option<(&str, &str)> {
+    let sep = word.find(':')?;
+    let (key, value) = (&word[..sep], &word[sep + 1..]);
+    if key.is_empty() || value.is_empty() || value.starts_with('/') || key.contains(':') {
+        None
+    } else {
+        Some((key, value))
+    }
+}
+
+/// Whether the word `word` of a name would be read as something else, at
+/// the start of the name if `first`.
+fn needs_escape(word: &str, first: bool) -> bool {
+    let marked = |mark| word.len() > 1 && word.starts_with(mark);
+    (first && (word == "x" || is_date(word) || is_priority(word)))
+        || marked('+')
+        || marked('@')
+        || word == "--"
+        || word.starts_with('\\')
+        || split_extension(word).is_some()
+}
+
+/// Formats an item as a todo.txt line.
+pub fn format_item(item: &ItemDesc) -> String {
+    let mut words: Vec<String> = vec![];
+    let attribute = |key: &str| item.attributes.get(key).map(String::as_str);
+
+    let completed = item.status == Status::Completed;
+    if completed {
+        words.push("x".into());
+        if let Some(date) = attribute(COMPLETED).filter(|date| is_date(date)) {
+            words.push(date.into());
+        }
+    } else if let Some(priority) = attribute(PRIORITY).filter(|p| is_priority(&format!("({})", p)))
+    {
+        words.push(format!("({})", priority));
+    }
+    if let Some(date) = attribute(CREATED).filter(|date| is_date(date)) {
+        words.push(date.into());
+    }
+
+    words.extend(item.name.split_whitespace().enumerate().map(|(idx, word)| {
+        if needs_escape(word, idx == 0) {
+            format!("\\{}", word)
+        } else {
+            word.into()
+        }
+    }));
+
+    words.extend(item.tags.iter().map(|tag| format!("+{}", tag)));
+    if let Some(contexts) = attribute(CONTEXT) {
+        words.extend(
+            contexts
+                .split(',')
+                .filter(|context| !context.is_empty())
+                .map(|context| format!("@{}", context)),
+        );
+    }
+
+    match item.status {
+        Status::Queuing => words.push(format!("{}:queuing", STATUS)),
+        Status::Working => words.push(format!("{}:working", STATUS)),
+        Status::Waiting | Status::Completed => {}
+    }
+
+    for (key, value) in &item.attributes {
+        let written = match key.as_str() {
+            CONTEXT | CREATED => true,
+            COMPLETED => completed && is_date(value),
+            PRIORITY => !completed && is_priority(&format!("({})", value)),
+            _ => false,
+        };
+        if !written {
+            words.extend(value.split_whitespace().map(|value| format!("{}:{}", key, value)));
+        }
+    }
+
+    if !item.description.trim().is_empty() {
+        words.push("--".into());
+        words.extend(item.description.split_whitespace().map(String::from));
+    }
+
+    words.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use list::{ItemDesc, Status};
+    use todotxt::{format_item, parse_line};
+
+    #[test]
+    fn parse_todotxt_line() {
+        let item = parse_line(
+            "(A) 2026-10-01 Call mom +family @phone @home due:2026-10-05 -- about the trip",
+        ).unwrap();
+        assert_eq!(item.name, "Call mom");
+        assert_eq!(item.description, "about the trip");
+        assert_eq!(item.status, Status::Waiting);
+        assert_eq!(item.tags, vec!["family"]);
+        assert_eq!(item.attributes["pri"], "A");
+        assert_eq!(item.attributes["created"], "2026-10-01");
+        assert_eq!(item.attributes["context"], "phone,home");
+        assert_eq!(item.attributes["due"], "2026-10-05");
+
+        let item = parse_line("x 2026-10-03 2026-10-01 deploy pri:B").unwrap();
+        assert_eq!(item.status, Status::Completed);
+        assert_eq!(item.attributes["completed"], "2026-10-03");
+        assert_eq!(item.attributes["created"], "2026-10-01");
+        assert_eq!(item.attributes["pri"], "B");
+
+        assert_eq!(parse_line("write docs status:working").unwrap().status, Status::Working);
+        assert!(parse_line("   ").is_none());
+    }
+
+    #[test]
+    fn todotxt_round_trip() {
+        let lines = vec![
+            "(A) 2026-10-01 Call mom +family @phone @home due:2026-10-05 -- about the trip",
+            "x 2026-10-03 2026-10-01 deploy +ops pri:B",
+            "write docs +docs status:working t:2026-11-01 rec:+1w",
+            "see http://example.com for details",
+            "water plants rec:mon rec:thu tag:a,b",
+        ];
+
+        for line in lines {
+            let item = parse_line(line).unwrap();
+            assert_eq!(parse_line(&format_item(&item)), Some(item), "{}", line);
+        }
+
+        assert_eq!(
+            format_item(&parse_line("x 2026-10-03 deploy pri:B ext:1").unwrap()),
+            "x 2026-10-03 deploy ext:1 pri:B"
+        );
+        assert_eq!(
+            parse_line("water plants rec:mon rec:thu").unwrap().attributes["rec"],
+            "mon thu"
+        );
+    }
+
+    #[test]
+    fn names_round_trip() {
+        let names = vec![
+            "x marks the spot",
+            "(A) call +bob",
+            "meet @home",
+            "2026-10-01 was a monday",
+            "read chapter:3 -- then \\stop",
+            "x",
+        ];
+
+        for name in names {
+            let mut item = ItemDesc::new(name, "", Vec::<String>::new());
+            item.description = "+not @a tag:here".into();
+            let line = format_item(&item);
+            assert_eq!(parse_line(&line), Some(item), "{}", line);
+        }
+
+        let item = parse_line("deploy -- +ops @home due:friday").unwrap();
+        assert_eq!(item.name, "deploy");
+        assert_eq!(item.description, "+ops @home due:friday");
+        assert!(item.tags.is_empty());
+        assert!(item.attributes.is_empty());
+    }
+}